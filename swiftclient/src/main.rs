@@ -1,7 +1,9 @@
 use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 use swiftlink_api::{
-    BlockingSwiftlinkClient, CreateLinkRequest, CreateLinkResponse, InfoResponse,
-    SwiftlinkClientError,
+    API_VERSION, BlockingSwiftlinkClient, BulkDeleteRequest, CreateLinkRequest, CreateLinkResponse,
+    EventsQuery, InfoResponse, ListLinksQuery, QrErrorCorrection, QrFormat, QrQuery, SortOrder,
+    StatsBucket, StatsQuery, StatsResponse, SwiftlinkClientError, UpdateLinkRequest,
 };
 
 #[derive(Parser)]
@@ -10,6 +12,10 @@ struct Cli {
     #[clap(short, long)]
     base_url: String,
 
+    /// Version of the API to use
+    #[clap(long, default_value = API_VERSION)]
+    api_version: String,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -20,12 +26,111 @@ enum Commands {
     Create {
         /// The URL to shorten
         url: String,
+        /// Custom alias to use instead of a generated code
+        #[arg(short, long)]
+        alias: Option<String>,
+        /// Number of seconds after which the short link expires
+        #[arg(long)]
+        ttl: Option<u64>,
+        /// Tag to give to the short link, may be repeated
+        #[arg(long = "tag")]
+        tags: Vec<String>,
     },
     /// Get information about a short link
     Info {
         /// The code of the short link
         code: String,
     },
+    /// List short links, newest first
+    List {
+        /// Only links to this host
+        #[arg(long)]
+        host: Option<String>,
+        /// Only links whose URL contains this text
+        #[arg(long)]
+        contains: Option<String>,
+        /// Only links created at or after this Unix timestamp
+        #[arg(long)]
+        created_after: Option<i64>,
+        /// Only links created before this Unix timestamp
+        #[arg(long)]
+        created_before: Option<i64>,
+        /// Only links with this tag
+        #[arg(long)]
+        tag: Option<String>,
+        /// List the oldest links first
+        #[arg(long)]
+        oldest_first: bool,
+        /// Maximum number of links to list
+        #[arg(short, long)]
+        limit: Option<usize>,
+        /// Bearer token for authentication
+        #[arg(short, long)]
+        token: String,
+    },
+    /// Change the destination or expiration of a short link
+    Update {
+        /// The code of the short link to update
+        code: String,
+        /// New URL to redirect to
+        #[arg(long)]
+        url: Option<String>,
+        /// New expiration as a Unix timestamp
+        #[arg(long, conflicts_with_all = ["ttl", "no_expiry"])]
+        expires_at: Option<i64>,
+        /// New time to live in seconds from now
+        #[arg(long, conflicts_with = "no_expiry")]
+        ttl: Option<u64>,
+        /// Remove the expiration of the short link
+        #[arg(long)]
+        no_expiry: bool,
+        /// Bearer token for authentication
+        #[arg(short, long)]
+        token: String,
+    },
+    /// Show click statistics of a short link
+    Stats {
+        /// The code of the short link
+        code: String,
+        /// Start of the range as a Unix timestamp
+        #[arg(long)]
+        from: Option<i64>,
+        /// End of the range as a Unix timestamp
+        #[arg(long)]
+        to: Option<i64>,
+        /// Width of the timeline buckets
+        #[arg(long, value_parser = ["hour", "day", "week"])]
+        bucket: Option<String>,
+        /// Bearer token for authentication
+        #[arg(short, long)]
+        token: String,
+    },
+    /// Render the QR code of a short link, printing it to the terminal unless written to a file
+    Qr {
+        /// The code of the short link
+        code: String,
+        /// File to write the image to
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Image format, guessed from the output file extension when not set
+        #[arg(long, value_parser = ["png", "svg"])]
+        format: Option<String>,
+        /// Image size in pixels, at most 1024, rounded down to a multiple of the number of modules
+        #[arg(long)]
+        size: Option<u32>,
+        /// Border width in modules
+        #[arg(long)]
+        margin: Option<u32>,
+        /// Error correction level
+        #[arg(long, value_parser = ["low", "medium", "quartile", "high"])]
+        ecc: Option<String>,
+        /// Color of the dark modules, as RRGGBB hex
+        #[arg(long)]
+        foreground: Option<String>,
+        /// Color of the light modules, as RRGGBB hex
+        #[arg(long)]
+        background: Option<String>,
+    },
     /// Delete a short link
     Delete {
         /// The code of the short link to delete
@@ -34,17 +139,173 @@ enum Commands {
         #[arg(short, long)]
         token: String,
     },
+    /// Delete many short links, given by code or matching filters
+    BulkDelete {
+        /// The codes of the short links to delete
+        codes: Vec<String>,
+        /// Only delete links to this host
+        #[arg(long)]
+        host: Option<String>,
+        /// Only delete links created before this Unix timestamp
+        #[arg(long)]
+        created_before: Option<i64>,
+        /// Only delete links with this tag
+        #[arg(long)]
+        tag: Option<String>,
+        /// Only show which links would be deleted
+        #[arg(long)]
+        dry_run: bool,
+        /// Bearer token for authentication
+        #[arg(short, long)]
+        token: String,
+    },
+    /// Manage the tags grouping short links
+    Tag {
+        #[command(subcommand)]
+        command: TagCommands,
+    },
+    /// Print redirects and link lifecycle events as they happen
+    Tail {
+        /// Only print the events of the short link with this code
+        #[arg(long)]
+        code: Option<String>,
+        /// Bearer token for authentication
+        #[arg(short, long)]
+        token: String,
+    },
+    /// Show the API versions and capabilities of the server
+    ServerVersion,
+    /// Check whether the server and its database are ready
+    Health,
+}
+
+#[derive(Subcommand)]
+enum TagCommands {
+    /// Give tags to a short link
+    Add {
+        /// The code of the short link
+        code: String,
+        /// The tags to give
+        #[arg(required = true)]
+        tags: Vec<String>,
+        /// Bearer token for authentication
+        #[arg(short, long)]
+        token: String,
+    },
+    /// Take tags from a short link
+    Remove {
+        /// The code of the short link
+        code: String,
+        /// The tags to take
+        #[arg(required = true)]
+        tags: Vec<String>,
+        /// Bearer token for authentication
+        #[arg(short, long)]
+        token: String,
+    },
+    /// Replace all tags of a short link, removing them when none is given
+    Set {
+        /// The code of the short link
+        code: String,
+        /// The new tags
+        tags: Vec<String>,
+        /// Bearer token for authentication
+        #[arg(short, long)]
+        token: String,
+    },
+    /// List every tag in use with its number of links
+    List {
+        /// Bearer token for authentication
+        #[arg(short, long)]
+        token: String,
+    },
+    /// Show the combined click statistics of the short links with a tag
+    Stats {
+        /// The tag of the short links
+        tag: String,
+        /// Start of the range as a Unix timestamp
+        #[arg(long)]
+        from: Option<i64>,
+        /// End of the range as a Unix timestamp
+        #[arg(long)]
+        to: Option<i64>,
+        /// Width of the timeline buckets
+        #[arg(long, value_parser = ["hour", "day", "week"])]
+        bucket: Option<String>,
+        /// Bearer token for authentication
+        #[arg(short, long)]
+        token: String,
+    },
+}
+
+fn stats_query(from: Option<i64>, to: Option<i64>, bucket: Option<&str>) -> StatsQuery {
+    StatsQuery {
+        from,
+        to,
+        bucket: bucket.map(|bucket| match bucket {
+            "hour" => StatsBucket::Hour,
+            "week" => StatsBucket::Week,
+            _ => StatsBucket::Day,
+        }),
+    }
+}
+
+fn print_stats(subject: &str, response: &StatsResponse) {
+    println!(
+        "Stats for {}: Total Clicks = {}, Unique Visitors = {}",
+        subject, response.total_clicks, response.unique_visitors
+    );
+    for bucket in response.timeline.iter().filter(|b| b.clicks > 0) {
+        println!("  {}: {}", bucket.start, bucket.clicks);
+    }
+    for referrer in &response.top_referrers {
+        println!("  Referrer {}: {}", referrer.name, referrer.clicks);
+    }
+    for family in &response.user_agents {
+        println!("  User Agent {}: {}", family.name, family.clicks);
+    }
+}
+
+fn print_tags(link: &InfoResponse) {
+    if !link.tags.is_empty() {
+        println!("Tags = {}", link.tags.join(", "));
+    }
+}
+
+fn print_updated_tags(link: &InfoResponse) {
+    if link.tags.is_empty() {
+        println!("Link {} has no tags.", link.code);
+    } else {
+        println!("Tags of {}: {}", link.code, link.tags.join(", "));
+    }
 }
 
 fn main() -> Result<(), SwiftlinkClientError> {
     let cli = Cli::parse();
-    let client = BlockingSwiftlinkClient::new(cli.base_url);
+    let client = BlockingSwiftlinkClient::new(cli.base_url).with_api_version(cli.api_version);
 
     match &cli.command {
-        Commands::Create { url } => {
-            let request = CreateLinkRequest { url: url.clone() };
-            let response: CreateLinkResponse = client.create_link(&request.url)?;
-            println!("Short link created: {}", response.code);
+        Commands::Create {
+            url,
+            alias,
+            ttl,
+            tags,
+        } => {
+            let request = CreateLinkRequest {
+                code: alias.clone(),
+                ttl: *ttl,
+                tags: tags.clone(),
+                ..CreateLinkRequest::new(url)
+            };
+            let response: CreateLinkResponse = client.create_link_with(&request)?;
+            if response.short_url.is_empty() {
+                println!("Short link created: {}", response.code);
+            } else {
+                println!("Short link created: {}", response.short_url);
+            }
+            if let Some(expires_at) = response.expires_at {
+                println!("Expires At = {}", expires_at);
+            }
         }
         Commands::Info { code } => {
             let response: InfoResponse = client.get_link_info(code)?;
@@ -52,11 +313,244 @@ fn main() -> Result<(), SwiftlinkClientError> {
                 "Link info for {}: URL = {}, Created At = {}",
                 response.code, response.url, response.created_at
             );
+            if let Some(expires_at) = response.expires_at {
+                println!("Expires At = {}", expires_at);
+            }
+            print_tags(&response);
+        }
+        Commands::List {
+            host,
+            contains,
+            created_after,
+            created_before,
+            tag,
+            oldest_first,
+            limit,
+            token,
+        } => {
+            let query = ListLinksQuery {
+                host: host.clone(),
+                contains: contains.clone(),
+                created_after: *created_after,
+                created_before: *created_before,
+                tag: tag.clone(),
+                order: Some(if *oldest_first {
+                    SortOrder::Asc
+                } else {
+                    SortOrder::Desc
+                }),
+                limit: limit.map(|limit| limit.min(500) as u32),
+                cursor: None,
+            };
+            let links = client
+                .list_all_links(query, token)
+                .take(limit.unwrap_or(usize::MAX));
+            for link in links {
+                let link = link?;
+                if link.tags.is_empty() {
+                    println!(
+                        "{}: URL = {}, Created At = {}",
+                        link.code, link.url, link.created_at
+                    );
+                } else {
+                    println!(
+                        "{}: URL = {}, Created At = {}, Tags = {}",
+                        link.code,
+                        link.url,
+                        link.created_at,
+                        link.tags.join(", ")
+                    );
+                }
+            }
+        }
+        Commands::Update {
+            code,
+            url,
+            expires_at,
+            ttl,
+            no_expiry,
+            token,
+        } => {
+            let request = UpdateLinkRequest {
+                url: url.clone(),
+                expires_at: if *no_expiry {
+                    Some(None)
+                } else {
+                    expires_at.map(Some)
+                },
+                ttl: *ttl,
+                ..UpdateLinkRequest::default()
+            };
+            let response = client.update_link(code, &request, token)?;
+            println!("Link {} updated: URL = {}", response.code, response.url);
+            if let Some(expires_at) = response.expires_at {
+                println!("Expires At = {}", expires_at);
+            }
+        }
+        Commands::Stats {
+            code,
+            from,
+            to,
+            bucket,
+            token,
+        } => {
+            let query = stats_query(*from, *to, bucket.as_deref());
+            let response = client.get_link_stats(code, &query, token)?;
+            print_stats(&response.code, &response);
+        }
+        Commands::Qr {
+            code,
+            output,
+            format,
+            size,
+            margin,
+            ecc,
+            foreground,
+            background,
+        } => {
+            let extension = output
+                .as_ref()
+                .and_then(|path| path.extension())
+                .and_then(|extension| extension.to_str());
+            let format = match (output, format.as_deref().or(extension)) {
+                (None, _) => QrFormat::Text,
+                (Some(_), Some("svg")) => QrFormat::Svg,
+                (Some(_), _) => QrFormat::Png,
+            };
+            let query = QrQuery {
+                format: Some(format),
+                size: *size,
+                margin: *margin,
+                ecc: ecc.as_deref().map(|ecc| match ecc {
+                    "low" => QrErrorCorrection::Low,
+                    "quartile" => QrErrorCorrection::Quartile,
+                    "high" => QrErrorCorrection::High,
+                    _ => QrErrorCorrection::Medium,
+                }),
+                foreground: foreground.clone(),
+                background: background.clone(),
+            };
+            let image = client.get_qr(code, &query)?;
+            match output {
+                Some(path) => {
+                    std::fs::write(path, image)?;
+                    println!("QR code of {} written to {}", code, path.display());
+                }
+                None => print!("{}", String::from_utf8_lossy(&image)),
+            }
         }
         Commands::Delete { code, token } => {
             client.delete_link(code, token)?;
             println!("Link {} deleted.", code);
         }
+        Commands::BulkDelete {
+            codes,
+            host,
+            created_before,
+            tag,
+            dry_run,
+            token,
+        } => {
+            let request = BulkDeleteRequest {
+                codes: (!codes.is_empty()).then(|| codes.clone()),
+                host: host.clone(),
+                created_before: *created_before,
+                tag: tag.clone(),
+                dry_run: *dry_run,
+            };
+            let response = client.delete_links(&request, token)?;
+            for code in &response.codes {
+                println!("{}", code);
+            }
+            if response.dry_run {
+                println!("{} links would be deleted.", response.codes.len());
+            } else {
+                println!("{} links deleted.", response.codes.len());
+            }
+        }
+        Commands::Tag { command } => match command {
+            TagCommands::Add { code, tags, token } => {
+                let request = UpdateLinkRequest {
+                    add_tags: tags.clone(),
+                    ..UpdateLinkRequest::default()
+                };
+                let response = client.update_link(code, &request, token)?;
+                print_updated_tags(&response);
+            }
+            TagCommands::Remove { code, tags, token } => {
+                let request = UpdateLinkRequest {
+                    remove_tags: tags.clone(),
+                    ..UpdateLinkRequest::default()
+                };
+                let response = client.update_link(code, &request, token)?;
+                print_updated_tags(&response);
+            }
+            TagCommands::Set { code, tags, token } => {
+                let request = UpdateLinkRequest {
+                    tags: Some(tags.clone()),
+                    ..UpdateLinkRequest::default()
+                };
+                let response = client.update_link(code, &request, token)?;
+                print_updated_tags(&response);
+            }
+            TagCommands::List { token } => {
+                let response = client.list_tags(token)?;
+                for tag in &response.tags {
+                    println!("{}: {} links", tag.tag, tag.links);
+                }
+            }
+            TagCommands::Stats {
+                tag,
+                from,
+                to,
+                bucket,
+                token,
+            } => {
+                let query = stats_query(*from, *to, bucket.as_deref());
+                let response = client.get_tag_stats(tag, &query, token)?;
+                print_stats(&format!("tag {}", tag), &response);
+            }
+        },
+        Commands::Tail { code, token } => {
+            let query = EventsQuery { code: code.clone() };
+            for event in client.watch(&query, token)? {
+                let event = event?;
+                let detail = match (&event.clicks, &event.referrer, &event.url) {
+                    (Some(clicks), _, _) => format!(" {} clicks", clicks),
+                    (_, Some(referrer), _) => format!(" from {}", referrer),
+                    (_, _, Some(url)) => format!(" {}", url),
+                    _ => String::new(),
+                };
+                println!(
+                    "{} {} {}{}",
+                    event.timestamp,
+                    event.event.name(),
+                    event.code,
+                    detail
+                );
+            }
+        }
+        Commands::ServerVersion => {
+            let response = client.get_version()?;
+            println!("Server version: {}", response.server_version);
+            println!("API versions: {}", response.api_versions.join(", "));
+            println!("Capabilities: {}", response.capabilities.join(", "));
+        }
+        Commands::Health => {
+            let response = client.health()?;
+            println!("Status: {:?}", response.status);
+            for check in &response.checks {
+                println!(
+                    "{}: {:?} ({})",
+                    check.name,
+                    check.status,
+                    check.detail.as_deref().unwrap_or_default()
+                );
+            }
+            if !response.is_ok() {
+                std::process::exit(1);
+            }
+        }
     }
 
     Ok(())
@@ -1,5 +1,7 @@
 use crate::request_types::*;
+use crate::sse::SseParser;
 use crate::{SwiftlinkClientError, SwiftlinkResult};
+use futures_util::{Stream, TryStreamExt, stream};
 use reqwest::Client;
 
 /// An asynchronous client for interacting with the Swiftlink URL shortening service.
@@ -10,59 +12,366 @@ use reqwest::Client;
 pub struct SwiftlinkClient {
     client: Client,
     base_url: String,
+    api_version: String,
 }
 
 impl SwiftlinkClient {
-    /// Creates a new server client with the given base URL (e.g., "http://localhost:8080") to use
-    /// the API.
+    /// Creates a new server client with the given base URL (e.g., <http://localhost:8080>, or
+    /// <https://example.com/s/> for a server under a path prefix) to use the API.
     pub fn new(base_url: impl Into<String>) -> Self {
         SwiftlinkClient {
             client: Client::new(),
-            base_url: base_url.into(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            api_version: API_VERSION.to_string(),
         }
     }
 
-    /// Calls the `/api/create` endpoint to create a short link.
+    /// Targets another version of the API than [`API_VERSION`], e.g. to talk to a server that
+    /// only serves a newer one.
+    pub fn with_api_version(mut self, version: impl Into<String>) -> Self {
+        self.api_version = version.into();
+        self
+    }
+
+    /// URL of an endpoint of the targeted API version
+    fn api_url(&self, path: &str) -> String {
+        format!("{}/api/{}/{}", self.base_url, self.api_version, path)
+    }
+
+    /// Calls the `/api/version` endpoint to get the API versions and capabilities of the server.
+    ///
+    /// Returns a [`SwiftlinkResult`] containing a [`VersionResponse`] on success.
+    pub async fn get_version(&self) -> SwiftlinkResult<VersionResponse> {
+        let resp = self
+            .client
+            .get(format!("{}/api/version", self.base_url))
+            .send()
+            .await
+            .map_err(SwiftlinkClientError::RequestError)?
+            .check_status()
+            .await?
+            .json::<VersionResponse>()
+            .await
+            .map_err(SwiftlinkClientError::RequestError)?;
+        Ok(resp)
+    }
+
+    /// Calls the `/readyz` endpoint to check whether the server and its database are ready to
+    /// handle requests.
+    ///
+    /// Returns a [`SwiftlinkResult`] containing a [`HealthResponse`], with an `unavailable`
+    /// status and the failed checks when the server is not ready.
+    pub async fn health(&self) -> SwiftlinkResult<HealthResponse> {
+        let resp = self
+            .client
+            .get(format!("{}/readyz", self.base_url))
+            .send()
+            .await
+            .map_err(SwiftlinkClientError::RequestError)?;
+        // Unready servers answer 503 with the same document
+        let resp = if resp.status() == reqwest::StatusCode::SERVICE_UNAVAILABLE {
+            resp
+        } else {
+            resp.check_status().await?
+        };
+        resp.json::<HealthResponse>()
+            .await
+            .map_err(SwiftlinkClientError::RequestError)
+    }
+
+    /// Calls the `/api/v1/create` endpoint to create a short link.
     ///
     /// Returns a [`SwiftlinkResult`] containing a [`CreateLinkResponse`] on success.
     pub async fn create_link(&self, url: impl AsRef<str>) -> SwiftlinkResult<CreateLinkResponse> {
-        let final_url = url.as_ref();
-        let req_body = CreateLinkRequest {
-            url: final_url.to_string(),
-        };
+        self.create_link_with(&CreateLinkRequest::new(url.as_ref()))
+            .await
+    }
+
+    /// Calls the `/api/v1/create` endpoint with a full [`CreateLinkRequest`], allowing options such
+    /// as a custom alias or an expiration to be set.
+    ///
+    /// Returns a [`SwiftlinkResult`] containing a [`CreateLinkResponse`] on success.
+    pub async fn create_link_with(
+        &self,
+        request: &CreateLinkRequest,
+    ) -> SwiftlinkResult<CreateLinkResponse> {
         let resp = self
             .client
-            .post(format!("{}/api/create", self.base_url.as_str()))
-            .json(&req_body)
+            .post(self.api_url("create"))
+            .json(request)
             .send()
             .await
             .map_err(SwiftlinkClientError::RequestError)?
-            .error_for_status()
-            .map_err(SwiftlinkClientError::RequestError)?
+            .check_status()
+            .await?
             .json::<CreateLinkResponse>()
             .await
             .map_err(SwiftlinkClientError::RequestError)?;
         Ok(resp)
     }
 
-    /// Calls the `/api/info/{code}` endpoint to retrieve link info.
+    /// Calls the `/api/v1/create/bulk` endpoint to create many short links in one request,
+    /// which requires the bearer token.
+    ///
+    /// Returns a [`SwiftlinkResult`] containing a [`BulkCreateResponse`] with one result per
+    /// request, in order, on success.
+    pub async fn create_links(
+        &self,
+        requests: &[CreateLinkRequest],
+        token: impl AsRef<str>,
+    ) -> SwiftlinkResult<BulkCreateResponse> {
+        let resp = self
+            .client
+            .post(self.api_url("create/bulk"))
+            .header("Authorization", format!("Bearer {}", token.as_ref()))
+            .json(requests)
+            .send()
+            .await
+            .map_err(SwiftlinkClientError::RequestError)?
+            .check_status()
+            .await?
+            .json::<BulkCreateResponse>()
+            .await
+            .map_err(SwiftlinkClientError::RequestError)?;
+        Ok(resp)
+    }
+
+    /// Calls the `/api/v1/info/{code}` endpoint to retrieve link info.
     ///
     /// Returns a [`SwiftlinkResult`] containing an [`InfoResponse`] on success.
     pub async fn get_link_info(&self, code: impl AsRef<str>) -> SwiftlinkResult<InfoResponse> {
         let resp = self
             .client
-            .get(format!("{}/api/info/{}", self.base_url, code.as_ref()))
+            .get(self.api_url(&format!("info/{}", code.as_ref())))
             .send()
             .await
             .map_err(SwiftlinkClientError::RequestError)?
-            .error_for_status()
+            .check_status()
+            .await?
+            .json::<InfoResponse>()
+            .await
+            .map_err(SwiftlinkClientError::RequestError)?;
+        Ok(resp)
+    }
+
+    /// Calls the `/api/v1/links/{code}` endpoint with the `PATCH` method to change a short link.
+    ///
+    /// Returns a [`SwiftlinkResult`] containing the updated [`InfoResponse`] on success.
+    pub async fn update_link(
+        &self,
+        code: impl AsRef<str>,
+        request: &UpdateLinkRequest,
+        token: impl AsRef<str>,
+    ) -> SwiftlinkResult<InfoResponse> {
+        let resp = self
+            .client
+            .patch(self.api_url(&format!("links/{}", code.as_ref())))
+            .header("Authorization", format!("Bearer {}", token.as_ref()))
+            .json(request)
+            .send()
+            .await
             .map_err(SwiftlinkClientError::RequestError)?
+            .check_status()
+            .await?
             .json::<InfoResponse>()
             .await
             .map_err(SwiftlinkClientError::RequestError)?;
         Ok(resp)
     }
 
+    /// Calls the `/api/v1/links` endpoint to retrieve one page of the links matching the query.
+    ///
+    /// Returns a [`SwiftlinkResult`] containing a [`ListLinksResponse`] on success.
+    pub async fn list_links(
+        &self,
+        query: &ListLinksQuery,
+        token: impl AsRef<str>,
+    ) -> SwiftlinkResult<ListLinksResponse> {
+        let resp = self
+            .client
+            .get(self.api_url("links"))
+            .header("Authorization", format!("Bearer {}", token.as_ref()))
+            .query(query)
+            .send()
+            .await
+            .map_err(SwiftlinkClientError::RequestError)?
+            .check_status()
+            .await?
+            .json::<ListLinksResponse>()
+            .await
+            .map_err(SwiftlinkClientError::RequestError)?;
+        Ok(resp)
+    }
+
+    /// Streams every link matching the query, calling the `/api/v1/links` endpoint for each page
+    /// as the stream is consumed, starting at `query.cursor`.
+    ///
+    /// The stream ends after the first error.
+    pub fn list_all_links(
+        &self,
+        query: ListLinksQuery,
+        token: impl Into<String>,
+    ) -> impl Stream<Item = SwiftlinkResult<InfoResponse>> + '_ {
+        let token = token.into();
+        stream::try_unfold(Some(query), move |query| {
+            let token = token.clone();
+            async move {
+                let Some(mut query) = query else {
+                    return Ok(None);
+                };
+                let page = self.list_links(&query, &token).await?;
+                let next = page.next_cursor.map(|cursor| {
+                    query.cursor = Some(cursor);
+                    query
+                });
+                let links = stream::iter(page.links.into_iter().map(Ok));
+                SwiftlinkResult::Ok(Some((links, next)))
+            }
+        })
+        .try_flatten()
+    }
+
+    /// Calls the `/api/v1/stats/{code}` endpoint to retrieve click statistics, which requires
+    /// the bearer token.
+    ///
+    /// Returns a [`SwiftlinkResult`] containing a [`StatsResponse`] on success.
+    pub async fn get_link_stats(
+        &self,
+        code: impl AsRef<str>,
+        query: &StatsQuery,
+        token: impl AsRef<str>,
+    ) -> SwiftlinkResult<StatsResponse> {
+        let resp = self
+            .client
+            .get(self.api_url(&format!("stats/{}", code.as_ref())))
+            .header("Authorization", format!("Bearer {}", token.as_ref()))
+            .query(query)
+            .send()
+            .await
+            .map_err(SwiftlinkClientError::RequestError)?
+            .check_status()
+            .await?
+            .json::<StatsResponse>()
+            .await
+            .map_err(SwiftlinkClientError::RequestError)?;
+        Ok(resp)
+    }
+
+    /// Calls the `/api/v1/tags` endpoint to list every tag in use with its number of links.
+    ///
+    /// Returns a [`SwiftlinkResult`] containing a [`TagsResponse`] on success.
+    pub async fn list_tags(&self, token: impl AsRef<str>) -> SwiftlinkResult<TagsResponse> {
+        let resp = self
+            .client
+            .get(self.api_url("tags"))
+            .header("Authorization", format!("Bearer {}", token.as_ref()))
+            .send()
+            .await
+            .map_err(SwiftlinkClientError::RequestError)?
+            .check_status()
+            .await?
+            .json::<TagsResponse>()
+            .await
+            .map_err(SwiftlinkClientError::RequestError)?;
+        Ok(resp)
+    }
+
+    /// Calls the `/api/v1/tags/{tag}/stats` endpoint to retrieve the combined click statistics
+    /// of every link with a tag.
+    ///
+    /// Returns a [`SwiftlinkResult`] containing a [`StatsResponse`] on success.
+    pub async fn get_tag_stats(
+        &self,
+        tag: impl AsRef<str>,
+        query: &StatsQuery,
+        token: impl AsRef<str>,
+    ) -> SwiftlinkResult<StatsResponse> {
+        let resp = self
+            .client
+            .get(self.api_url(&format!("tags/{}/stats", tag.as_ref())))
+            .header("Authorization", format!("Bearer {}", token.as_ref()))
+            .query(query)
+            .send()
+            .await
+            .map_err(SwiftlinkClientError::RequestError)?
+            .check_status()
+            .await?
+            .json::<StatsResponse>()
+            .await
+            .map_err(SwiftlinkClientError::RequestError)?;
+        Ok(resp)
+    }
+
+    /// Calls the `/api/v1/qr/{code}` endpoint to render a QR code of the short link.
+    ///
+    /// Returns a [`SwiftlinkResult`] containing the image in the requested format (PNG by
+    /// default), or the text to print for [`QrFormat::Text`], on success.
+    pub async fn get_qr(&self, code: impl AsRef<str>, query: &QrQuery) -> SwiftlinkResult<Vec<u8>> {
+        let resp = self
+            .client
+            .get(self.api_url(&format!("qr/{}", code.as_ref())))
+            .query(query)
+            .send()
+            .await
+            .map_err(SwiftlinkClientError::RequestError)?
+            .check_status()
+            .await?
+            .bytes()
+            .await
+            .map_err(SwiftlinkClientError::RequestError)?;
+        Ok(resp.to_vec())
+    }
+
+    /// Subscribes to the `/api/v1/events` endpoint, streaming redirect and link lifecycle events
+    /// as the server publishes them, only those of `query.code` if set.
+    ///
+    /// The connection is opened when the stream is first polled. The stream ends after the first
+    /// error, or when the server closes the connection.
+    pub fn watch(
+        &self,
+        query: EventsQuery,
+        token: impl Into<String>,
+    ) -> impl Stream<Item = SwiftlinkResult<LinkEvent>> + '_ {
+        let token = token.into();
+        // (response once connected, parser)
+        let state = (None, SseParser::default());
+        stream::try_unfold(state, move |(response, mut parser)| {
+            let request = self
+                .client
+                .get(self.api_url("events"))
+                .header("Authorization", format!("Bearer {}", token))
+                .query(&query);
+            async move {
+                let mut response: reqwest::Response = match response {
+                    Some(response) => response,
+                    None => {
+                        request
+                            .send()
+                            .await
+                            .map_err(SwiftlinkClientError::RequestError)?
+                            .check_status()
+                            .await?
+                    }
+                };
+                loop {
+                    if let Some(data) = parser.next_event() {
+                        let event = serde_json::from_str::<LinkEvent>(&data)
+                            .map_err(|e| SwiftlinkClientError::UnexpectedResponse(e.to_string()))?;
+                        return Ok(Some((event, (Some(response), parser))));
+                    }
+                    match response
+                        .chunk()
+                        .await
+                        .map_err(SwiftlinkClientError::RequestError)?
+                    {
+                        Some(chunk) => parser.feed(&chunk),
+                        None => return Ok(None),
+                    }
+                }
+            }
+        })
+    }
+
     /// Calls the `/{code}` endpoint to get the redirection URL.
     ///
     /// The server should return a "Location" header on redirection.
@@ -74,8 +383,8 @@ impl SwiftlinkClient {
             .send()
             .await
             .map_err(SwiftlinkClientError::RequestError)?
-            .error_for_status()
-            .map_err(SwiftlinkClientError::RequestError)?;
+            .check_status()
+            .await?;
 
         if let Some(loc) = resp.headers().get("Location") {
             Ok(loc
@@ -88,4 +397,50 @@ impl SwiftlinkClient {
             ))
         }
     }
+
+    /// Calls the `/api/v1/delete/bulk` endpoint to delete the short links given by code or matching
+    /// a filter, or only list them in a dry run.
+    ///
+    /// Returns a [`SwiftlinkResult`] containing a [`BulkDeleteResponse`] on success.
+    pub async fn delete_links(
+        &self,
+        request: &BulkDeleteRequest,
+        token: impl AsRef<str>,
+    ) -> SwiftlinkResult<BulkDeleteResponse> {
+        let resp = self
+            .client
+            .post(self.api_url("delete/bulk"))
+            .header("Authorization", format!("Bearer {}", token.as_ref()))
+            .json(request)
+            .send()
+            .await
+            .map_err(SwiftlinkClientError::RequestError)?
+            .check_status()
+            .await?
+            .json::<BulkDeleteResponse>()
+            .await
+            .map_err(SwiftlinkClientError::RequestError)?;
+        Ok(resp)
+    }
+}
+
+/// Turns error statuses into the matching [`SwiftlinkClientError`], parsed from the problem
+/// document returned by the server.
+trait CheckStatus: Sized {
+    async fn check_status(self) -> SwiftlinkResult<Self>;
+}
+
+impl CheckStatus for reqwest::Response {
+    async fn check_status(self) -> SwiftlinkResult<Self> {
+        let status = self.status();
+        if !(status.is_client_error() || status.is_server_error()) {
+            return Ok(self);
+        }
+        let headers = self.headers().clone();
+        let body = self
+            .text()
+            .await
+            .map_err(SwiftlinkClientError::RequestError)?;
+        Err(SwiftlinkClientError::from_response(status, &headers, &body))
+    }
 }
@@ -1,7 +1,13 @@
+use crate::sse::SseParser;
 use crate::{
-    CreateLinkRequest, CreateLinkResponse, InfoResponse, SwiftlinkClientError, SwiftlinkResult,
+    API_VERSION, BulkCreateResponse, BulkDeleteRequest, BulkDeleteResponse, CreateLinkRequest,
+    CreateLinkResponse, EventsQuery, HealthResponse, InfoResponse, LinkEvent, ListLinksQuery,
+    ListLinksResponse, QrQuery, StatsQuery, StatsResponse, SwiftlinkClientError, SwiftlinkResult,
+    TagsResponse, UpdateLinkRequest, VersionResponse,
 };
-use reqwest::blocking::Client;
+use reqwest::blocking::{Client, Response};
+use std::io::Read;
+use std::vec;
 
 /// A blocking client for interacting with the Swiftlink URL shortening service.
 ///
@@ -11,53 +17,292 @@ use reqwest::blocking::Client;
 pub struct SwiftlinkClient {
     client: Client,
     base_url: String,
+    api_version: String,
 }
 
 impl SwiftlinkClient {
-    /// Creates a new SwiftlinkClient with the given base URL (e.g., "http://localhost:8080")
+    /// Creates a new SwiftlinkClient with the given base URL (e.g., <http://localhost:8080>, or
+    /// <https://example.com/s/> for a server under a path prefix)
     pub fn new(base_url: impl Into<String>) -> Self {
         SwiftlinkClient {
             client: Client::new(),
-            base_url: base_url.into(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            api_version: API_VERSION.to_string(),
         }
     }
 
-    /// Calls the `/api/create` endpoint to create a short link.
+    /// Targets another version of the API than [`API_VERSION`], e.g. to talk to a server that
+    /// only serves a newer one.
+    pub fn with_api_version(mut self, version: impl Into<String>) -> Self {
+        self.api_version = version.into();
+        self
+    }
+
+    /// URL of an endpoint of the targeted API version
+    fn api_url(&self, path: &str) -> String {
+        format!("{}/api/{}/{}", self.base_url, self.api_version, path)
+    }
+
+    /// Calls the `/api/version` endpoint to get the API versions and capabilities of the server.
+    ///
+    /// Returns a [`SwiftlinkResult`] containing a [`VersionResponse`] on success.
+    pub fn get_version(&self) -> SwiftlinkResult<VersionResponse> {
+        let resp = self
+            .client
+            .get(format!("{}/api/version", self.base_url))
+            .send()
+            .map_err(SwiftlinkClientError::RequestError)?
+            .check_status()?
+            .json::<VersionResponse>()
+            .map_err(SwiftlinkClientError::RequestError)?;
+        Ok(resp)
+    }
+
+    /// Calls the `/readyz` endpoint to check whether the server and its database are ready to
+    /// handle requests.
+    ///
+    /// Returns a [`SwiftlinkResult`] containing a [`HealthResponse`], with an `unavailable`
+    /// status and the failed checks when the server is not ready.
+    pub fn health(&self) -> SwiftlinkResult<HealthResponse> {
+        let resp = self
+            .client
+            .get(format!("{}/readyz", self.base_url))
+            .send()
+            .map_err(SwiftlinkClientError::RequestError)?;
+        // Unready servers answer 503 with the same document
+        let resp = if resp.status() == reqwest::StatusCode::SERVICE_UNAVAILABLE {
+            resp
+        } else {
+            resp.check_status()?
+        };
+        resp.json::<HealthResponse>()
+            .map_err(SwiftlinkClientError::RequestError)
+    }
+
+    /// Calls the `/api/v1/create` endpoint to create a short link.
     ///
     /// Returns a [`SwiftlinkResult`] containing a [`CreateLinkResponse`] on success.
     pub fn create_link(&self, url: impl AsRef<str>) -> SwiftlinkResult<CreateLinkResponse> {
-        let req_body = CreateLinkRequest {
-            url: url.as_ref().to_string(),
-        };
+        self.create_link_with(&CreateLinkRequest::new(url.as_ref()))
+    }
+
+    /// Calls the `/api/v1/create` endpoint with a full [`CreateLinkRequest`], allowing options such
+    /// as a custom alias or an expiration to be set.
+    ///
+    /// Returns a [`SwiftlinkResult`] containing a [`CreateLinkResponse`] on success.
+    pub fn create_link_with(
+        &self,
+        request: &CreateLinkRequest,
+    ) -> SwiftlinkResult<CreateLinkResponse> {
         let resp = self
             .client
-            .post(format!("{}/api/create", self.base_url))
-            .json(&req_body)
+            .post(self.api_url("create"))
+            .json(request)
             .send()
             .map_err(SwiftlinkClientError::RequestError)?
-            .error_for_status()
-            .map_err(SwiftlinkClientError::RequestError)?
+            .check_status()?
             .json::<CreateLinkResponse>()
             .map_err(SwiftlinkClientError::RequestError)?;
         Ok(resp)
     }
 
-    /// Calls the `/api/info/{code}` endpoint to retrieve link info.
+    /// Calls the `/api/v1/create/bulk` endpoint to create many short links in one request,
+    /// which requires the bearer token.
+    ///
+    /// Returns a [`SwiftlinkResult`] containing a [`BulkCreateResponse`] with one result per
+    /// request, in order, on success.
+    pub fn create_links(
+        &self,
+        requests: &[CreateLinkRequest],
+        token: impl AsRef<str>,
+    ) -> SwiftlinkResult<BulkCreateResponse> {
+        let resp = self
+            .client
+            .post(self.api_url("create/bulk"))
+            .header("Authorization", format!("Bearer {}", token.as_ref()))
+            .json(requests)
+            .send()
+            .map_err(SwiftlinkClientError::RequestError)?
+            .check_status()?
+            .json::<BulkCreateResponse>()
+            .map_err(SwiftlinkClientError::RequestError)?;
+        Ok(resp)
+    }
+
+    /// Calls the `/api/v1/info/{code}` endpoint to retrieve link info.
     ///
     /// Returns a [`SwiftlinkResult`] containing an [`InfoResponse`] on success.
     pub fn get_link_info(&self, code: impl AsRef<str>) -> SwiftlinkResult<InfoResponse> {
         let resp = self
             .client
-            .get(format!("{}/api/info/{}", self.base_url, code.as_ref()))
+            .get(self.api_url(&format!("info/{}", code.as_ref())))
             .send()
             .map_err(SwiftlinkClientError::RequestError)?
-            .error_for_status()
+            .check_status()?
+            .json::<InfoResponse>()
+            .map_err(SwiftlinkClientError::RequestError)?;
+        Ok(resp)
+    }
+
+    /// Calls the `/api/v1/links/{code}` endpoint with the `PATCH` method to change a short link.
+    ///
+    /// Returns a [`SwiftlinkResult`] containing the updated [`InfoResponse`] on success.
+    pub fn update_link(
+        &self,
+        code: impl AsRef<str>,
+        request: &UpdateLinkRequest,
+        token: impl AsRef<str>,
+    ) -> SwiftlinkResult<InfoResponse> {
+        let resp = self
+            .client
+            .patch(self.api_url(&format!("links/{}", code.as_ref())))
+            .header("Authorization", format!("Bearer {}", token.as_ref()))
+            .json(request)
+            .send()
             .map_err(SwiftlinkClientError::RequestError)?
+            .check_status()?
             .json::<InfoResponse>()
             .map_err(SwiftlinkClientError::RequestError)?;
         Ok(resp)
     }
 
+    /// Calls the `/api/v1/links` endpoint to retrieve one page of the links matching the query.
+    ///
+    /// Returns a [`SwiftlinkResult`] containing a [`ListLinksResponse`] on success.
+    pub fn list_links(
+        &self,
+        query: &ListLinksQuery,
+        token: impl AsRef<str>,
+    ) -> SwiftlinkResult<ListLinksResponse> {
+        let resp = self
+            .client
+            .get(self.api_url("links"))
+            .header("Authorization", format!("Bearer {}", token.as_ref()))
+            .query(query)
+            .send()
+            .map_err(SwiftlinkClientError::RequestError)?
+            .check_status()?
+            .json::<ListLinksResponse>()
+            .map_err(SwiftlinkClientError::RequestError)?;
+        Ok(resp)
+    }
+
+    /// Iterates over every link matching the query, calling the `/api/v1/links` endpoint for each
+    /// page as the iterator is consumed, starting at `query.cursor`.
+    ///
+    /// The iterator ends after the first error.
+    pub fn list_all_links(&self, query: ListLinksQuery, token: impl Into<String>) -> LinkIter<'_> {
+        LinkIter {
+            client: self,
+            token: token.into(),
+            query: Some(query),
+            page: Vec::new().into_iter(),
+        }
+    }
+
+    /// Subscribes to the `/api/v1/events` endpoint, iterating over redirect and link lifecycle
+    /// events as the server publishes them, only those of `query.code` if set.
+    ///
+    /// The iterator blocks until the next event and ends after the first error, or when the
+    /// server closes the connection.
+    pub fn watch(&self, query: &EventsQuery, token: impl AsRef<str>) -> SwiftlinkResult<EventIter> {
+        // The default timeout of the client would cut the stream after 30 seconds
+        let client = Client::builder()
+            .timeout(None)
+            .build()
+            .map_err(SwiftlinkClientError::RequestError)?;
+        let response = client
+            .get(self.api_url("events"))
+            .header("Authorization", format!("Bearer {}", token.as_ref()))
+            .query(query)
+            .send()
+            .map_err(SwiftlinkClientError::RequestError)?
+            .check_status()?;
+        Ok(EventIter {
+            response: Some(response),
+            parser: SseParser::default(),
+        })
+    }
+
+    /// Calls the `/api/v1/stats/{code}` endpoint to retrieve click statistics, which requires
+    /// the bearer token.
+    ///
+    /// Returns a [`SwiftlinkResult`] containing a [`StatsResponse`] on success.
+    pub fn get_link_stats(
+        &self,
+        code: impl AsRef<str>,
+        query: &StatsQuery,
+        token: impl AsRef<str>,
+    ) -> SwiftlinkResult<StatsResponse> {
+        let resp = self
+            .client
+            .get(self.api_url(&format!("stats/{}", code.as_ref())))
+            .header("Authorization", format!("Bearer {}", token.as_ref()))
+            .query(query)
+            .send()
+            .map_err(SwiftlinkClientError::RequestError)?
+            .check_status()?
+            .json::<StatsResponse>()
+            .map_err(SwiftlinkClientError::RequestError)?;
+        Ok(resp)
+    }
+
+    /// Calls the `/api/v1/tags` endpoint to list every tag in use with its number of links.
+    ///
+    /// Returns a [`SwiftlinkResult`] containing a [`TagsResponse`] on success.
+    pub fn list_tags(&self, token: impl AsRef<str>) -> SwiftlinkResult<TagsResponse> {
+        let resp = self
+            .client
+            .get(self.api_url("tags"))
+            .header("Authorization", format!("Bearer {}", token.as_ref()))
+            .send()
+            .map_err(SwiftlinkClientError::RequestError)?
+            .check_status()?
+            .json::<TagsResponse>()
+            .map_err(SwiftlinkClientError::RequestError)?;
+        Ok(resp)
+    }
+
+    /// Calls the `/api/v1/tags/{tag}/stats` endpoint to retrieve the combined click statistics
+    /// of every link with a tag.
+    ///
+    /// Returns a [`SwiftlinkResult`] containing a [`StatsResponse`] on success.
+    pub fn get_tag_stats(
+        &self,
+        tag: impl AsRef<str>,
+        query: &StatsQuery,
+        token: impl AsRef<str>,
+    ) -> SwiftlinkResult<StatsResponse> {
+        let resp = self
+            .client
+            .get(self.api_url(&format!("tags/{}/stats", tag.as_ref())))
+            .header("Authorization", format!("Bearer {}", token.as_ref()))
+            .query(query)
+            .send()
+            .map_err(SwiftlinkClientError::RequestError)?
+            .check_status()?
+            .json::<StatsResponse>()
+            .map_err(SwiftlinkClientError::RequestError)?;
+        Ok(resp)
+    }
+
+    /// Calls the `/api/v1/qr/{code}` endpoint to render a QR code of the short link.
+    ///
+    /// Returns a [`SwiftlinkResult`] containing the image in the requested format (PNG by
+    /// default), or the text to print for [`QrFormat::Text`](crate::QrFormat::Text), on success.
+    pub fn get_qr(&self, code: impl AsRef<str>, query: &QrQuery) -> SwiftlinkResult<Vec<u8>> {
+        let resp = self
+            .client
+            .get(self.api_url(&format!("qr/{}", code.as_ref())))
+            .query(query)
+            .send()
+            .map_err(SwiftlinkClientError::RequestError)?
+            .check_status()?
+            .bytes()
+            .map_err(SwiftlinkClientError::RequestError)?;
+        Ok(resp.to_vec())
+    }
+
     /// Calls the `/{code}` endpoint to get the redirection URL.
     ///
     /// Assumes that the server returns a "Location" header on redirection.
@@ -68,8 +313,7 @@ impl SwiftlinkClient {
             .get(format!("{}/{}", self.base_url, code.as_ref()))
             .send()
             .map_err(SwiftlinkClientError::RequestError)?
-            .error_for_status()
-            .map_err(SwiftlinkClientError::RequestError)?;
+            .check_status()?;
 
         if let Some(loc) = resp.headers().get("Location") {
             Ok(loc
@@ -83,7 +327,7 @@ impl SwiftlinkClient {
         }
     }
 
-    /// Calls the `/{code}` endpoint to delete a short link.
+    /// Calls the `/api/v1/links/{code}` endpoint with DELETE method to delete a short link.
     ///
     /// Returns a [`SwiftlinkResult`] indicating success or failure.
     pub fn delete_link(
@@ -92,12 +336,126 @@ impl SwiftlinkClient {
         token: impl AsRef<str>,
     ) -> SwiftlinkResult<()> {
         self.client
-            .delete(format!("{}/{}", self.base_url, code.as_ref()))
+            .delete(self.api_url(&format!("links/{}", code.as_ref())))
             .header("Authorization", format!("Bearer {}", token.as_ref()))
             .send()
             .map_err(SwiftlinkClientError::RequestError)?
-            .error_for_status()
-            .map_err(SwiftlinkClientError::RequestError)?;
+            .check_status()?;
         Ok(())
     }
+
+    /// Calls the `/api/v1/delete/bulk` endpoint to delete the short links given by code or matching
+    /// a filter, or only list them in a dry run.
+    ///
+    /// Returns a [`SwiftlinkResult`] containing a [`BulkDeleteResponse`] on success.
+    pub fn delete_links(
+        &self,
+        request: &BulkDeleteRequest,
+        token: impl AsRef<str>,
+    ) -> SwiftlinkResult<BulkDeleteResponse> {
+        let resp = self
+            .client
+            .post(self.api_url("delete/bulk"))
+            .header("Authorization", format!("Bearer {}", token.as_ref()))
+            .json(request)
+            .send()
+            .map_err(SwiftlinkClientError::RequestError)?
+            .check_status()?
+            .json::<BulkDeleteResponse>()
+            .map_err(SwiftlinkClientError::RequestError)?;
+        Ok(resp)
+    }
+}
+
+/// Iterator over the links matching a [`ListLinksQuery`], fetching pages lazily.
+///
+/// Created by [`SwiftlinkClient::list_all_links`].
+#[derive(Debug)]
+pub struct LinkIter<'a> {
+    client: &'a SwiftlinkClient,
+    token: String,
+    /// Query of the next page to fetch, `None` once the last page was fetched
+    query: Option<ListLinksQuery>,
+    page: vec::IntoIter<InfoResponse>,
+}
+
+impl Iterator for LinkIter<'_> {
+    type Item = SwiftlinkResult<InfoResponse>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(link) = self.page.next() {
+                return Some(Ok(link));
+            }
+
+            let mut query = self.query.take()?;
+            match self.client.list_links(&query, &self.token) {
+                Ok(page) => {
+                    self.page = page.links.into_iter();
+                    self.query = page.next_cursor.map(|cursor| {
+                        query.cursor = Some(cursor);
+                        query
+                    });
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Iterator over the events streamed by the server, blocking until the next one.
+///
+/// Created by [`SwiftlinkClient::watch`].
+#[derive(Debug)]
+pub struct EventIter {
+    /// The streamed response, `None` once the stream ended
+    response: Option<Response>,
+    parser: SseParser,
+}
+
+impl Iterator for EventIter {
+    type Item = SwiftlinkResult<LinkEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut chunk = [0; 4096];
+        loop {
+            if let Some(data) = self.parser.next_event() {
+                let event = serde_json::from_str::<LinkEvent>(&data)
+                    .map_err(|e| SwiftlinkClientError::UnexpectedResponse(e.to_string()));
+                if event.is_err() {
+                    self.response = None;
+                }
+                return Some(event);
+            }
+            match self.response.as_mut()?.read(&mut chunk) {
+                Ok(0) => {
+                    self.response = None;
+                    return None;
+                }
+                Ok(read) => self.parser.feed(&chunk[..read]),
+                Err(e) => {
+                    self.response = None;
+                    return Some(Err(SwiftlinkClientError::UnexpectedResponse(e.to_string())));
+                }
+            }
+        }
+    }
+}
+
+/// Turns error statuses into the matching [`SwiftlinkClientError`], parsed from the problem
+/// document returned by the server.
+trait CheckStatus: Sized {
+    fn check_status(self) -> SwiftlinkResult<Self>;
+}
+
+impl CheckStatus for reqwest::blocking::Response {
+    fn check_status(self) -> SwiftlinkResult<Self> {
+        let status = self.status();
+        if !(status.is_client_error() || status.is_server_error()) {
+            return Ok(self);
+        }
+        let headers = self.headers().clone();
+        let body = self.text().map_err(SwiftlinkClientError::RequestError)?;
+        Err(SwiftlinkClientError::from_response(status, &headers, &body))
+    }
 }
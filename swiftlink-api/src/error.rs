@@ -1,3 +1,4 @@
+use crate::request_types::ProblemDetails;
 use thiserror::Error;
 
 /// A specialized `Result` type for Swiftlink client operations.
@@ -19,4 +20,79 @@ pub enum SwiftlinkClientError {
     /// The server returned an unexpected or malformed response.
     #[error("Unexpected response: {0}")]
     UnexpectedResponse(String),
+    /// There is no short link with the requested code.
+    #[error("Not found: {0}")]
+    NotFound(String),
+    /// The bearer token is missing or invalid.
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+    /// The request conflicts with an existing short link, e.g. a taken alias.
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    /// The URL to shorten was rejected by the server.
+    #[error("Invalid URL: {0}")]
+    InvalidUrl(String),
+    /// Too many requests were made.
+    #[error("Rate limited: {message}")]
+    RateLimited {
+        /// Explanation given by the server.
+        message: String,
+        /// Number of seconds to wait before retrying, from the `Retry-After` header.
+        retry_after: Option<u64>,
+    },
+    /// The server reported another error, described by the problem document.
+    #[error("Server error ({}): {}", .0.status, .0.detail.as_deref().unwrap_or(&.0.title))]
+    Problem(ProblemDetails),
+}
+
+#[cfg(any(feature = "async", feature = "blocking"))]
+impl SwiftlinkClientError {
+    /// Builds the error matching an error response of the server from its status, headers and
+    /// body, which is an RFC 7807 problem document for servers that support them.
+    pub(crate) fn from_response(
+        status: reqwest::StatusCode,
+        headers: &reqwest::header::HeaderMap,
+        body: &str,
+    ) -> Self {
+        use crate::request_types::ErrorCode;
+
+        let retry_after = headers
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse().ok());
+
+        let problem = match serde_json::from_str::<ProblemDetails>(body) {
+            Ok(problem) => problem,
+            // Plain-text errors of servers without problem documents
+            Err(_) => {
+                let message = body.to_string();
+                return match status.as_u16() {
+                    401 => SwiftlinkClientError::Unauthorized(message),
+                    404 => SwiftlinkClientError::NotFound(message),
+                    409 => SwiftlinkClientError::Conflict(message),
+                    429 => SwiftlinkClientError::RateLimited {
+                        message,
+                        retry_after,
+                    },
+                    _ => SwiftlinkClientError::UnexpectedResponse(format!("{status}: {message}")),
+                };
+            }
+        };
+
+        let message = problem
+            .detail
+            .clone()
+            .unwrap_or_else(|| problem.title.clone());
+        match problem.code {
+            ErrorCode::NotFound => SwiftlinkClientError::NotFound(message),
+            ErrorCode::Unauthorized => SwiftlinkClientError::Unauthorized(message),
+            ErrorCode::AliasTaken => SwiftlinkClientError::Conflict(message),
+            ErrorCode::InvalidUrl => SwiftlinkClientError::InvalidUrl(message),
+            ErrorCode::RateLimited => SwiftlinkClientError::RateLimited {
+                message,
+                retry_after,
+            },
+            _ => SwiftlinkClientError::Problem(problem),
+        }
+    }
 }
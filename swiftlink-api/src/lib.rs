@@ -22,6 +22,8 @@ pub mod client_blocking;
 pub mod error;
 /// Request and response types for the Swiftlink API.
 pub mod request_types;
+#[cfg(any(feature = "async", feature = "blocking"))]
+mod sse;
 
 // Re-export the client and common types so that users of the library have a unified API.
 #[cfg(feature = "async")]
@@ -33,3 +35,14 @@ pub use error::{SwiftlinkClientError, SwiftlinkResult};
 pub use request_types::CreateLinkRequest;
 pub use request_types::CreateLinkResponse;
 pub use request_types::InfoResponse;
+pub use request_types::UpdateLinkRequest;
+pub use request_types::{API_VERSION, VersionResponse};
+pub use request_types::{BulkCreateResponse, BulkCreateResult};
+pub use request_types::{BulkDeleteRequest, BulkDeleteResponse};
+pub use request_types::{ErrorCode, ProblemDetails};
+pub use request_types::{EventsQuery, LinkEvent, LinkEventType};
+pub use request_types::{HealthCheck, HealthResponse, HealthStatus};
+pub use request_types::{ListLinksQuery, ListLinksResponse, SortOrder};
+pub use request_types::{QrErrorCorrection, QrFormat, QrQuery};
+pub use request_types::{StatsBucket, StatsQuery, StatsResponse};
+pub use request_types::{TagCount, TagsResponse};
@@ -1,29 +1,576 @@
 /// The common types used in the Swiftlink API.
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// Deserializes a present field (even `null`) as `Some`, so that a missing field and an explicit
+/// `null` can be told apart in `Option<Option<T>>` fields.
+fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Version of the API targeted by the clients of this crate, served under `/api/v1`.
+pub const API_VERSION: &str = "v1";
+
+/// Represents the versions and optional features supported by a server, returned by the
+/// `/api/version` endpoint.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct VersionResponse {
+    /// Version of the server software.
+    pub server_version: String,
+    /// API versions served under `/api/{version}`, oldest first (e.g. `["v1"]`).
+    pub api_versions: Vec<String>,
+    /// Features supported by the server (e.g. `bulk_create`, `openapi`).
+    pub capabilities: Vec<String>,
+}
+
+impl VersionResponse {
+    /// Whether the server serves the given API version.
+    pub fn supports_version(&self, version: &str) -> bool {
+        self.api_versions.iter().any(|v| v == version)
+    }
+
+    /// Whether the server reports the given capability.
+    pub fn has_capability(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|c| c == capability)
+    }
+}
+
+/// Overall status of a server, or status of one of its health checks.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    /// Everything works.
+    Ok,
+    /// The server cannot handle requests correctly.
+    Unavailable,
+}
+
+/// Result of one check run by the `/readyz` endpoint.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct HealthCheck {
+    /// Name of the check (`database`, `migrations` or `pool`).
+    pub name: String,
+    /// Whether the check passed.
+    pub status: HealthStatus,
+    /// What was checked, or why the check failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// Represents the health of a server, returned by the `/healthz` and `/readyz` endpoints.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct HealthResponse {
+    /// `ok` when every check passed.
+    pub status: HealthStatus,
+    /// The checks that were run, empty for `/healthz`.
+    #[serde(default)]
+    pub checks: Vec<HealthCheck>,
+}
+
+impl HealthResponse {
+    /// Whether the server is ready to handle requests.
+    pub fn is_ok(&self) -> bool {
+        self.status == HealthStatus::Ok
+    }
+}
+
+/// Stable machine-readable codes of the errors reported by the server.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request is malformed or has invalid parameters.
+    InvalidRequest,
+    /// The URL to shorten is invalid.
+    InvalidUrl,
+    /// The custom alias is too short, too long, reserved or has invalid characters.
+    InvalidAlias,
+    /// The requested expiration is invalid or not in the future.
+    InvalidExpiration,
+    /// The bearer token is missing or wrong.
+    Unauthorized,
+    /// There is no short link with this code.
+    NotFound,
+    /// The short link has expired.
+    Expired,
+    /// The custom alias is already used by another short link.
+    AliasTaken,
+    /// Too many requests were made, the client has to wait before retrying.
+    RateLimited,
+    /// No free code could be generated for the short link.
+    CodesExhausted,
+    /// The server failed to handle the request.
+    InternalError,
+    /// An error code unknown to this version of the library.
+    #[serde(other)]
+    Unknown,
+}
+
+fn about_blank() -> String {
+    "about:blank".to_string()
+}
+
+/// An RFC 7807 problem document, the body of every error response of the server.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ProblemDetails {
+    /// URI reference identifying the problem type, `about:blank` when the HTTP status says it all.
+    #[serde(rename = "type", default = "about_blank")]
+    pub problem_type: String,
+    /// Short summary of the problem type.
+    pub title: String,
+    /// The HTTP status code of the response.
+    pub status: u16,
+    /// Explanation specific to this occurrence of the problem.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// Machine-readable error code.
+    pub code: ErrorCode,
+}
 
 /// Represents a request to create a new short link.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct CreateLinkRequest {
     /// The original URL to be shortened.
     pub url: String,
+    /// A custom alias to use as the short code instead of a generated one.
+    ///
+    /// The server rejects a taken alias with `409 Conflict`, as well as one differing from an
+    /// existing code only by case.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    /// The Unix timestamp (in seconds) after which the short link expires.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+    /// Time to live of the short link in seconds, as an alternative to `expires_at`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u64>,
+    /// Tags grouping the short link, e.g. by campaign. Tags are lowercased and may only contain
+    /// letters, digits, `-`, `_`, `.` and `:`.
+    ///
+    /// Like expiring links, tagged links never reuse the code of an identical URL.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+impl CreateLinkRequest {
+    /// Creates a request for a short link that never expires.
+    pub fn new(url: impl Into<String>) -> Self {
+        CreateLinkRequest {
+            url: url.into(),
+            ..Default::default()
+        }
+    }
 }
 
 /// Represents the response containing the details of a newly created short link.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct CreateLinkResponse {
     /// The generated short code for the link.
     pub code: String,
+    /// The full short URL of the link, such as `https://example.com/s/abc123`.
+    /// Empty when returned by servers predating it.
+    #[serde(default)]
+    pub short_url: String,
     /// The shortened URL.
     pub url: String,
+    /// The Unix timestamp (in seconds) when the short link expires, if it does.
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+}
+
+/// Outcome of one link of a bulk creation request.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum BulkCreateResult {
+    /// A new short link was created.
+    Created(CreateLinkResponse),
+    /// The URL (or the alias with the same URL) was already shortened; the existing short link
+    /// is returned.
+    Existing(CreateLinkResponse),
+    /// The request was rejected, for example because of an invalid URL or a taken alias.
+    Invalid {
+        /// Machine-readable reason of the rejection.
+        code: ErrorCode,
+        /// Why the short link could not be created.
+        message: String,
+    },
+}
+
+/// Represents the response of the `/api/v1/create/bulk` endpoint.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct BulkCreateResponse {
+    /// One result per requested link, in the order of the requests.
+    pub results: Vec<BulkCreateResult>,
+}
+
+/// Represents a request to delete many short links at once, given by code or by filter.
+///
+/// Links have to match every set field, and at least one of `codes`, `host`,
+/// `created_before` and `tag` has to be set.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct BulkDeleteRequest {
+    /// Codes of the short links to delete.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub codes: Option<Vec<String>>,
+    /// Only delete links to this host (e.g. `example.com`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// Only delete links created before this Unix timestamp (in seconds).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_before: Option<i64>,
+    /// Only delete links with this tag.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// Only report which links would be deleted, without deleting them.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Represents the response of the `/api/v1/delete/bulk` endpoint.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct BulkDeleteResponse {
+    /// Whether this was a dry run, in which case nothing was deleted.
+    pub dry_run: bool,
+    /// Codes of the deleted short links, or of those that would be deleted in a dry run.
+    pub codes: Vec<String>,
+}
+
+/// Represents a request to change an existing short link. Fields left out are not changed.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct UpdateLinkRequest {
+    /// The new URL the short link redirects to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// The new expiration as a Unix timestamp in seconds, `Some(None)` removing the expiration.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_some"
+    )]
+    pub expires_at: Option<Option<i64>>,
+    /// The new time to live in seconds from now, as an alternative to `expires_at`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u64>,
+    /// The new tags of the short link, replacing all of its current ones.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    /// Tags to give to the short link, after `tags` is applied.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub add_tags: Vec<String>,
+    /// Tags to take from the short link, after `tags` and `add_tags` are applied.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remove_tags: Vec<String>,
 }
 
 /// Represents the response containing information about an existing short link.
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct InfoResponse {
     /// The short code of the link.
     pub code: String,
+    /// The full short URL of the link, such as `https://example.com/s/abc123`.
+    /// Empty when returned by servers predating it.
+    #[serde(default)]
+    pub short_url: String,
     /// The original URL that the short link redirects to.
     pub url: String,
     /// The Unix timestamp (in seconds) when the short link was created.
     pub created_at: i64,
+    /// The Unix timestamp (in seconds) when the short link expires, if it does.
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    /// The tags of the short link, in alphabetical order.
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Order in which links are listed, by creation time.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    /// Oldest links first.
+    Asc,
+    /// Newest links first.
+    #[default]
+    Desc,
+}
+
+/// Query parameters of the `/api/v1/links` endpoint. Only links matching every set filter are listed.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema, utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct ListLinksQuery {
+    /// Host of the destination URL (e.g. `example.com`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// Text the destination URL contains, compared case-insensitively.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contains: Option<String>,
+    /// Only links created at or after this Unix timestamp (in seconds).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_after: Option<i64>,
+    /// Only links created before this Unix timestamp (in seconds).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_before: Option<i64>,
+    /// Only links with this tag.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// Sort order by creation time, newest first by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<SortOrder>,
+    /// Maximum number of links in the page. The server caps it and defaults to 50.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+    /// The `next_cursor` of the previous page, to fetch the page following it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+}
+
+/// Represents one page of links returned by the `/api/v1/links` endpoint.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ListLinksResponse {
+    /// The links of the page.
+    pub links: Vec<InfoResponse>,
+    /// Opaque cursor to pass in [`ListLinksQuery::cursor`] for the next page, `None` on the
+    /// last page.
+    #[serde(default)]
+    pub next_cursor: Option<String>,
+}
+
+/// Width of the time buckets in a [`StatsResponse`] timeline.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum StatsBucket {
+    /// One bucket per hour.
+    Hour,
+    /// One bucket per day (UTC).
+    #[default]
+    Day,
+    /// One bucket per week, starting on Monday (UTC).
+    Week,
+}
+
+/// Query parameters of the `/api/v1/stats/{code}` and `/api/v1/tags/{tag}/stats` endpoints.
+///
+/// The server defaults to the last 30 days bucketed by day.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema, utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct StatsQuery {
+    /// Start of the range as a Unix timestamp in seconds (inclusive).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<i64>,
+    /// End of the range as a Unix timestamp in seconds (exclusive).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<i64>,
+    /// Width of the timeline buckets.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bucket: Option<StatsBucket>,
+}
+
+/// Number of clicks in one bucket of a [`StatsResponse`] timeline.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TimeBucketCount {
+    /// Start of the bucket as a Unix timestamp in seconds.
+    pub start: i64,
+    /// Number of clicks in the bucket.
+    pub clicks: u64,
+}
+
+/// Number of clicks attributed to a referrer or user-agent family.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct NamedCount {
+    /// Referrer host or user-agent family.
+    pub name: String,
+    /// Number of clicks.
+    pub clicks: u64,
+}
+
+/// Represents the click statistics of a short link, or of every link with a tag, over a time
+/// range.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct StatsResponse {
+    /// The short code of the link, empty for the statistics of a tag.
+    pub code: String,
+    /// The tag of the links, for the statistics of a tag.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// Start of the range as a Unix timestamp in seconds (inclusive).
+    pub from: i64,
+    /// End of the range as a Unix timestamp in seconds (exclusive).
+    pub to: i64,
+    /// Width of the timeline buckets.
+    pub bucket: StatsBucket,
+    /// Total number of clicks in the range.
+    pub total_clicks: u64,
+    /// Number of distinct visitors (anonymized IP and user agent) in the range.
+    pub unique_visitors: u64,
+    /// Clicks per bucket, including empty buckets.
+    pub timeline: Vec<TimeBucketCount>,
+    /// The most frequent referrer hosts, `direct` standing for clicks without a referrer.
+    pub top_referrers: Vec<NamedCount>,
+    /// Clicks per user-agent family (e.g. `Firefox`, `Chrome`, `Bot`).
+    pub user_agents: Vec<NamedCount>,
+}
+
+/// Number of links with a tag.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TagCount {
+    /// The tag.
+    pub tag: String,
+    /// Number of links with the tag.
+    pub links: u64,
+}
+
+/// Represents the tags in use, returned by the `/api/v1/tags` endpoint.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TagsResponse {
+    /// Every tag of at least one link, in alphabetical order.
+    pub tags: Vec<TagCount>,
+}
+
+/// Image format of a QR code.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum QrFormat {
+    /// PNG image.
+    #[default]
+    Png,
+    /// SVG image.
+    Svg,
+    /// Unicode block characters for terminals with a dark background, two rows per line.
+    Text,
+}
+
+/// Error-correction level of a QR code. Higher levels survive more damage but make denser
+/// codes.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum QrErrorCorrection {
+    /// Recovers about 7% of the code.
+    Low,
+    /// Recovers about 15% of the code.
+    #[default]
+    Medium,
+    /// Recovers about 25% of the code.
+    Quartile,
+    /// Recovers about 30% of the code.
+    High,
+}
+
+/// Query parameters of the `/api/v1/qr/{code}` endpoint.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema, utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct QrQuery {
+    /// Image format, PNG by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<QrFormat>,
+    /// Approximate width and height of PNG and SVG images in pixels, at most 1024, rounded down
+    /// to a multiple of the number of modules (default 256). Smaller sizes than the number of
+    /// modules give one pixel per module.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u32>,
+    /// Width of the blank border in modules (default 4).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub margin: Option<u32>,
+    /// Error-correction level, medium by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ecc: Option<QrErrorCorrection>,
+    /// Color of the dark modules as `RRGGBB` hex, optionally prefixed by `#` (default `000000`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub foreground: Option<String>,
+    /// Color of the light modules and border as `RRGGBB` hex (default `ffffff`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background: Option<String>,
+}
+
+/// Kind of a link lifecycle event.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum LinkEventType {
+    /// A link was created.
+    Created,
+    /// The destination or expiration of a link was changed.
+    Updated,
+    /// A link was deleted.
+    Deleted,
+    /// An expired link was removed by the server.
+    Expired,
+    /// The total number of clicks on a link reached a threshold configured on the server.
+    ClickThreshold,
+    /// A link was followed.
+    Clicked,
+}
+
+impl LinkEventType {
+    /// Name of the event type, as serialized.
+    pub fn name(&self) -> &'static str {
+        match self {
+            LinkEventType::Created => "created",
+            LinkEventType::Updated => "updated",
+            LinkEventType::Deleted => "deleted",
+            LinkEventType::Expired => "expired",
+            LinkEventType::ClickThreshold => "click_threshold",
+            LinkEventType::Clicked => "clicked",
+        }
+    }
+}
+
+/// A link event, as sent in the body of webhook requests and streamed by the `/api/v1/events`
+/// endpoint.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct LinkEvent {
+    /// What happened to the link.
+    pub event: LinkEventType,
+    /// The short code of the link.
+    pub code: String,
+    /// The URL the link redirects to, when known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// The Unix timestamp (in seconds) when the event occurred.
+    pub timestamp: i64,
+    /// The threshold reached, for `click_threshold` events.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clicks: Option<i64>,
+    /// The page the link was followed from, for `clicked` events.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub referrer: Option<String>,
+}
+
+/// Query parameters of the `/api/v1/events` endpoint.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema, utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct EventsQuery {
+    /// Only stream the events of the link with this code.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
 }
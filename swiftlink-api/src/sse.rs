@@ -0,0 +1,124 @@
+//! Minimal parser of the `text/event-stream` responses of the `/api/v1/events` endpoint.
+
+/// Accumulates the `data` lines of the event being received.
+#[derive(Debug, Default)]
+pub(crate) struct SseParser {
+    data: Option<String>,
+    /// Bytes received after the last complete line
+    buffer: Vec<u8>,
+}
+
+impl SseParser {
+    /// Feeds one line of the stream, with or without its line terminator. Returns the data of
+    /// an event once the blank line ending it is fed.
+    fn feed_line(&mut self, line: &str) -> Option<String> {
+        let line = line.trim_end_matches(['\n', '\r']);
+        if line.is_empty() {
+            return self.data.take();
+        }
+        // Comments, event names and ids are skipped: the data names the event type already.
+        // A field without colon has an empty value.
+        let value = match line.strip_prefix("data") {
+            Some("") => "",
+            Some(value) => match value.strip_prefix(':') {
+                Some(value) => value.strip_prefix(' ').unwrap_or(value),
+                None => return None,
+            },
+            None => return None,
+        };
+        match &mut self.data {
+            Some(data) => {
+                data.push('\n');
+                data.push_str(value);
+            }
+            None => self.data = Some(value.to_string()),
+        }
+        None
+    }
+
+    /// Buffers a chunk of the stream, which may end in the middle of a line or character.
+    pub(crate) fn feed(&mut self, chunk: &[u8]) {
+        self.buffer.extend_from_slice(chunk);
+    }
+
+    /// Parses the complete lines buffered by [`SseParser::feed`] until an event ends, returning
+    /// its data. Returns `None` when more bytes are needed.
+    pub(crate) fn next_event(&mut self) -> Option<String> {
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            if let Some(data) = self.feed_line(&String::from_utf8_lossy(&line)) {
+                return Some(data);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Data of every event of a stream received in the given chunks
+    fn events(chunks: &[&[u8]]) -> Vec<String> {
+        let mut parser = SseParser::default();
+        let mut events = Vec::new();
+        for chunk in chunks {
+            parser.feed(chunk);
+            while let Some(data) = parser.next_event() {
+                events.push(data);
+            }
+        }
+        events
+    }
+
+    #[test]
+    fn data_lines_are_joined() {
+        assert_eq!(
+            events(&[b"data: {\"a\":\ndata:1}\n\ndata: second\n\n"]),
+            ["{\"a\":\n1}", "second"]
+        );
+        // Only the space following the colon is removed
+        assert_eq!(events(&[b"data:  two spaces\n\n"]), [" two spaces"]);
+        // A field without colon has an empty value
+        assert_eq!(events(&[b"data\ndata\n\n"]), ["\n"]);
+    }
+
+    #[test]
+    fn comments_and_other_fields_are_skipped() {
+        assert_eq!(
+            events(&[b": keep-alive\n\nevent: created\nid: 1\nretry: 5\ndata: x\n\n"]),
+            ["x"]
+        );
+        // Keep-alives alone never produce events
+        assert!(events(&[b":\n\n: ping\n\n"]).is_empty());
+        // Nor do fields merely starting with "data"
+        assert!(events(&[b"database: x\n\n"]).is_empty());
+    }
+
+    #[test]
+    fn crlf_lines_are_accepted() {
+        assert_eq!(events(&[b"data: a\r\ndata: b\r\n\r\n"]), ["a\nb"]);
+        let mut parser = SseParser::default();
+        assert_eq!(parser.feed_line("data: a\r\n"), None);
+        assert_eq!(parser.feed_line("\r\n").as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn events_split_across_reads() {
+        let stream = "data: {\"code\":\"é\"}\r\n\r\n: ping\n\ndata: end\n\n".as_bytes();
+        let whole = events(&[stream]);
+        assert_eq!(whole, ["{\"code\":\"é\"}", "end"]);
+        // Split at every position, including inside the two-byte "é" and the CRLF
+        for i in 0..=stream.len() {
+            assert_eq!(events(&[&stream[..i], &stream[i..]]), whole, "split at {i}");
+        }
+        let bytes: Vec<&[u8]> = stream.chunks(1).collect();
+        assert_eq!(events(&bytes), whole);
+    }
+
+    #[test]
+    fn incomplete_events_are_not_returned() {
+        assert!(events(&[b"data: a\n"]).is_empty());
+        assert!(events(&[b"data: a"]).is_empty());
+    }
+}
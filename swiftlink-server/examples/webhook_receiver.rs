@@ -0,0 +1,81 @@
+//! Local endpoint printing the link events sent by a Swiftlink server, to try webhooks out.
+//!
+//! ```text
+//! cargo run --example webhook_receiver -- 9000 my-secret
+//! ```
+//!
+//! with the following in the configuration of the server:
+//!
+//! ```toml
+//! [[webhooks]]
+//! url = "http://localhost:9000/"
+//! secret = "my-secret"
+//! ```
+
+use actix_web::{App, HttpRequest, HttpResponse, HttpServer, web};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+fn header<'a>(req: &'a HttpRequest, name: &str) -> &'a str {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+}
+
+/// Whether the signature header matches the body, see `webhooks::signature` in the server
+fn verify(secret: &str, req: &HttpRequest, body: &str) -> bool {
+    let Some(signature) = header(req, "X-Swiftlink-Signature").strip_prefix("sha256=") else {
+        return false;
+    };
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(format!("{}.{}", header(req, "X-Swiftlink-Timestamp"), body).as_bytes());
+    mac.verify_slice(&signature).is_ok()
+}
+
+async fn receive(
+    secret: web::Data<Option<String>>,
+    req: HttpRequest,
+    body: String,
+) -> HttpResponse {
+    let verified = match secret.as_deref() {
+        Some(secret) if !verify(secret, &req, &body) => {
+            println!("Rejected delivery with an invalid signature: {body}");
+            return HttpResponse::Unauthorized().finish();
+        }
+        Some(_) => "signed",
+        None => "unsigned",
+    };
+    println!(
+        "Delivery {} ({}, {}): {}",
+        header(&req, "X-Swiftlink-Delivery"),
+        header(&req, "X-Swiftlink-Event"),
+        verified,
+        body
+    );
+    HttpResponse::NoContent().finish()
+}
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let port: u16 = args
+        .next()
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(9000);
+    let secret = web::Data::new(args.next());
+
+    println!("Listening for webhook deliveries on port {port}");
+    HttpServer::new(move || {
+        App::new()
+            .app_data(secret.clone())
+            .default_service(web::post().to(receive))
+    })
+    .bind(("127.0.0.1", port))?
+    .run()
+    .await
+}
@@ -0,0 +1,13 @@
+ALTER TABLE links ADD COLUMN expires_at BIGINT;
+
+CREATE INDEX links_expires_at_idx ON links (expires_at) WHERE expires_at IS NOT NULL;
+
+-- Expired links moved out of `links` by the reaper when archiving is enabled.
+CREATE TABLE expired_links (
+    id BIGSERIAL PRIMARY KEY,
+    code TEXT NOT NULL,
+    url TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL,
+    archived_at BIGINT NOT NULL
+);
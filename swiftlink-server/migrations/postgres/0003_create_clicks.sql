@@ -0,0 +1,11 @@
+CREATE TABLE clicks (
+    id BIGSERIAL PRIMARY KEY,
+    code TEXT NOT NULL,
+    clicked_at BIGINT NOT NULL,
+    referrer TEXT,
+    user_agent TEXT,
+    -- Anonymized client address (last IPv4 octet / last 80 IPv6 bits zeroed)
+    client_ip TEXT
+);
+
+CREATE INDEX clicks_code_clicked_at_idx ON clicks (code, clicked_at);
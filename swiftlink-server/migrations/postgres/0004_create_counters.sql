@@ -0,0 +1,5 @@
+-- Named monotonic counters, used by the sequential and sqids code generators.
+CREATE TABLE counters (
+    name TEXT PRIMARY KEY,
+    value BIGINT NOT NULL
+);
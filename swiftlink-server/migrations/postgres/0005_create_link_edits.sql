@@ -0,0 +1,13 @@
+-- History of changes made through `PATCH /api/links/{code}`.
+CREATE TABLE link_edits (
+    id BIGSERIAL PRIMARY KEY,
+    code TEXT NOT NULL,
+    edited_at BIGINT NOT NULL,
+    actor TEXT NOT NULL,
+    old_url TEXT NOT NULL,
+    new_url TEXT NOT NULL,
+    old_expires_at BIGINT,
+    new_expires_at BIGINT
+);
+
+CREATE INDEX link_edits_code_idx ON link_edits (code, edited_at);
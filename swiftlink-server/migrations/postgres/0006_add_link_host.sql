@@ -0,0 +1,7 @@
+-- Destination host of each link, so that links can be listed and filtered by host.
+ALTER TABLE links ADD COLUMN host TEXT;
+
+UPDATE links SET host = LOWER(SUBSTRING(url FROM '^[^:]+://(?:[^@/?#]*@)?([^:/?#]+)'));
+
+CREATE INDEX links_host_idx ON links (host);
+CREATE INDEX links_created_at_idx ON links (created_at, code);
@@ -0,0 +1,8 @@
+-- Tags grouping links, e.g. by campaign. Tags are stored lowercased.
+CREATE TABLE link_tags (
+    code TEXT NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (code, tag)
+);
+
+CREATE INDEX link_tags_tag_idx ON link_tags (tag, code);
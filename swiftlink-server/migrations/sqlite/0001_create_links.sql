@@ -0,0 +1,6 @@
+-- `IF NOT EXISTS` so deployments created before migrations existed are adopted as-is.
+CREATE TABLE IF NOT EXISTS links (
+    code TEXT PRIMARY KEY,
+    url TEXT NOT NULL,
+    created_at BIGINT NOT NULL DEFAULT (strftime('%s', 'now'))
+);
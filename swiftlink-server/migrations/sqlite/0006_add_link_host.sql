@@ -0,0 +1,21 @@
+-- Destination host of each link, so that links can be listed and filtered by host.
+ALTER TABLE links ADD COLUMN host TEXT;
+
+-- SQLite has no regular expressions: take the authority following `://` (up to the first
+-- `/`, `?` or `#`), then drop the user info before `@` and the port after `:`.
+UPDATE links SET host = LOWER(SUBSTR(hostport, 1, INSTR(hostport || ':', ':') - 1))
+FROM (
+    SELECT code AS host_code, SUBSTR(authority, INSTR(authority, '@') + 1) AS hostport
+    FROM (
+        SELECT code, SUBSTR(rest, 1, INSTR(rest || '/', '/') - 1) AS authority
+        FROM (
+            SELECT code,
+                   REPLACE(REPLACE(SUBSTR(url, INSTR(url, '://') + 3), '?', '/'), '#', '/') AS rest
+            FROM links
+        )
+    )
+)
+WHERE links.code = host_code;
+
+CREATE INDEX links_host_idx ON links (host);
+CREATE INDEX links_created_at_idx ON links (created_at, code);
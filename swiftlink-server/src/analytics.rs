@@ -0,0 +1,215 @@
+use actix_web::rt;
+use log::{debug, warn};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::Arc,
+};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use crate::{
+    config::AnalyticsConfig,
+    events::EventBus,
+    storage::{ClickRecord, LinkStore},
+};
+
+/// Hands click events over to the background batch writer, so that recording a click never
+/// delays a redirect.
+#[derive(Clone)]
+pub struct ClickRecorder {
+    sender: Option<mpsc::Sender<ClickRecord>>,
+}
+
+impl ClickRecorder {
+    /// Queue a click event. Events are dropped if analytics are disabled or the queue is full.
+    pub fn record(&self, click: ClickRecord) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+
+        match sender.try_send(click) {
+            Ok(()) => {}
+            Err(TrySendError::Full(click)) => {
+                warn!("Click queue is full, dropping click on {}", click.code)
+            }
+            Err(TrySendError::Closed(_)) => warn!("Click writer stopped, dropping click"),
+        }
+    }
+}
+
+/// Spawn the batch writer persisting click events, returning the handle used to queue them.
+/// An event is published whenever the clicks on a link reach one of the `click_thresholds`.
+pub fn spawn(
+    store: Arc<dyn LinkStore>,
+    config: &AnalyticsConfig,
+    events: EventBus,
+) -> ClickRecorder {
+    if !config.enabled {
+        return ClickRecorder { sender: None };
+    }
+
+    let batch_size = config.batch_size.unwrap_or(100).max(1);
+    let (sender, mut receiver) = mpsc::channel(config.queue_size.unwrap_or(10_000).max(1));
+    let thresholds = config.click_thresholds.clone();
+
+    rt::spawn(async move {
+        let mut batch = Vec::with_capacity(batch_size);
+        // Waits for at least one event, then takes whatever else is already queued
+        while receiver.recv_many(&mut batch, batch_size).await > 0 {
+            match store.record_clicks(&batch).await {
+                Ok(()) => {
+                    debug!("Recorded {} click(s)", batch.len());
+                    if !thresholds.is_empty() {
+                        publish_thresholds(store.as_ref(), &batch, &thresholds, &events).await;
+                    }
+                }
+                Err(e) => warn!("Error recording {} click(s): {:?}", batch.len(), e),
+            }
+            batch.clear();
+        }
+    });
+
+    ClickRecorder {
+        sender: Some(sender),
+    }
+}
+
+/// Publish the thresholds crossed by the links of a batch of clicks just recorded. The batch
+/// writer being the only one recording clicks, the totals before the batch are known exactly.
+async fn publish_thresholds(
+    store: &dyn LinkStore,
+    batch: &[ClickRecord],
+    thresholds: &[i64],
+    events: &EventBus,
+) {
+    let mut in_batch: HashMap<String, i64> = HashMap::new();
+    for click in batch {
+        *in_batch.entry(click.code.clone()).or_insert(0) += 1;
+    }
+    let codes: Vec<String> = in_batch.keys().cloned().collect();
+    let totals = match store.count_clicks(&codes).await {
+        Ok(totals) => totals,
+        Err(e) => {
+            warn!("Error counting clicks: {:?}", e);
+            return;
+        }
+    };
+
+    for (code, total) in totals {
+        let before = total - in_batch.get(&code).copied().unwrap_or_default();
+        for &threshold in thresholds {
+            if before < threshold && threshold <= total {
+                events.publish_click_threshold(&code, threshold);
+            }
+        }
+    }
+}
+
+/// Strip the host part of an IP address: the last octet for IPv4, everything past the
+/// /48 prefix for IPv6.
+pub fn anonymize_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+            IpAddr::V4(Ipv4Addr::new(a, b, c, 0))
+        }
+        IpAddr::V6(v6) => {
+            let [a, b, c, ..] = v6.segments();
+            IpAddr::V6(Ipv6Addr::new(a, b, c, 0, 0, 0, 0, 0))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use swiftlink_api::LinkEventType;
+
+    use crate::storage::MemoryStore;
+
+    fn click(code: &str) -> ClickRecord {
+        ClickRecord {
+            code: code.to_string(),
+            clicked_at: 0,
+            referrer: None,
+            user_agent: None,
+            client_ip: None,
+        }
+    }
+
+    #[test]
+    fn anonymize_ipv4_keeps_24_bits() {
+        let ip: IpAddr = "203.0.113.197".parse().unwrap();
+        assert_eq!(anonymize_ip(ip), "203.0.113.0".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn anonymize_ipv6_keeps_48_bits() {
+        let ip: IpAddr = "2001:db8:85a3:8d3:1319:8a2e:370:7348".parse().unwrap();
+        assert_eq!(
+            anonymize_ip(ip),
+            "2001:db8:85a3::".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[actix_web::test]
+    async fn clicks_are_written_in_batches_and_cross_thresholds() {
+        let store = Arc::new(MemoryStore::default());
+        let config = AnalyticsConfig {
+            batch_size: Some(2),
+            click_thresholds: vec![3, 100],
+            ..Default::default()
+        };
+        let events = EventBus::new(None);
+        let mut receiver = events.subscribe();
+        let recorder = spawn(store.clone(), &config, events);
+
+        for _ in 0..5 {
+            recorder.record(click("abc"));
+        }
+        recorder.record(click("def"));
+
+        let event = rt::time::timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.event, LinkEventType::ClickThreshold);
+        assert_eq!(event.code, "abc");
+        assert_eq!(event.clicks, Some(3));
+
+        let mut counts = Vec::new();
+        for _ in 0..100 {
+            counts = store
+                .count_clicks(&["abc".into(), "def".into()])
+                .await
+                .unwrap();
+            counts.sort();
+            if counts.iter().map(|(_, clicks)| clicks).sum::<i64>() == 6 {
+                break;
+            }
+            rt::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(counts, vec![("abc".into(), 5), ("def".into(), 1)]);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[actix_web::test]
+    async fn disabled_analytics_drop_clicks() {
+        let store = Arc::new(MemoryStore::default());
+        let config = AnalyticsConfig {
+            enabled: false,
+            ..Default::default()
+        };
+        let recorder = spawn(store.clone(), &config, EventBus::new(None));
+        recorder.record(click("abc"));
+        rt::task::yield_now().await;
+        assert!(
+            store
+                .count_clicks(&["abc".into()])
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use log::{error, warn};
+use std::collections::HashSet;
+use swiftlink_api::ErrorCode;
+
+use crate::{config::BaseOptions, problem::Problem, storage::LinkStore};
+
+/// Codes that are or may become server routes, since codes are served at `/{code}`
+/// next to them. Compared case-insensitively.
+pub const BUILTIN_RESERVED_CODES: &[&str] = &[
+    "admin",
+    "api",
+    "app",
+    "assets",
+    "dashboard",
+    "docs",
+    "events",
+    "favicon",
+    "health",
+    "healthz",
+    "login",
+    "logout",
+    "metrics",
+    "openapi",
+    "qr",
+    "readyz",
+    "robots",
+    "static",
+    "stats",
+    "status",
+    "v1",
+    "version",
+    "www",
+];
+
+/// The built-in reserved codes plus the ones from `reserved_codes` in the configuration
+pub struct ReservedCodes(HashSet<String>);
+
+impl ReservedCodes {
+    pub fn from_config(options: &BaseOptions) -> Self {
+        let builtin = BUILTIN_RESERVED_CODES.iter().map(|code| code.to_string());
+        let configured = options.reserved_codes.iter().flatten().cloned();
+        Self(
+            builtin
+                .chain(configured)
+                .map(|code| code.to_ascii_lowercase())
+                .collect(),
+        )
+    }
+
+    pub fn contains(&self, code: &str) -> bool {
+        self.0.contains(&code.to_ascii_lowercase())
+    }
+
+    /// Warn about existing links whose code has since become reserved.
+    pub async fn warn_existing_conflicts(&self, store: &dyn LinkStore) {
+        let reserved: Vec<String> = self.0.iter().cloned().collect();
+        match store.find_codes_ignore_case(&reserved).await {
+            Ok(conflicts) => {
+                for code in conflicts {
+                    warn!(
+                        "Existing link {} uses a reserved code and may be shadowed by a route",
+                        code
+                    );
+                }
+            }
+            Err(e) => error!("Error checking links for reserved codes: {:?}", e),
+        }
+    }
+}
+
+/// Default bounds on the length of custom aliases
+pub const DEFAULT_ALIAS_MIN_LENGTH: usize = 3;
+pub const DEFAULT_ALIAS_MAX_LENGTH: usize = 64;
+
+/// Validate a custom alias requested in place of a generated code.
+/// Aliases may only contain ASCII letters, digits, `-` and `_`, and must not be reserved.
+pub fn validate_alias(
+    alias: &str,
+    options: &BaseOptions,
+    reserved: &ReservedCodes,
+) -> Result<(), Problem> {
+    let min = options.alias_min_length.unwrap_or(DEFAULT_ALIAS_MIN_LENGTH);
+    let max = options.alias_max_length.unwrap_or(DEFAULT_ALIAS_MAX_LENGTH);
+
+    if alias.len() < min {
+        return Err(Problem::bad_request(
+            ErrorCode::InvalidAlias,
+            "Alias is too short",
+        ));
+    }
+    if alias.len() > max {
+        return Err(Problem::bad_request(
+            ErrorCode::InvalidAlias,
+            "Alias is too long",
+        ));
+    }
+    if !alias
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(Problem::bad_request(
+            ErrorCode::InvalidAlias,
+            "Alias may only contain letters, digits, '-' and '_'",
+        ));
+    }
+    if reserved.contains(alias) {
+        return Err(Problem::bad_request(
+            ErrorCode::InvalidAlias,
+            "Alias is reserved",
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use utoipa::OpenApi;
+
+    use crate::{config::Config, openapi::ApiDoc};
+
+    /// Segments of the routes registered by the server that a code could shadow, or be
+    /// shadowed by, including those of the `/api` scope
+    pub(crate) const ROUTE_SEGMENTS: &[&str] =
+        &["api", "healthz", "readyz", "metrics", "qr", "v1", "version"];
+
+    fn check(alias: &str, options: &BaseOptions) -> Result<(), String> {
+        validate_alias(alias, options, &ReservedCodes::from_config(options)).map_err(|problem| {
+            assert!(matches!(problem.code, ErrorCode::InvalidAlias));
+            problem.detail
+        })
+    }
+
+    #[test]
+    fn alias_length_is_bounded() {
+        let mut options = Config::default().base;
+        assert_eq!(check("ab", &options).unwrap_err(), "Alias is too short");
+        assert!(check("abc", &options).is_ok());
+        assert!(check(&"a".repeat(64), &options).is_ok());
+        assert_eq!(
+            check(&"a".repeat(65), &options).unwrap_err(),
+            "Alias is too long"
+        );
+
+        options.alias_min_length = Some(1);
+        options.alias_max_length = Some(4);
+        assert!(check("a", &options).is_ok());
+        assert_eq!(check("abcde", &options).unwrap_err(), "Alias is too long");
+    }
+
+    #[test]
+    fn alias_charset_is_url_safe() {
+        let options = Config::default().base;
+        assert!(check("Summer_Sale-2025", &options).is_ok());
+        for alias in [
+            "with space",
+            "slash/es",
+            "dot.ted",
+            "percent%20",
+            "émoji",
+            "q?x=1",
+        ] {
+            assert!(check(alias, &options).is_err(), "{alias} was accepted");
+        }
+    }
+
+    #[test]
+    fn reserved_aliases_are_rejected_in_any_case() {
+        let mut options = Config::default().base;
+        options.reserved_codes = Some(vec!["Promo".to_string()]);
+        assert_eq!(check("API", &options).unwrap_err(), "Alias is reserved");
+        assert_eq!(check("promo", &options).unwrap_err(), "Alias is reserved");
+        assert_eq!(check("PROMO", &options).unwrap_err(), "Alias is reserved");
+    }
+
+    #[test]
+    fn documented_routes_are_listed() {
+        // Every top-level route next to `/{code}`, so that a new one cannot go unnoticed
+        let first_segments: BTreeSet<String> = ApiDoc::openapi()
+            .paths
+            .paths
+            .keys()
+            .filter_map(|path| path.split('/').find(|segment| !segment.is_empty()))
+            .filter(|segment| !segment.starts_with('{'))
+            .map(str::to_string)
+            .collect();
+        assert!(!first_segments.is_empty());
+        for segment in first_segments {
+            assert!(
+                ROUTE_SEGMENTS.contains(&segment.as_str()),
+                "/{segment} is missing from ROUTE_SEGMENTS"
+            );
+        }
+    }
+
+    #[test]
+    fn route_segments_are_rejected_as_aliases() {
+        let mut options = Config::default().base;
+        options.alias_min_length = Some(1);
+        for segment in ROUTE_SEGMENTS {
+            for alias in [segment.to_string(), segment.to_ascii_uppercase()] {
+                assert_eq!(
+                    check(&alias, &options).unwrap_err(),
+                    "Alias is reserved",
+                    "{alias} was accepted"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,331 @@
+use serde::Deserialize;
+use swiftlink_api::LinkEventType;
+
+/// Server configuration, comprising of base options and database configuration
+#[derive(Deserialize)]
+pub struct Config {
+    /// Base options
+    pub base: BaseOptions,
+    /// Database configuration details
+    pub database: DatabaseConfig,
+    /// Link expiration settings
+    #[serde(default)]
+    pub expiration: ExpirationConfig,
+    /// Click analytics settings
+    #[serde(default)]
+    pub analytics: AnalyticsConfig,
+    /// Readiness probe settings
+    #[serde(default)]
+    pub health: HealthConfig,
+    /// Prometheus metrics settings
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    /// Per-client rate limits
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    /// Cross-origin requests to the API
+    #[serde(default)]
+    pub cors: CorsConfig,
+    /// Endpoints notified of link events
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+}
+
+/// Base options, for the web server and core functionality
+#[derive(Deserialize)]
+pub struct BaseOptions {
+    /// Code length for generated short links, default is 6 if not provided.
+    /// Minimum length for the `sqids` generator, unused by `sequential` and `words`.
+    /// Random codes grow longer when collisions become frequent, and keep growing from
+    /// that length after a restart.
+    pub code_size: Option<usize>,
+    /// Strategy used to generate codes, default is `random`
+    pub code_generator: Option<CodeGeneratorKind>,
+    /// Characters used by the `random`, `sequential` and `sqids` generators,
+    /// default is ASCII letters and digits
+    pub code_alphabet: Option<String>,
+    /// Number of words in codes of the `words` generator, default is 3
+    pub code_words: Option<usize>,
+    /// Number of generated codes tried before giving up on creating a link, default is 5.
+    /// Also bounds the reserved codes skipped when generating each of them.
+    pub max_code_attempts: Option<u32>,
+    /// Codes that can neither be generated nor chosen as alias, on top of the built-in
+    /// ones reserved for server routes
+    pub reserved_codes: Option<Vec<String>>,
+    /// Port for the web server to listen on
+    pub port: Option<u16>,
+    /// Public URL of the short links, such as `https://example.com/s/`, used to return full
+    /// short URLs. Every endpoint is served under its path unless `path_prefix` is set. When
+    /// not set, short URLs are built from the host of each request.
+    pub public_base_url: Option<String>,
+    /// Path under which every endpoint is served, such as `/s`, default is the path of
+    /// `public_base_url`. Set it to `/` when a reverse proxy strips that path.
+    pub path_prefix: Option<String>,
+    /// Bearer token required to delete, update and list links, create or delete them in bulk,
+    /// read click statistics and tags, and subscribe to `/events`, as well as to scrape
+    /// `/metrics` unless `require_token` is off. Creating links, their info, QR codes and
+    /// redirects are public. If omitted, a 10-character token is generated at startup and logged.
+    pub bearer_token: Option<String>,
+    /// Minimum length of custom aliases, default is 3
+    pub alias_min_length: Option<usize>,
+    /// Maximum length of custom aliases, default is 64
+    pub alias_max_length: Option<usize>,
+    /// Maximum number of links created, or codes deleted, by one bulk request, default is 1000
+    pub max_bulk_links: Option<usize>,
+}
+
+/// Short-code generation strategies
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CodeGeneratorKind {
+    /// Random characters of the alphabet
+    #[default]
+    Random,
+    /// A counter written in the alphabet's base, yielding the shortest codes
+    Sequential,
+    /// Obfuscated counter values, short but not enumerable
+    Sqids,
+    /// Dash-separated dictionary words, easy to type and read aloud
+    Words,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DatabaseType {
+    Postgres,
+    Sqlite,
+    /// Volatile in-process storage, mostly useful for testing
+    Memory,
+}
+
+/// Database-specific configuration
+#[derive(Deserialize)]
+pub struct DatabaseConfig {
+    #[serde(default = "default_database_type")]
+    pub database_type: DatabaseType,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Optional host (default "localhost")
+    pub host: Option<String>,
+    /// Optional port (default 5432)
+    pub port: Option<u16>,
+    /// Optional database name (default "swiftlink_db")
+    pub database: Option<String>,
+    pub max_connections: Option<u32>,
+}
+
+/// Settings for the background task removing expired links
+#[derive(Deserialize, Default)]
+pub struct ExpirationConfig {
+    /// Seconds between two runs of the expired link reaper (default 300)
+    pub reaper_interval: Option<u64>,
+    /// Move expired links to the `expired_links` table instead of deleting them
+    #[serde(default)]
+    pub archive: bool,
+}
+
+/// Settings for recording clicks on redirects
+#[derive(Deserialize)]
+pub struct AnalyticsConfig {
+    /// Whether clicks are recorded at all (default true)
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Number of clicks that can wait to be written before new ones are dropped (default 10000)
+    pub queue_size: Option<usize>,
+    /// Maximum number of clicks written in one batch (default 100)
+    pub batch_size: Option<usize>,
+    /// Total numbers of clicks on a link that trigger a `click_threshold` event when reached
+    #[serde(default)]
+    pub click_thresholds: Vec<i64>,
+}
+
+impl Default for AnalyticsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            queue_size: None,
+            batch_size: None,
+            click_thresholds: Vec::new(),
+        }
+    }
+}
+
+/// Settings for the `/readyz` readiness probe
+#[derive(Deserialize, Default)]
+pub struct HealthConfig {
+    /// Share of the database connections in use above which the server reports itself as not
+    /// ready, between 0 and 1 (default 0.9)
+    pub max_pool_saturation: Option<f64>,
+    /// Milliseconds the database has to answer the probe (default 2000)
+    pub timeout_ms: Option<u64>,
+}
+
+/// Settings for the Prometheus `/metrics` endpoint
+#[derive(Deserialize)]
+pub struct MetricsConfig {
+    /// Whether `/metrics` is served (default false)
+    #[serde(default)]
+    pub enabled: bool,
+    /// Whether scrapers have to send the bearer token, since metrics reveal traffic and
+    /// database internals (default true)
+    #[serde(default = "default_true")]
+    pub require_token: bool,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            require_token: true,
+        }
+    }
+}
+
+/// Settings for the per-client rate limits, applied with one token bucket per client and
+/// kind of request
+#[derive(Deserialize, Default)]
+pub struct RateLimitConfig {
+    /// Whether requests are rate limited at all (default false). Behind a reverse proxy, this
+    /// needs `trust_forwarded_for`, or every client shares the buckets of the proxy
+    #[serde(default)]
+    pub enabled: bool,
+    /// Identify clients by the address their reverse proxy appended to `X-Forwarded-For`
+    /// instead of the peer address (default false). Also applies to the addresses recorded
+    /// with clicks
+    #[serde(default)]
+    pub trust_forwarded_for: bool,
+    /// Number of reverse proxies in front of the server, each appending the address it got
+    /// the request from to `X-Forwarded-For` (default 1). The client address is the entry this
+    /// many positions from the end, since clients can write any entry before it
+    pub trusted_proxies: Option<usize>,
+    /// Limit of single link creation (default 20 burst, 60 per minute)
+    pub create: Option<RateLimit>,
+    /// Limit of bulk link creation, each link taking a token (default 1000 burst, 1000 per
+    /// minute). Requests of more links than the burst need a full bucket
+    pub bulk_create: Option<RateLimit>,
+    /// Limit of the read endpoints: info, listing and stats (default 60 burst, 600 per minute)
+    pub info: Option<RateLimit>,
+    /// Limit of redirects (default 120 burst, 3000 per minute)
+    pub redirect: Option<RateLimit>,
+}
+
+/// A token bucket holding up to `burst` requests, refilled at `per_minute` requests per minute
+#[derive(Deserialize, Clone, Copy)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+/// Settings for cross-origin requests to the `/api` endpoints, redirects never being affected
+#[derive(Deserialize, Default)]
+pub struct CorsConfig {
+    /// Origins allowed to call the API (e.g. `https://tools.example.com`), `*` for any.
+    /// CORS headers are only sent when this is not empty (default empty)
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// Allowed methods, default is GET, POST, PATCH and DELETE
+    pub allowed_methods: Option<Vec<String>>,
+    /// Allowed request headers, default is Authorization and Content-Type
+    pub allowed_headers: Option<Vec<String>>,
+    /// Whether requests may carry credentials such as cookies (default false)
+    #[serde(default)]
+    pub allow_credentials: bool,
+    /// Seconds browsers may cache preflight responses (default 3600)
+    pub max_age: Option<usize>,
+}
+
+/// An HTTP endpoint receiving link events as signed JSON `POST` requests
+#[derive(Deserialize, Clone)]
+pub struct WebhookConfig {
+    /// URL the events are sent to
+    pub url: String,
+    /// Events sent to the endpoint, every one when not set. `clicked` is not delivered to
+    /// webhooks.
+    pub events: Option<Vec<LinkEventType>>,
+    /// Secret signing the requests with HMAC-SHA256 in the `X-Swiftlink-Signature` header
+    pub secret: Option<String>,
+    /// Attempts to deliver an event before giving up (default 8)
+    pub max_attempts: Option<u32>,
+    /// Seconds to wait for a response (default 10)
+    pub timeout: Option<u64>,
+}
+
+impl WebhookConfig {
+    /// Whether the endpoint subscribed to a type of event
+    pub fn accepts(&self, event: LinkEventType) -> bool {
+        match &self.events {
+            Some(events) => events.contains(&event),
+            None => event != LinkEventType::Clicked,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_database_type() -> DatabaseType {
+    DatabaseType::Postgres
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            base: BaseOptions {
+                code_size: Some(6),
+                code_generator: None,
+                code_alphabet: None,
+                code_words: None,
+                max_code_attempts: None,
+                reserved_codes: None,
+                port: Some(8080),
+                public_base_url: None,
+                path_prefix: None,
+                bearer_token: None,
+                alias_min_length: None,
+                alias_max_length: None,
+                max_bulk_links: None,
+            },
+            database: DatabaseConfig {
+                database_type: DatabaseType::Postgres,
+                username: Some("postgres".into()),
+                password: Some("password".into()),
+                host: Some("localhost".into()),
+                port: Some(5432),
+                database: Some("swiftlink_db".into()),
+                max_connections: Some(5),
+            },
+            expiration: ExpirationConfig::default(),
+            analytics: AnalyticsConfig::default(),
+            health: HealthConfig::default(),
+            metrics: MetricsConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            cors: CorsConfig::default(),
+            webhooks: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn optional_sections_default_to_safe_settings() {
+        let config: Config = toml::from_str(
+            r#"
+            [base]
+            [database]
+            database_type = "memory"
+            "#,
+        )
+        .unwrap();
+        assert!(!config.metrics.enabled);
+        assert!(config.metrics.require_token);
+        assert!(!config.rate_limit.enabled);
+        assert!(!config.rate_limit.trust_forwarded_for);
+        assert!(config.analytics.enabled);
+        assert!(config.cors.allowed_origins.is_empty());
+        assert!(config.webhooks.is_empty());
+    }
+}
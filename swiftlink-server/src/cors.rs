@@ -0,0 +1,81 @@
+//! Cross-origin resource sharing for browser front-ends calling the API.
+
+use actix_cors::Cors;
+use actix_web::{
+    http::{Method, header::HeaderName},
+    middleware::Condition,
+};
+
+use crate::config::CorsConfig;
+
+/// Methods allowed when `allowed_methods` is not set
+const DEFAULT_METHODS: &[&str] = &["GET", "POST", "PATCH", "DELETE"];
+
+/// Request headers allowed when `allowed_headers` is not set
+const DEFAULT_HEADERS: &[&str] = &["authorization", "content-type"];
+
+/// Response headers readable by scripts, on top of the CORS-safelisted ones
+const EXPOSED_HEADERS: &[&str] = &[
+    "ratelimit-limit",
+    "ratelimit-remaining",
+    "ratelimit-reset",
+    "retry-after",
+];
+
+/// Seconds browsers may cache preflight responses when `max_age` is not set
+const DEFAULT_MAX_AGE: usize = 3600;
+
+/// Check the CORS settings, which would otherwise only fail when the workers start.
+pub fn validate(config: &CorsConfig) -> Result<(), String> {
+    let any_origin = config.allowed_origins.iter().any(|origin| origin == "*");
+    if any_origin && config.allow_credentials {
+        return Err("CORS credentials cannot be allowed for any origin (\"*\")".to_string());
+    }
+    for origin in &config.allowed_origins {
+        if origin != "*" && url::Url::parse(origin).is_err() {
+            return Err(format!("Invalid CORS origin: {origin}"));
+        }
+    }
+    for method in config.allowed_methods.iter().flatten() {
+        Method::from_bytes(method.as_bytes())
+            .map_err(|_| format!("Invalid CORS method: {method}"))?;
+    }
+    for name in config.allowed_headers.iter().flatten() {
+        HeaderName::try_from(name.as_str()).map_err(|_| format!("Invalid CORS header: {name}"))?;
+    }
+    Ok(())
+}
+
+/// Build the CORS middleware of the API scope, doing nothing unless origins are configured.
+pub fn middleware(config: &CorsConfig) -> Condition<Cors> {
+    let mut cors = Cors::default()
+        .allowed_methods(
+            config
+                .allowed_methods
+                .as_ref()
+                .map(|methods| methods.iter().map(String::as_str).collect())
+                .unwrap_or_else(|| DEFAULT_METHODS.to_vec()),
+        )
+        .allowed_headers(
+            config
+                .allowed_headers
+                .as_ref()
+                .map(|headers| headers.iter().map(String::as_str).collect())
+                .unwrap_or_else(|| DEFAULT_HEADERS.to_vec()),
+        )
+        .expose_headers(EXPOSED_HEADERS.to_vec())
+        .max_age(config.max_age.unwrap_or(DEFAULT_MAX_AGE));
+
+    for origin in &config.allowed_origins {
+        cors = if origin == "*" {
+            cors.allow_any_origin()
+        } else {
+            cors.allowed_origin(origin)
+        };
+    }
+    if config.allow_credentials {
+        cors = cors.supports_credentials();
+    }
+
+    Condition::new(!config.allowed_origins.is_empty(), cors)
+}
@@ -0,0 +1,125 @@
+//! Internal bus of link events, published by the handlers and background tasks and consumed
+//! by the webhook worker and the `/api/v1/events` stream.
+//!
+//! The `/api/v1/events` streams subscribe to a broadcast channel, on which slow subscribers may
+//! miss events. Lifecycle events are also pushed to the queue of the webhook worker, without
+//! waiting for it. Clicks are only broadcast, webhooks receiving click thresholds instead.
+
+use actix_web::{rt, web::Bytes};
+use futures_util::{Stream, stream};
+use log::{debug, warn};
+use std::{convert::Infallible, time::Duration};
+use swiftlink_api::{LinkEvent, LinkEventType};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{unix_now, webhooks::WebhookQueue};
+
+/// Number of events a slow subscriber may lag behind before missing some
+const CAPACITY: usize = 1024;
+
+/// Interval of the comments sent on idle event streams, so that proxies keep them open
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// Handle publishing events to every subscriber
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<LinkEvent>,
+    webhooks: Option<WebhookQueue>,
+}
+
+impl EventBus {
+    /// Create a bus also sending lifecycle events to `webhooks` when set.
+    pub fn new(webhooks: Option<WebhookQueue>) -> Self {
+        Self {
+            sender: broadcast::channel(CAPACITY).0,
+            webhooks,
+        }
+    }
+
+    /// Publish an event on a link, timestamped now.
+    pub fn publish(&self, event: LinkEventType, code: &str, url: Option<&str>) {
+        self.send_lifecycle(LinkEvent {
+            event,
+            code: code.to_string(),
+            url: url.map(str::to_string),
+            timestamp: unix_now(),
+            clicks: None,
+            referrer: None,
+        });
+    }
+
+    /// Publish that a link was followed, to the event streams only.
+    pub fn publish_click(&self, code: &str, url: &str, referrer: Option<String>) {
+        self.send(LinkEvent {
+            event: LinkEventType::Clicked,
+            code: code.to_string(),
+            url: Some(url.to_string()),
+            timestamp: unix_now(),
+            clicks: None,
+            referrer,
+        });
+    }
+
+    /// Publish that the clicks on a link reached a threshold.
+    pub fn publish_click_threshold(&self, code: &str, clicks: i64) {
+        self.send_lifecycle(LinkEvent {
+            event: LinkEventType::ClickThreshold,
+            code: code.to_string(),
+            url: None,
+            timestamp: unix_now(),
+            clicks: Some(clicks),
+            referrer: None,
+        });
+    }
+
+    fn send_lifecycle(&self, event: LinkEvent) {
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.push(event.clone());
+        }
+        self.send(event);
+    }
+
+    fn send(&self, event: LinkEvent) {
+        if self.sender.send(event).is_err() {
+            debug!("No subscriber to link events");
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LinkEvent> {
+        self.sender.subscribe()
+    }
+}
+
+/// Body of a `text/event-stream` response carrying the events published from now on, only
+/// those of the link `code` if set. Events are named after their type and carry the JSON
+/// of the [`LinkEvent`] as data.
+pub fn sse_stream(
+    receiver: broadcast::Receiver<LinkEvent>,
+    code: Option<String>,
+) -> impl Stream<Item = Result<Bytes, Infallible>> {
+    stream::unfold(receiver, move |mut receiver| {
+        let code = code.clone();
+        async move {
+            loop {
+                let event = match rt::time::timeout(KEEP_ALIVE, receiver.recv()).await {
+                    Err(_) => return Some((Ok(Bytes::from_static(b": keep-alive\n\n")), receiver)),
+                    Ok(Ok(event)) => event,
+                    Ok(Err(RecvError::Lagged(missed))) => {
+                        warn!("Event stream lagging, {} event(s) skipped", missed);
+                        let comment = format!(": {missed} event(s) skipped\n\n");
+                        return Some((Ok(Bytes::from(comment)), receiver));
+                    }
+                    Ok(Err(RecvError::Closed)) => return None,
+                };
+                if code.as_ref().is_some_and(|code| *code != event.code) {
+                    continue;
+                }
+                let Ok(data) = serde_json::to_string(&event) else {
+                    continue;
+                };
+                let message = format!("event: {}\ndata: {}\n\n", event.event.name(), data);
+                return Some((Ok(Bytes::from(message)), receiver));
+            }
+        }
+    })
+}
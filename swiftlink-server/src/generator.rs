@@ -0,0 +1,669 @@
+//! Short-code generation strategies, selected with `code_generator` in the base options.
+
+use actix_web::rt;
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use prometheus::IntCounter;
+use rand::{Rng, seq::IndexedRandom};
+use std::{
+    collections::HashSet,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use crate::{
+    codes::ReservedCodes,
+    config::{BaseOptions, CodeGeneratorKind},
+    storage::{LinkStore, StoreError, StoreResult},
+};
+
+/// Alphabet used when `code_alphabet` is not set
+pub const DEFAULT_ALPHABET: &str = "0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// Name of the store counter backing the sequential and sqids generators
+const COUNTER_NAME: &str = "link_codes";
+
+/// Number of generated codes over which the collision rate is evaluated
+const GROWTH_WINDOW: u32 = 100;
+
+/// Collisions within one window that make random codes one character longer
+const GROWTH_THRESHOLD: u32 = 5;
+
+/// Names of the store counters recording how many times generated codes grew, so that the
+/// grown length survives restarts
+const RANDOM_GROWTH_COUNTER: &str = "random_code_growth";
+const WORDS_GROWTH_COUNTER: &str = "words_code_growth";
+
+/// Produces codes for new short links.
+#[async_trait]
+pub trait CodeGenerator: Send + Sync {
+    async fn generate(&self) -> StoreResult<String>;
+
+    /// Restore the state persisted by previous runs, before the first code is generated.
+    async fn restore(&self) -> StoreResult<()> {
+        Ok(())
+    }
+
+    /// Report whether the last generated code was already taken, so that the generator
+    /// can adapt to a crowded keyspace.
+    fn record_attempt(&self, _collided: bool) {}
+}
+
+/// Running totals of generated codes and collisions, from which the collision rate is derived.
+/// Also exported as Prometheus counters by `/metrics`.
+pub struct CollisionMetrics {
+    pub attempts: IntCounter,
+    pub collisions: IntCounter,
+}
+
+impl Default for CollisionMetrics {
+    fn default() -> Self {
+        Self {
+            attempts: IntCounter::new(
+                "swiftlink_code_attempts_total",
+                "Generated codes tried when creating links",
+            )
+            .expect("valid metric"),
+            collisions: IntCounter::new(
+                "swiftlink_code_collisions_total",
+                "Generated codes that were already taken",
+            )
+            .expect("valid metric"),
+        }
+    }
+}
+
+impl CollisionMetrics {
+    pub fn record(&self, collided: bool) {
+        self.attempts.inc();
+        if collided {
+            self.collisions.inc();
+        }
+    }
+
+    /// Fraction of generated codes that were already taken
+    pub fn rate(&self) -> f64 {
+        let attempts = self.attempts.get();
+        if attempts == 0 {
+            return 0.0;
+        }
+        self.collisions.get() as f64 / attempts as f64
+    }
+}
+
+/// A code length that grows by one whenever collisions become frequent, i.e. when the
+/// keyspace of the current length is getting crowded. The number of times it grew is kept in
+/// a store counter and added to the configured length on startup.
+struct AdaptiveLength {
+    /// Configured length
+    initial: usize,
+    current: AtomicUsize,
+    /// (attempts, collisions) in the current window
+    window: Mutex<(u32, u32)>,
+    store: Arc<dyn LinkStore>,
+    /// Counter of the growths in the store
+    counter: &'static str,
+}
+
+impl AdaptiveLength {
+    fn new(initial: usize, store: Arc<dyn LinkStore>, counter: &'static str) -> Self {
+        let initial = initial.max(1);
+        Self {
+            initial,
+            current: AtomicUsize::new(initial),
+            window: Mutex::new((0, 0)),
+            store,
+            counter,
+        }
+    }
+
+    fn get(&self) -> usize {
+        self.current.load(Ordering::Relaxed)
+    }
+
+    /// Resume from the length reached by previous runs.
+    async fn restore(&self, unit: &str) -> StoreResult<()> {
+        let grown = usize::try_from(self.store.counter(self.counter).await?).unwrap_or(0);
+        if grown > 0 {
+            let length = self.initial + grown;
+            info!(
+                "Codes grew {} time(s) before, generating {} {}",
+                grown, length, unit
+            );
+            self.current.store(length, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    fn record(&self, collided: bool, unit: &str) {
+        let mut window = self.window.lock().unwrap();
+        window.0 += 1;
+        window.1 += collided as u32;
+
+        if window.1 >= GROWTH_THRESHOLD {
+            let length = self.current.fetch_add(1, Ordering::Relaxed) + 1;
+            info!(
+                "{} collisions in the last {} generated codes, growing codes to {} {}",
+                window.1, window.0, length, unit
+            );
+            *window = (0, 0);
+
+            let store = self.store.clone();
+            let counter = self.counter;
+            rt::spawn(async move {
+                if let Err(e) = store.next_counter(counter).await {
+                    warn!("Error persisting the length of generated codes: {:?}", e);
+                }
+            });
+        } else if window.0 >= GROWTH_WINDOW {
+            *window = (0, 0);
+        }
+    }
+}
+
+/// Build the generator selected in the configuration, never producing reserved codes.
+pub fn from_config(
+    options: &BaseOptions,
+    store: Arc<dyn LinkStore>,
+    reserved: Arc<ReservedCodes>,
+) -> Result<Arc<dyn CodeGenerator>, String> {
+    let code_size = options.code_size.unwrap_or(6);
+    let alphabet = options.code_alphabet.as_deref().unwrap_or(DEFAULT_ALPHABET);
+    let alphabet = parse_alphabet(alphabet)?;
+
+    let generator: Arc<dyn CodeGenerator> = match options.code_generator.unwrap_or_default() {
+        CodeGeneratorKind::Random => Arc::new(RandomGenerator {
+            alphabet,
+            length: AdaptiveLength::new(code_size, store, RANDOM_GROWTH_COUNTER),
+        }),
+        CodeGeneratorKind::Sequential => Arc::new(SequentialGenerator { alphabet, store }),
+        CodeGeneratorKind::Sqids => Arc::new(SqidsGenerator {
+            alphabet: shuffle(alphabet),
+            min_length: code_size,
+            store,
+        }),
+        CodeGeneratorKind::Words => Arc::new(WordsGenerator {
+            words: AdaptiveLength::new(
+                options.code_words.unwrap_or(3),
+                store,
+                WORDS_GROWTH_COUNTER,
+            ),
+        }),
+    };
+    Ok(Arc::new(SkipReserved {
+        inner: generator,
+        reserved,
+        max_attempts: options.max_code_attempts.unwrap_or(5).max(1),
+    }))
+}
+
+/// Regenerates codes until one that is not reserved comes up, failing with
+/// [`StoreError::CodesExhausted`] after `max_attempts` reserved ones
+struct SkipReserved {
+    inner: Arc<dyn CodeGenerator>,
+    reserved: Arc<ReservedCodes>,
+    max_attempts: u32,
+}
+
+#[async_trait]
+impl CodeGenerator for SkipReserved {
+    async fn generate(&self) -> StoreResult<String> {
+        for _ in 0..self.max_attempts {
+            let code = self.inner.generate().await?;
+            if !self.reserved.contains(&code) {
+                return Ok(code);
+            }
+            debug!("Skipping reserved code {}", code);
+        }
+        Err(StoreError::CodesExhausted(self.max_attempts))
+    }
+
+    async fn restore(&self) -> StoreResult<()> {
+        self.inner.restore().await
+    }
+
+    fn record_attempt(&self, collided: bool) {
+        self.inner.record_attempt(collided);
+    }
+}
+
+/// Check that an alphabet has enough distinct, URL-safe characters.
+fn parse_alphabet(alphabet: &str) -> Result<Vec<char>, String> {
+    let chars: Vec<char> = alphabet.chars().collect();
+    if chars.len() < 3 {
+        return Err("code_alphabet must contain at least 3 characters".into());
+    }
+    if chars.iter().collect::<HashSet<_>>().len() != chars.len() {
+        return Err("code_alphabet must not contain duplicate characters".into());
+    }
+    if let Some(c) = chars
+        .iter()
+        .find(|c| !(c.is_ascii_alphanumeric() || **c == '-' || **c == '_'))
+    {
+        return Err(format!(
+            "code_alphabet contains unsupported character {c:?}"
+        ));
+    }
+    Ok(chars)
+}
+
+/// Encode a positive number in the positional system formed by the alphabet.
+fn to_base(mut value: u64, alphabet: &[char]) -> String {
+    let base = alphabet.len() as u64;
+    let mut digits = Vec::new();
+    loop {
+        digits.push(alphabet[(value % base) as usize]);
+        value /= base;
+        if value == 0 {
+            break;
+        }
+    }
+    digits.iter().rev().collect()
+}
+
+/// Uniformly random codes, `code_size` characters long until collisions become frequent
+struct RandomGenerator {
+    alphabet: Vec<char>,
+    length: AdaptiveLength,
+}
+
+#[async_trait]
+impl CodeGenerator for RandomGenerator {
+    async fn generate(&self) -> StoreResult<String> {
+        let mut rng = rand::rng();
+        Ok((0..self.length.get())
+            .map(|_| self.alphabet[rng.random_range(0..self.alphabet.len())])
+            .collect())
+    }
+
+    async fn restore(&self) -> StoreResult<()> {
+        self.length.restore("characters").await
+    }
+
+    fn record_attempt(&self, collided: bool) {
+        self.length.record(collided, "characters");
+    }
+}
+
+/// Shortest possible codes: a persistent counter written in the alphabet's base
+struct SequentialGenerator {
+    alphabet: Vec<char>,
+    store: Arc<dyn LinkStore>,
+}
+
+#[async_trait]
+impl CodeGenerator for SequentialGenerator {
+    async fn generate(&self) -> StoreResult<String> {
+        let value = self.store.next_counter(COUNTER_NAME).await?;
+        Ok(to_base(value as u64, &self.alphabet))
+    }
+}
+
+/// Non-enumerable codes derived from a persistent counter, following the Sqids encoding:
+/// consecutive values give unrelated-looking codes, padded to a minimum length.
+/// Using a custom (shuffled) alphabet makes the sequence specific to the deployment.
+struct SqidsGenerator {
+    alphabet: Vec<char>,
+    min_length: usize,
+    store: Arc<dyn LinkStore>,
+}
+
+/// Deterministic alphabet shuffle from the Sqids reference implementation
+fn shuffle(mut chars: Vec<char>) -> Vec<char> {
+    let len = chars.len();
+    let (mut i, mut j) = (0, len - 1);
+    while j > 0 {
+        let r = (i * j + chars[i] as usize + chars[j] as usize) % len;
+        chars.swap(i, r);
+        i += 1;
+        j -= 1;
+    }
+    chars
+}
+
+impl SqidsGenerator {
+    fn encode(&self, value: u64) -> String {
+        let len = self.alphabet.len();
+        let offset = (self.alphabet[(value % len as u64) as usize] as usize + 1) % len;
+
+        let mut alphabet = self.alphabet.clone();
+        alphabet.rotate_left(offset);
+        let prefix = alphabet[0];
+        alphabet.reverse();
+
+        let mut id = String::from(prefix);
+        id.push_str(&to_base(value, &alphabet[1..]));
+
+        if id.len() < self.min_length {
+            id.push(alphabet[0]);
+            while id.len() < self.min_length {
+                alphabet = shuffle(alphabet);
+                let missing = (self.min_length - id.len()).min(len);
+                id.extend(&alphabet[..missing]);
+            }
+        }
+        id
+    }
+}
+
+#[async_trait]
+impl CodeGenerator for SqidsGenerator {
+    async fn generate(&self) -> StoreResult<String> {
+        let value = self.store.next_counter(COUNTER_NAME).await?;
+        Ok(self.encode(value as u64))
+    }
+}
+
+/// Human-readable codes such as `brave-quiet-otter`: adjectives followed by a noun
+struct WordsGenerator {
+    words: AdaptiveLength,
+}
+
+const ADJECTIVES: &[&str] = &[
+    "able", "bold", "brave", "bright", "calm", "clean", "clever", "cool", "crisp", "curly",
+    "daring", "eager", "early", "easy", "fair", "fancy", "fast", "fine", "fluffy", "fresh",
+    "friendly", "funny", "gentle", "giant", "glad", "golden", "good", "grand", "green", "happy",
+    "honest", "humble", "jolly", "keen", "kind", "large", "lively", "lucky", "mellow", "merry",
+    "mighty", "modern", "neat", "nice", "noble", "odd", "plain", "polite", "proud", "quick",
+    "quiet", "rapid", "rare", "ready", "rich", "robust", "rosy", "royal", "shiny", "silent",
+    "simple", "sleepy", "smart", "smooth", "snowy", "soft", "solid", "spicy", "steady", "sunny",
+    "super", "sweet", "swift", "tall", "tidy", "tiny", "vast", "warm", "wild", "wise", "witty",
+    "young", "zesty",
+];
+
+const NOUNS: &[&str] = &[
+    "anchor", "apple", "badger", "bear", "beacon", "bison", "breeze", "brook", "cactus", "canyon",
+    "cedar", "cloud", "comet", "coral", "crane", "daisy", "dolphin", "eagle", "ember", "falcon",
+    "fern", "finch", "forest", "fox", "garden", "gecko", "glacier", "harbor", "hawk", "heron",
+    "island", "jaguar", "koala", "lagoon", "lantern", "lemur", "lion", "lotus", "maple", "meadow",
+    "meteor", "moose", "mountain", "nebula", "oak", "ocean", "orchid", "otter", "owl", "panda",
+    "pebble", "pepper", "pine", "planet", "puffin", "quartz", "rabbit", "raven", "reef", "river",
+    "robin", "rocket", "salmon", "sparrow", "spruce", "squid", "star", "stone", "summit", "swan",
+    "tiger", "tulip", "turtle", "valley", "walrus", "willow", "wolf", "zebra",
+];
+
+#[async_trait]
+impl CodeGenerator for WordsGenerator {
+    async fn generate(&self) -> StoreResult<String> {
+        let mut rng = rand::rng();
+        let mut words: Vec<&str> = (1..self.words.get())
+            .map(|_| *ADJECTIVES.choose(&mut rng).unwrap())
+            .collect();
+        words.push(NOUNS.choose(&mut rng).unwrap());
+        Ok(words.join("-"))
+    }
+
+    async fn restore(&self) -> StoreResult<()> {
+        self.words.restore("words").await
+    }
+
+    fn record_attempt(&self, collided: bool) {
+        self.words.record(collided, "words");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{codes::tests::ROUTE_SEGMENTS, config::Config, storage::MemoryStore};
+
+    /// Alphabet of the Sqids reference implementation
+    const SQIDS_ALPHABET: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+    fn sqids(min_length: usize) -> SqidsGenerator {
+        SqidsGenerator {
+            alphabet: shuffle(SQIDS_ALPHABET.chars().collect()),
+            min_length,
+            store: Arc::new(MemoryStore::default()),
+        }
+    }
+
+    fn from_base(code: &str, alphabet: &[char]) -> u64 {
+        code.chars().fold(0, |value, c| {
+            value * alphabet.len() as u64 + alphabet.iter().position(|a| *a == c).unwrap() as u64
+        })
+    }
+
+    #[test]
+    fn sqids_match_reference_encoding() {
+        // From the incremental numbers of the Sqids specification tests
+        let expected = ["bM", "Uk", "gb", "Ef", "Vq", "uw", "OI", "AX", "p6", "nJ"];
+        let generator = sqids(0);
+        for (value, code) in expected.iter().enumerate() {
+            assert_eq!(generator.encode(value as u64), *code);
+        }
+    }
+
+    #[test]
+    fn sqids_are_padded_to_min_length() {
+        for min_length in [0, 1, 6, 10, 62, 100] {
+            let generator = sqids(min_length);
+            for value in [0, 1, 61, 62, 1_000_000, u64::MAX] {
+                let code = generator.encode(value);
+                assert!(code.len() >= min_length);
+                assert!(code.starts_with(&sqids(0).encode(value)));
+                if code.len() > sqids(0).encode(value).len() {
+                    assert_eq!(code.len(), min_length);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn to_base_round_trips() {
+        let alphabet: Vec<char> = DEFAULT_ALPHABET.chars().collect();
+        assert_eq!(to_base(0, &alphabet), "0");
+        assert_eq!(to_base(61, &alphabet), "Z");
+        assert_eq!(to_base(62, &alphabet), "10");
+        for value in [0, 1, 9, 61, 62, 3_843, 3_844, 123_456_789, u64::MAX] {
+            assert_eq!(from_base(&to_base(value, &alphabet), &alphabet), value);
+        }
+
+        let binary = ['a', 'b'];
+        assert_eq!(to_base(5, &binary), "bab");
+        assert_eq!(from_base("bab", &binary), 5);
+    }
+
+    #[test]
+    fn alphabets_are_checked() {
+        assert!(parse_alphabet("ab").is_err());
+        assert!(parse_alphabet("abca").is_err());
+        assert!(parse_alphabet("abc/").is_err());
+        assert_eq!(parse_alphabet("a-_").unwrap(), vec!['a', '-', '_']);
+    }
+
+    #[actix_web::test]
+    async fn random_codes_use_the_alphabet() {
+        let generator = RandomGenerator {
+            alphabet: vec!['x', 'y', 'z'],
+            length: AdaptiveLength::new(8, Arc::new(MemoryStore::default()), "test"),
+        };
+        for _ in 0..100 {
+            let code = generator.generate().await.unwrap();
+            assert_eq!(code.len(), 8);
+            assert!(code.chars().all(|c| "xyz".contains(c)));
+        }
+    }
+
+    #[actix_web::test]
+    async fn words_come_from_the_word_lists() {
+        let generator = WordsGenerator {
+            words: AdaptiveLength::new(3, Arc::new(MemoryStore::default()), "test"),
+        };
+        for _ in 0..100 {
+            let code = generator.generate().await.unwrap();
+            let words: Vec<&str> = code.split('-').collect();
+            assert_eq!(words.len(), 3);
+            assert!(ADJECTIVES.contains(&words[0]));
+            assert!(ADJECTIVES.contains(&words[1]));
+            assert!(NOUNS.contains(&words[2]));
+            assert!(code.chars().all(|c| c.is_ascii_lowercase() || c == '-'));
+        }
+    }
+
+    #[actix_web::test]
+    async fn sequential_codes_count_in_the_alphabet() {
+        let mut options = Config::default().base;
+        options.code_generator = Some(CodeGeneratorKind::Sequential);
+        options.code_alphabet = Some("abc".to_string());
+        let reserved = Arc::new(ReservedCodes::from_config(&options));
+        let generator = from_config(&options, Arc::new(MemoryStore::default()), reserved).unwrap();
+
+        let mut codes = Vec::new();
+        for _ in 0..4 {
+            codes.push(generator.generate().await.unwrap());
+        }
+        assert_eq!(codes, ["b", "c", "ba", "bb"]);
+    }
+
+    /// Yields the given codes in order
+    struct Scripted(Mutex<Vec<&'static str>>);
+
+    #[async_trait]
+    impl CodeGenerator for Scripted {
+        async fn generate(&self) -> StoreResult<String> {
+            Ok(self.0.lock().unwrap().remove(0).to_string())
+        }
+    }
+
+    #[actix_web::test]
+    async fn reserved_codes_are_skipped() {
+        let mut options = Config::default().base;
+        options.reserved_codes = Some(vec!["taken".to_string()]);
+        let generator = SkipReserved {
+            inner: Arc::new(Scripted(Mutex::new(vec![
+                "API", "taken", "Metrics", "free",
+            ]))),
+            reserved: Arc::new(ReservedCodes::from_config(&options)),
+            max_attempts: 4,
+        };
+        assert_eq!(generator.generate().await.unwrap(), "free");
+    }
+
+    #[actix_web::test]
+    async fn generated_codes_are_never_reserved() {
+        let mut options = Config::default().base;
+        // Every code of one or two characters but `bb` is reserved
+        options.code_alphabet = Some("abc".to_string());
+        options.code_size = Some(2);
+        // Random codes are `bb` one time in nine
+        options.max_code_attempts = Some(1000);
+        let short: Vec<String> = ["a", "b", "c"]
+            .iter()
+            .flat_map(|first| ["", "a", "b", "c"].map(|second| format!("{first}{second}")))
+            .filter(|code| code != "bb")
+            .collect();
+        options.reserved_codes = Some(short);
+        let reserved = Arc::new(ReservedCodes::from_config(&options));
+
+        for kind in [CodeGeneratorKind::Sequential, CodeGeneratorKind::Random] {
+            options.code_generator = Some(kind);
+            let generator =
+                from_config(&options, Arc::new(MemoryStore::default()), reserved.clone()).unwrap();
+            for _ in 0..20 {
+                let code = generator.generate().await.unwrap();
+                assert!(!reserved.contains(&code), "{code} is reserved");
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn frequent_collisions_grow_length_once() {
+        let store = Arc::new(MemoryStore::default());
+        let length = AdaptiveLength::new(6, store.clone(), RANDOM_GROWTH_COUNTER);
+
+        for _ in 0..GROWTH_THRESHOLD - 1 {
+            length.record(true, "characters");
+        }
+        assert_eq!(length.get(), 6);
+        length.record(true, "characters");
+        assert_eq!(length.get(), 7);
+        // The window starts over after growing
+        assert_eq!(*length.window.lock().unwrap(), (0, 0));
+        for _ in 0..GROWTH_THRESHOLD - 1 {
+            length.record(true, "characters");
+        }
+        assert_eq!(length.get(), 7);
+
+        rt::task::yield_now().await;
+        assert_eq!(store.counter(RANDOM_GROWTH_COUNTER).await.unwrap(), 1);
+    }
+
+    #[actix_web::test]
+    async fn rare_collisions_do_not_grow_length() {
+        let length = AdaptiveLength::new(6, Arc::new(MemoryStore::default()), "test");
+        for window in 0..3 {
+            for attempt in 0..GROWTH_WINDOW {
+                length.record(attempt < GROWTH_THRESHOLD - 1, "characters");
+            }
+            assert_eq!(length.get(), 6, "grew in window {window}");
+            assert_eq!(*length.window.lock().unwrap(), (0, 0));
+        }
+    }
+
+    #[actix_web::test]
+    async fn grown_length_is_restored() {
+        let store: Arc<dyn LinkStore> = Arc::new(MemoryStore::default());
+        let options = Config::default().base;
+        let reserved = Arc::new(ReservedCodes::from_config(&options));
+
+        let generator = from_config(&options, store.clone(), reserved.clone()).unwrap();
+        for _ in 0..2 * GROWTH_THRESHOLD {
+            generator.record_attempt(true);
+        }
+        assert_eq!(generator.generate().await.unwrap().len(), 8);
+        rt::task::yield_now().await;
+
+        let restarted = from_config(&options, store, reserved).unwrap();
+        assert_eq!(restarted.generate().await.unwrap().len(), 6);
+        restarted.restore().await.unwrap();
+        assert_eq!(restarted.generate().await.unwrap().len(), 8);
+    }
+
+    #[actix_web::test]
+    async fn route_segments_are_never_generated() {
+        let options = Config::default().base;
+        let mut codes: Vec<&'static str> = ROUTE_SEGMENTS.to_vec();
+        codes.push("free");
+        let generator = SkipReserved {
+            inner: Arc::new(Scripted(Mutex::new(codes))),
+            reserved: Arc::new(ReservedCodes::from_config(&options)),
+            max_attempts: ROUTE_SEGMENTS.len() as u32 + 1,
+        };
+        assert_eq!(generator.generate().await.unwrap(), "free");
+    }
+
+    #[actix_web::test]
+    async fn reserved_codes_exhaust_the_attempts() {
+        let mut options = Config::default().base;
+        options.code_alphabet = Some("abc".to_string());
+        options.code_size = Some(1);
+        options.max_code_attempts = Some(3);
+        let short: Vec<String> = ["a", "b", "c"]
+            .iter()
+            .flat_map(|first| ["", "a", "b", "c"].map(|second| format!("{first}{second}")))
+            .collect();
+        options.reserved_codes = Some(short);
+        let reserved = Arc::new(ReservedCodes::from_config(&options));
+
+        // Every possible random code is reserved
+        let generator =
+            from_config(&options, Arc::new(MemoryStore::default()), reserved.clone()).unwrap();
+        for _ in 0..3 {
+            assert!(matches!(
+                generator.generate().await,
+                Err(StoreError::CodesExhausted(3))
+            ));
+        }
+
+        // The counter moves past the reserved codes across calls: b, c, ba, then bb, bc, ca,
+        // then cb, cc and baa
+        options.code_generator = Some(CodeGeneratorKind::Sequential);
+        let generator = from_config(&options, Arc::new(MemoryStore::default()), reserved).unwrap();
+        assert!(generator.generate().await.is_err());
+        assert!(generator.generate().await.is_err());
+        assert_eq!(generator.generate().await.unwrap(), "baa");
+    }
+}
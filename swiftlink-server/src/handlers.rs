@@ -0,0 +1,1375 @@
+use actix_web::{
+    HttpRequest, HttpResponse, Responder,
+    http::{StatusCode, header},
+    web,
+};
+use log::{error, info, warn};
+use std::collections::HashMap;
+use swiftlink_api::{
+    BulkCreateResponse, BulkCreateResult, BulkDeleteRequest, BulkDeleteResponse, CreateLinkRequest,
+    CreateLinkResponse, ErrorCode, EventsQuery, InfoResponse, LinkEvent, LinkEventType,
+    ListLinksQuery, ListLinksResponse, ProblemDetails, QrQuery, SortOrder, StatsBucket, StatsQuery,
+    StatsResponse, TagCount, TagsResponse, UpdateLinkRequest, VersionResponse,
+};
+use url::Url;
+
+use crate::{
+    AppState,
+    analytics::anonymize_ip,
+    codes::validate_alias,
+    events,
+    problem::Problem,
+    public_url::short_url,
+    qr::{self, QrOptions},
+    rate_limit, stats,
+    storage::{
+        ClickRecord, LinkChanges, LinkFilter, LinkRecord, LinkStore, PageRequest, StoreError,
+    },
+    tags::{normalize_tag, normalize_tags},
+    unix_now,
+};
+
+/// Number of links in a page of `/api/v1/links` when no limit is requested
+const DEFAULT_PAGE_SIZE: u32 = 50;
+
+/// Largest number of links in a page of `/api/v1/links`
+const MAX_PAGE_SIZE: u32 = 500;
+
+/// API versions served under `/api/{version}`, oldest first
+const API_VERSIONS: &[&str] = &["v1"];
+
+/// Optional features reported by `/api/version`, so that clients can adapt to older servers
+const CAPABILITIES: &[&str] = &[
+    "bulk_create",
+    "bulk_delete",
+    "link_listing",
+    "link_updates",
+    "click_stats",
+    "problem_details",
+    "openapi",
+    "health",
+    "qr_codes",
+    "event_stream",
+    "tags",
+    #[cfg(feature = "swagger-ui")]
+    "swagger_ui",
+];
+
+/// Number of links one bulk request may create when `max_bulk_links` is not set
+const DEFAULT_MAX_BULK_LINKS: usize = 1000;
+
+/// Author recorded in the edit history, the bearer token being the only credential
+const TOKEN_ACTOR: &str = "bearer_token";
+
+/// Validate URL and returns an error if invalid.
+/// Returns Ok(()) if valid.
+fn validate_url(input: &str) -> Result<(), Problem> {
+    match Url::parse(input) {
+        Ok(url) => {
+            if url.has_host() {
+                Ok(())
+            } else {
+                Err(Problem::bad_request(
+                    ErrorCode::InvalidUrl,
+                    "URL must have a host",
+                ))
+            }
+        }
+        Err(_) => Err(Problem::bad_request(ErrorCode::InvalidUrl, "Invalid URL")),
+    }
+}
+
+/// Public view of a stored link with its tags, `base` being the base of short URLs
+fn link_info(record: LinkRecord, tags: Vec<String>, base: &str) -> InfoResponse {
+    InfoResponse {
+        short_url: short_url(base, &record.code),
+        code: record.code,
+        created_at: record.created_at,
+        url: record.url,
+        expires_at: record.expires_at,
+        tags,
+    }
+}
+
+/// Fetch the tags of the given links, by code. Links without tags are left out.
+async fn tags_by_code(
+    store: &dyn LinkStore,
+    codes: &[String],
+) -> Result<HashMap<String, Vec<String>>, StoreError> {
+    let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+    for (code, tag) in store.link_tags(codes).await? {
+        tags.entry(code).or_default().push(tag);
+    }
+    Ok(tags)
+}
+
+/// Fetch the tags of one link.
+async fn tags_of(store: &dyn LinkStore, code: &str) -> Result<Vec<String>, StoreError> {
+    let tags = store.link_tags(&[code.to_string()]).await?;
+    Ok(tags.into_iter().map(|(_, tag)| tag).collect())
+}
+
+/// Cursor pointing after the given link in the listing order
+fn encode_cursor(link: &LinkRecord) -> String {
+    format!("{}:{}", link.created_at, link.code)
+}
+
+/// Parse a cursor made by [`encode_cursor`] back into `(created_at, code)`.
+fn decode_cursor(cursor: &str) -> Option<(i64, String)> {
+    let (created_at, code) = cursor.split_once(':')?;
+    Some((created_at.parse().ok()?, code.to_string()))
+}
+
+/// Read a header as an owned string, if present and valid.
+fn header_string(req: &HttpRequest, name: header::HeaderName) -> Option<String> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Resolve the absolute expiration of a link from either `expires_at` or `ttl`.
+/// Returns an error if both are given or the expiration is not in the future.
+fn resolve_expiration(
+    expires_at: Option<i64>,
+    ttl: Option<u64>,
+    now: i64,
+) -> Result<Option<i64>, Problem> {
+    let invalid = |detail| Problem::bad_request(ErrorCode::InvalidExpiration, detail);
+    let expires_at = match (expires_at, ttl) {
+        (Some(_), Some(_)) => return Err(invalid("Only one of expires_at and ttl may be set")),
+        (Some(expires_at), None) => expires_at,
+        (None, Some(ttl)) => i64::try_from(ttl)
+            .ok()
+            .and_then(|ttl| now.checked_add(ttl))
+            .ok_or_else(|| invalid("ttl is too large"))?,
+        (None, None) => return Ok(None),
+    };
+
+    if expires_at <= now {
+        return Err(invalid("Expiration must be in the future"));
+    }
+    Ok(Some(expires_at))
+}
+
+/// Validates the URL, alias, expiration and tags of a request to create a link.
+/// Returns the absolute expiration and the normalized tags of the link, or an error if invalid.
+fn validate_create_request(
+    state: &AppState,
+    req: &CreateLinkRequest,
+    now: i64,
+) -> Result<(Option<i64>, Vec<String>), Problem> {
+    validate_url(&req.url)?;
+    if let Some(alias) = &req.code {
+        validate_alias(alias, &state.config.base, &state.reserved_codes)?;
+    }
+    let expires_at = resolve_expiration(req.expires_at, req.ttl, now)?;
+    Ok((expires_at, normalize_tags(&req.tags)?))
+}
+
+/// Checks if the URL already exists in the database.
+/// Returns Ok(Some(existing_code)) if found, Ok(None) if not found,
+/// or Err(response) if a database error occurs.
+async fn check_existing_url(
+    store: &dyn LinkStore,
+    url: &str,
+) -> Result<Option<String>, HttpResponse> {
+    match store.find_code_by_url(url).await {
+        Ok(result) => Ok(result),
+        Err(e) => {
+            error!("Error checking for existing URL: {:?}", e);
+            Err(Problem::internal("Error creating link").into())
+        }
+    }
+}
+
+/// Looks up the link holding a taken alias. Requesting the same alias for the same
+/// permanent URL again is not an error: the existing link is returned in that case,
+/// and `None` if the alias belongs to another link.
+async fn existing_alias(
+    store: &dyn LinkStore,
+    link: &LinkRecord,
+    base: &str,
+) -> Result<Option<CreateLinkResponse>, StoreError> {
+    let existing = store.get_link(&link.code).await?;
+    Ok(existing
+        .filter(|existing| {
+            existing.url == link.url && existing.expires_at.is_none() && link.expires_at.is_none()
+        })
+        .map(|existing| CreateLinkResponse {
+            short_url: short_url(base, &existing.code),
+            code: existing.code,
+            url: existing.url,
+            expires_at: None,
+        }))
+}
+
+/// Handles a custom alias that is already taken, see [`existing_alias`].
+async fn handle_alias_conflict(
+    store: &dyn LinkStore,
+    link: &LinkRecord,
+    base: &str,
+) -> HttpResponse {
+    match existing_alias(store, link, base).await {
+        Ok(Some(existing)) => HttpResponse::Ok().json(existing),
+        Ok(None) => Problem::new(
+            StatusCode::CONFLICT,
+            ErrorCode::AliasTaken,
+            format!("Alias {} is already taken", link.code),
+        )
+        .into(),
+        Err(e) => {
+            error!("Error fetching existing link after alias conflict: {:?}", e);
+            Problem::internal("Error creating link").into()
+        }
+    }
+}
+
+/// Returns an existing code differing from `alias` only by case, such as `Promo` for `promo`.
+/// Codes are case-sensitive, but such links would be mistaken for one another.
+async fn case_clash(store: &dyn LinkStore, alias: &str) -> Result<Option<String>, StoreError> {
+    let found = store.find_codes_ignore_case(&[alias.to_string()]).await?;
+    Ok(found.into_iter().find(|code| code != alias))
+}
+
+fn alias_taken(alias: &str, existing: &str) -> Problem {
+    Problem::new(
+        StatusCode::CONFLICT,
+        ErrorCode::AliasTaken,
+        format!("Alias {alias} is already taken by {existing} in another case"),
+    )
+}
+
+/// Checks the `Authorization: Bearer` header against the configured token.
+/// Returns Err(problem) if the request is not authorized.
+pub(crate) fn check_bearer_token(state: &AppState, req: &HttpRequest) -> Result<(), Problem> {
+    let configured_token = match &state.config.base.bearer_token {
+        Some(tok) => tok.clone(),
+        None => {
+            error!("Bearer token was somehow not set in configuration.");
+            return Err(Problem::internal("Server misconfiguration"));
+        }
+    };
+
+    let auth_header = match req.headers().get("Authorization") {
+        Some(hv) => hv.to_str().unwrap_or(""),
+        None => "",
+    };
+
+    let expected_prefix = "Bearer ";
+    if !auth_header.starts_with(expected_prefix) {
+        return Err(Problem::unauthorized(
+            "Missing or invalid authorization header",
+        ));
+    }
+    let provided_token = &auth_header[expected_prefix.len()..];
+    if provided_token != configured_token {
+        return Err(Problem::unauthorized("Invalid bearer token"));
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/links/{code}",
+    tag = "links",
+    params(("code" = String, Path, description = "Short code of the link")),
+    security(("bearer_token" = [])),
+    responses(
+        (status = 200, description = "Link deleted"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown code", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn delete_link(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> impl Responder {
+    if let Err(e) = check_bearer_token(&state, &req) {
+        return e.into();
+    }
+
+    let code_to_delete: String = path.into_inner();
+    let result = state.store.delete_link(&code_to_delete).await;
+    info!("Deleting code: {code_to_delete}");
+
+    match result {
+        Ok(true) => {
+            state
+                .events
+                .publish(LinkEventType::Deleted, &code_to_delete, None);
+            HttpResponse::Ok().body("Link deleted")
+        }
+        Ok(false) => Problem::link_not_found().into(),
+        Err(e) => {
+            warn!("Error deleting link: {:?}", e);
+            Problem::internal("Error deleting link").into()
+        }
+    }
+}
+
+/// API Handler: Delete every link given by code or matching a filter
+///
+/// Requires the bearer token. With `dry_run`, nothing is deleted and the response lists the
+/// links that would be.
+#[utoipa::path(
+    post,
+    path = "/api/v1/delete/bulk",
+    tag = "links",
+    request_body = BulkDeleteRequest,
+    security(("bearer_token" = [])),
+    responses(
+        (status = 200, description = "Codes of the deleted links", body = BulkDeleteResponse),
+        (status = 400, description = "No link selected", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn delete_links_bulk(
+    state: web::Data<AppState>,
+    body: web::Json<BulkDeleteRequest>,
+    req: HttpRequest,
+) -> impl Responder {
+    if let Err(e) = check_bearer_token(&state, &req) {
+        return e.into();
+    }
+
+    let request = body.into_inner();
+    if request.codes.is_none()
+        && request.host.is_none()
+        && request.created_before.is_none()
+        && request.tag.is_none()
+    {
+        return Problem::invalid_request("codes, host, created_before or tag must be set").into();
+    }
+    let tag = match request.tag.as_deref().map(normalize_tag).transpose() {
+        Ok(tag) => tag,
+        Err(e) => return e.into(),
+    };
+    let max_links = state
+        .config
+        .base
+        .max_bulk_links
+        .unwrap_or(DEFAULT_MAX_BULK_LINKS);
+    if request
+        .codes
+        .as_ref()
+        .is_some_and(|codes| codes.len() > max_links)
+    {
+        return Problem::invalid_request(format!(
+            "At most {max_links} codes can be deleted at once"
+        ))
+        .into();
+    }
+
+    let filter = LinkFilter {
+        codes: request.codes,
+        host: request.host,
+        created_before: request.created_before,
+        tag,
+        ..LinkFilter::default()
+    };
+    let result = if request.dry_run {
+        state.store.find_codes(&filter).await
+    } else {
+        state.store.delete_links(&filter).await
+    };
+
+    match result {
+        Ok(codes) => {
+            if !request.dry_run {
+                info!("Deleted {} links: {:?}", codes.len(), codes);
+                for code in &codes {
+                    state.events.publish(LinkEventType::Deleted, code, None);
+                }
+            }
+            HttpResponse::Ok().json(BulkDeleteResponse {
+                dry_run: request.dry_run,
+                codes,
+            })
+        }
+        Err(e) => {
+            warn!("Error deleting links: {:?}", e);
+            Problem::internal("Error deleting links").into()
+        }
+    }
+}
+
+/// API Handler: Create a new short link
+///
+/// The main handler calls helper functions for input validation,
+/// existing URL check, insertions etc. This makes error handling and
+/// code readability better.
+#[utoipa::path(
+    post,
+    path = "/api/v1/create",
+    tag = "links",
+    request_body = CreateLinkRequest,
+    responses(
+        (status = 200, description = "The new or already existing short link", body = CreateLinkResponse),
+        (status = 400, description = "Invalid URL, alias, expiration or tags", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "Alias already taken", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "No free code could be generated", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 429, description = "Rate limited", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn create_link(
+    state: web::Data<AppState>,
+    req: web::Json<CreateLinkRequest>,
+    http_req: HttpRequest,
+) -> impl Responder {
+    let base = state.public_url.base_for(&http_req);
+
+    // Input Validation
+    let created_at = unix_now();
+    let (expires_at, tags) = match validate_create_request(&state, &req, created_at) {
+        Ok(validated) => validated,
+        Err(e) => return e.into(),
+    };
+
+    // Check if URL is already present in the DB. Expiring and tagged links are never shared,
+    // since each one has its own lifetime or group, and aliases always get their own link.
+    if expires_at.is_none() && req.code.is_none() && tags.is_empty() {
+        match check_existing_url(state.store.as_ref(), &req.url).await {
+            Ok(Some(existing_code)) => {
+                info!("URL already exists: {} -> {}", existing_code, req.url);
+                return HttpResponse::Ok().json(CreateLinkResponse {
+                    short_url: short_url(&base, &existing_code),
+                    code: existing_code,
+                    url: req.url.clone(),
+                    expires_at: None,
+                });
+            }
+            Ok(None) => {} // Continue to create new link
+            Err(err_response) => return err_response,
+        }
+    }
+
+    let link = match &req.code {
+        Some(alias) => {
+            let link = LinkRecord {
+                code: alias.clone(),
+                url: req.url.clone(),
+                created_at,
+                expires_at,
+            };
+            match case_clash(state.store.as_ref(), alias).await {
+                Ok(None) => {}
+                Ok(Some(existing)) => return alias_taken(alias, &existing).into(),
+                Err(e) => {
+                    error!("Error checking alias {}: {:?}", alias, e);
+                    return Problem::internal("Error creating link").into();
+                }
+            }
+            match state.store.insert_link(&link, &tags).await {
+                Ok(()) => link,
+                Err(StoreError::CodeTaken) => {
+                    return handle_alias_conflict(state.store.as_ref(), &link, &base).await;
+                }
+                Err(e) => {
+                    error!("Error inserting link: {:?}", e);
+                    return Problem::internal("Error creating link").into();
+                }
+            }
+        }
+        None => {
+            match insert_with_generated_code(&state, &req.url, created_at, expires_at, &tags).await
+            {
+                Ok(link) => link,
+                Err(err_response) => return err_response,
+            }
+        }
+    };
+
+    state.metrics.links_created("single", 1);
+    state
+        .events
+        .publish(LinkEventType::Created, &link.code, Some(&link.url));
+    info!(
+        "Created link: {} -> {} at {}",
+        link.code, link.url, link.created_at
+    );
+    HttpResponse::Ok().json(CreateLinkResponse {
+        short_url: short_url(&base, &link.code),
+        code: link.code,
+        url: link.url,
+        expires_at: link.expires_at,
+    })
+}
+
+/// API Handler: Create many short links at once
+///
+/// Requires the bearer token. Every link gets its own result (created, existing or invalid),
+/// in the order of the requests. The links are inserted in a single transaction; only
+/// generated codes that turn out to be taken are retried in another one.
+#[utoipa::path(
+    post,
+    path = "/api/v1/create/bulk",
+    tag = "links",
+    request_body = Vec<CreateLinkRequest>,
+    security(("bearer_token" = [])),
+    responses(
+        (status = 200, description = "One result per requested link", body = BulkCreateResponse),
+        (status = 400, description = "Too many links", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "Every generated code was reserved", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 429, description = "Rate limited", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn create_links_bulk(
+    state: web::Data<AppState>,
+    body: web::Json<Vec<CreateLinkRequest>>,
+    req: HttpRequest,
+) -> impl Responder {
+    if let Err(e) = check_bearer_token(&state, &req) {
+        return e.into();
+    }
+
+    let base = state.public_url.base_for(&req);
+    let requests = body.into_inner();
+    let max_links = state
+        .config
+        .base
+        .max_bulk_links
+        .unwrap_or(DEFAULT_MAX_BULK_LINKS);
+    if requests.len() > max_links {
+        return Problem::invalid_request(format!(
+            "At most {max_links} links can be created at once"
+        ))
+        .into();
+    }
+    let rate_limit = rate_limit::limit_bulk_create(&state, &req, requests.len());
+    if let Some(response) = rate_limit
+        .as_ref()
+        .and_then(|decision| decision.rejection())
+    {
+        return response;
+    }
+
+    let created_at = unix_now();
+    let mut results: Vec<Option<BulkCreateResult>> = requests.iter().map(|_| None).collect();
+    // Normalized tags of every request
+    let mut tags: Vec<Vec<String>> = requests.iter().map(|_| Vec::new()).collect();
+    // (request index, link, whether the code is generated)
+    let mut to_insert: Vec<(usize, LinkRecord, bool)> = Vec::new();
+    // Permanent links without alias, which reuse the code of an identical URL
+    let mut shared: Vec<(usize, LinkRecord)> = Vec::new();
+
+    for (i, req) in requests.iter().enumerate() {
+        let expires_at = match validate_create_request(&state, req, created_at) {
+            Ok((expires_at, link_tags)) => {
+                tags[i] = link_tags;
+                expires_at
+            }
+            Err(e) => {
+                results[i] = Some(BulkCreateResult::Invalid {
+                    code: e.code,
+                    message: e.detail,
+                });
+                continue;
+            }
+        };
+        let link = LinkRecord {
+            code: req.code.clone().unwrap_or_default(),
+            url: req.url.clone(),
+            created_at,
+            expires_at,
+        };
+        match (&req.code, expires_at) {
+            (None, None) if tags[i].is_empty() => shared.push((i, link)),
+            (code, _) => to_insert.push((i, link, code.is_none())),
+        }
+    }
+
+    // Aliases clashing in case with an existing code, or with another alias of the request
+    let aliases: Vec<String> = to_insert
+        .iter()
+        .filter(|(_, _, generated)| !generated)
+        .map(|(_, link, _)| link.code.clone())
+        .collect();
+    let mut codes_by_lowercase: HashMap<String, Vec<String>> = HashMap::new();
+    match state.store.find_codes_ignore_case(&aliases).await {
+        Ok(found) => {
+            for code in found {
+                codes_by_lowercase
+                    .entry(code.to_lowercase())
+                    .or_default()
+                    .push(code);
+            }
+        }
+        Err(e) => {
+            error!("Error checking aliases: {:?}", e);
+            return Problem::internal("Error creating links").into();
+        }
+    }
+    for (i, link, generated) in std::mem::take(&mut to_insert) {
+        if !generated {
+            let codes = codes_by_lowercase
+                .entry(link.code.to_lowercase())
+                .or_default();
+            if let Some(existing) = codes.iter().find(|code| **code != link.code) {
+                let problem = alias_taken(&link.code, existing);
+                results[i] = Some(BulkCreateResult::Invalid {
+                    code: problem.code,
+                    message: problem.detail,
+                });
+                continue;
+            }
+            codes.push(link.code.clone());
+        }
+        to_insert.push((i, link, generated));
+    }
+
+    let urls: Vec<String> = shared.iter().map(|(_, link)| link.url.clone()).collect();
+    let existing: HashMap<String, String> = match state.store.find_codes_by_urls(&urls).await {
+        Ok(found) => found.into_iter().collect(),
+        Err(e) => {
+            error!("Error checking for existing URLs: {:?}", e);
+            return Problem::internal("Error creating links").into();
+        }
+    };
+
+    // A URL requested several times is shortened once, for its first occurrence
+    let mut first_of_url: HashMap<String, usize> = HashMap::new();
+    let mut duplicates: Vec<(usize, usize)> = Vec::new();
+    for (i, link) in shared {
+        if let Some(code) = existing.get(&link.url) {
+            results[i] = Some(BulkCreateResult::Existing(CreateLinkResponse {
+                short_url: short_url(&base, code),
+                code: code.clone(),
+                url: link.url,
+                expires_at: None,
+            }));
+        } else if let Some(&first) = first_of_url.get(&link.url) {
+            duplicates.push((i, first));
+        } else {
+            first_of_url.insert(link.url.clone(), i);
+            to_insert.push((i, link, true));
+        }
+    }
+
+    let max_attempts = state.config.base.max_code_attempts.unwrap_or(5).max(1);
+    let mut alias_conflicts: Vec<(usize, LinkRecord)> = Vec::new();
+    let mut attempt = 0;
+    while !to_insert.is_empty() {
+        attempt += 1;
+        for (_, link, generated) in &mut to_insert {
+            if *generated {
+                link.code = match state.generator.generate().await {
+                    Ok(code) => code,
+                    Err(e) => {
+                        error!("Error generating code: {:?}", e);
+                        return generation_error(&e, "Error creating links").into();
+                    }
+                };
+            }
+        }
+
+        let links: Vec<(LinkRecord, Vec<String>)> = to_insert
+            .iter()
+            .map(|(i, link, _)| (link.clone(), tags[*i].clone()))
+            .collect();
+        let inserted = match state.store.insert_links(&links).await {
+            Ok(inserted) => inserted,
+            Err(e) => {
+                error!("Error inserting links: {:?}", e);
+                return Problem::internal("Error creating links").into();
+            }
+        };
+
+        let mut retry = Vec::new();
+        for ((i, link, generated), inserted) in to_insert.into_iter().zip(inserted) {
+            if generated {
+                state.generator.record_attempt(!inserted);
+                state.code_metrics.record(!inserted);
+            }
+
+            if inserted {
+                state
+                    .events
+                    .publish(LinkEventType::Created, &link.code, Some(&link.url));
+                results[i] = Some(BulkCreateResult::Created(CreateLinkResponse {
+                    short_url: short_url(&base, &link.code),
+                    code: link.code,
+                    url: link.url,
+                    expires_at: link.expires_at,
+                }));
+            } else if !generated {
+                alias_conflicts.push((i, link));
+            } else if attempt < max_attempts {
+                warn!("Generated code {} is already taken", link.code);
+                retry.push((i, link, true));
+            } else {
+                error!("No free code found after {} attempts", max_attempts);
+                results[i] = Some(BulkCreateResult::Invalid {
+                    code: ErrorCode::CodesExhausted,
+                    message: "No free code found".to_string(),
+                });
+            }
+        }
+        to_insert = retry;
+    }
+
+    for (i, link) in alias_conflicts {
+        results[i] = Some(
+            match existing_alias(state.store.as_ref(), &link, &base).await {
+                Ok(Some(existing)) => BulkCreateResult::Existing(existing),
+                Ok(None) => BulkCreateResult::Invalid {
+                    code: ErrorCode::AliasTaken,
+                    message: format!("Alias {} is already taken", link.code),
+                },
+                Err(e) => {
+                    error!("Error fetching existing link after alias conflict: {:?}", e);
+                    return Problem::internal("Error creating links").into();
+                }
+            },
+        );
+    }
+
+    for (i, first) in duplicates {
+        results[i] = match &results[first] {
+            Some(BulkCreateResult::Created(link)) => Some(BulkCreateResult::Existing(link.clone())),
+            _ => Some(BulkCreateResult::Invalid {
+                code: ErrorCode::CodesExhausted,
+                message: "No free code found".to_string(),
+            }),
+        };
+    }
+
+    let results: Vec<BulkCreateResult> = results
+        .into_iter()
+        .map(|result| result.expect("every requested link has a result"))
+        .collect();
+    let created = results
+        .iter()
+        .filter(|result| matches!(result, BulkCreateResult::Created(_)))
+        .count();
+    state.metrics.links_created("bulk", created);
+    info!("Bulk created {} of {} links", created, results.len());
+    let mut response = HttpResponse::Ok().json(BulkCreateResponse { results });
+    if let Some(decision) = rate_limit {
+        decision.add_headers(response.headers_mut());
+    }
+    response
+}
+
+/// Inserts a new link under a generated code, generating another one whenever the code
+/// is already taken, up to `max_code_attempts` times.
+async fn insert_with_generated_code(
+    state: &AppState,
+    url: &str,
+    created_at: i64,
+    expires_at: Option<i64>,
+    tags: &[String],
+) -> Result<LinkRecord, HttpResponse> {
+    let max_attempts = state.config.base.max_code_attempts.unwrap_or(5).max(1);
+
+    for _ in 0..max_attempts {
+        let code = state.generator.generate().await.map_err(|e| {
+            error!("Error generating code: {:?}", e);
+            HttpResponse::from(generation_error(&e, "Error creating link"))
+        })?;
+        let link = LinkRecord {
+            code,
+            url: url.to_string(),
+            created_at,
+            expires_at,
+        };
+
+        let collided = match state.store.insert_link(&link, tags).await {
+            Ok(()) => false,
+            Err(StoreError::CodeTaken) => true,
+            Err(e) => {
+                error!("Error inserting link: {:?}", e);
+                return Err(Problem::internal("Error creating link").into());
+            }
+        };
+        state.generator.record_attempt(collided);
+        state.code_metrics.record(collided);
+
+        if !collided {
+            return Ok(link);
+        }
+        warn!(
+            "Generated code {} is already taken (collision rate {:.2}%)",
+            link.code,
+            state.code_metrics.rate() * 100.0
+        );
+    }
+
+    error!("No free code found after {} attempts", max_attempts);
+    Err(no_free_code().into())
+}
+
+fn no_free_code() -> Problem {
+    Problem::new(
+        StatusCode::SERVICE_UNAVAILABLE,
+        ErrorCode::CodesExhausted,
+        "No free code found",
+    )
+}
+
+/// Problem reported when no code could be generated, 503 when every generated code was
+/// reserved
+fn generation_error(e: &StoreError, detail: &str) -> Problem {
+    match e {
+        StoreError::CodesExhausted(_) => no_free_code(),
+        _ => Problem::internal(detail),
+    }
+}
+
+/// API Handler: Change the destination, expiration or tags of an existing link
+///
+/// Requires the bearer token. Previous destinations and expirations are kept in the edit
+/// history until the link is deleted.
+#[utoipa::path(
+    patch,
+    path = "/api/v1/links/{code}",
+    tag = "links",
+    params(("code" = String, Path, description = "Short code of the link")),
+    request_body = UpdateLinkRequest,
+    security(("bearer_token" = [])),
+    responses(
+        (status = 200, description = "The updated link", body = InfoResponse),
+        (status = 400, description = "Invalid URL, expiration or tags", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown code", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn update_link(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    body: web::Json<UpdateLinkRequest>,
+    req: HttpRequest,
+) -> impl Responder {
+    if let Err(e) = check_bearer_token(&state, &req) {
+        return e.into();
+    }
+
+    let code = path.into_inner();
+    let update = body.into_inner();
+
+    if let Some(Err(e)) = update.url.as_deref().map(validate_url) {
+        return e.into();
+    }
+
+    let expires_at = match (update.expires_at, update.ttl) {
+        (Some(None), None) => Some(None),
+        (expires_at, ttl) => match resolve_expiration(expires_at.flatten(), ttl, unix_now()) {
+            Ok(Some(expires_at)) => Some(Some(expires_at)),
+            Ok(None) => None,
+            Err(e) => return e.into(),
+        },
+    };
+
+    let tags = match update.tags.as_deref().map(normalize_tags).transpose() {
+        Ok(tags) => tags,
+        Err(e) => return e.into(),
+    };
+    let (add_tags, remove_tags) = match (
+        normalize_tags(&update.add_tags),
+        normalize_tags(&update.remove_tags),
+    ) {
+        (Ok(add_tags), Ok(remove_tags)) => (add_tags, remove_tags),
+        (Err(e), _) | (_, Err(e)) => return e.into(),
+    };
+
+    let changes = LinkChanges {
+        url: update.url,
+        expires_at,
+        tags,
+        add_tags,
+        remove_tags,
+    };
+    if !changes.edits_link() && !changes.edits_tags() {
+        return Problem::invalid_request("Nothing to update").into();
+    }
+
+    match state
+        .store
+        .update_link(&code, &changes, TOKEN_ACTOR, unix_now())
+        .await
+    {
+        Ok(Some(record)) => {
+            info!("Updated link {}: {:?}", code, changes);
+            state
+                .events
+                .publish(LinkEventType::Updated, &code, Some(&record.url));
+            let tags = match tags_of(state.store.as_ref(), &code).await {
+                Ok(tags) => tags,
+                Err(e) => {
+                    error!("Error fetching tags of link {}: {:?}", code, e);
+                    return Problem::internal("Error fetching link").into();
+                }
+            };
+            HttpResponse::Ok().json(link_info(record, tags, &state.public_url.base_for(&req)))
+        }
+        Ok(None) => Problem::link_not_found().into(),
+        Err(e) => {
+            error!("Error updating link {}: {:?}", code, e);
+            Problem::internal("Error updating link").into()
+        }
+    }
+}
+
+/// API Handler: Get link info (given a code)
+#[utoipa::path(
+    get,
+    path = "/api/v1/info/{code}",
+    tag = "links",
+    params(("code" = String, Path, description = "Short code of the link")),
+    responses(
+        (status = 200, description = "The link", body = InfoResponse),
+        (status = 404, description = "Unknown code", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 429, description = "Rate limited", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn get_link_info(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> impl Responder {
+    let code = path.into_inner();
+
+    let record = match state.store.get_link(&code).await {
+        Ok(Some(record)) => record,
+        Ok(None) => return Problem::link_not_found().into(),
+        Err(e) => {
+            error!("Error fetching info for code {}: {:?}", code, e);
+            return Problem::internal("Error fetching link").into();
+        }
+    };
+    match tags_of(state.store.as_ref(), &code).await {
+        Ok(tags) => {
+            HttpResponse::Ok().json(link_info(record, tags, &state.public_url.base_for(&req)))
+        }
+        Err(e) => {
+            error!("Error fetching tags of link {}: {:?}", code, e);
+            Problem::internal("Error fetching link").into()
+        }
+    }
+}
+
+/// API Handler: List the links matching some filters, one page at a time
+///
+/// Requires the bearer token. Links are ordered by creation time and each page carries the
+/// cursor of the next one.
+#[utoipa::path(
+    get,
+    path = "/api/v1/links",
+    tag = "links",
+    params(ListLinksQuery),
+    security(("bearer_token" = [])),
+    responses(
+        (status = 200, description = "One page of links", body = ListLinksResponse),
+        (status = 400, description = "Invalid cursor or parameters", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 429, description = "Rate limited", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn list_links(
+    state: web::Data<AppState>,
+    query: web::Query<ListLinksQuery>,
+    req: HttpRequest,
+) -> impl Responder {
+    if let Err(e) = check_bearer_token(&state, &req) {
+        return e.into();
+    }
+
+    let query = query.into_inner();
+    let after = match &query.cursor {
+        Some(cursor) => match decode_cursor(cursor) {
+            Some(after) => Some(after),
+            None => return Problem::invalid_request("Invalid cursor").into(),
+        },
+        None => None,
+    };
+
+    let tag = match query.tag.as_deref().map(normalize_tag).transpose() {
+        Ok(tag) => tag,
+        Err(e) => return e.into(),
+    };
+
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let filter = LinkFilter {
+        codes: None,
+        host: query.host,
+        url_contains: query.contains,
+        created_after: query.created_after,
+        created_before: query.created_before,
+        tag,
+    };
+    // One extra link tells whether there is a next page
+    let page = PageRequest {
+        after,
+        limit: limit + 1,
+        descending: query.order.unwrap_or_default() == SortOrder::Desc,
+    };
+
+    let mut links = match state.store.list_links(&filter, &page).await {
+        Ok(links) => links,
+        Err(e) => {
+            error!("Error listing links: {:?}", e);
+            return Problem::internal("Error listing links").into();
+        }
+    };
+    let next_cursor = if links.len() > limit as usize {
+        links.truncate(limit as usize);
+        links.last().map(encode_cursor)
+    } else {
+        None
+    };
+
+    let codes: Vec<String> = links.iter().map(|link| link.code.clone()).collect();
+    let mut tags = match tags_by_code(state.store.as_ref(), &codes).await {
+        Ok(tags) => tags,
+        Err(e) => {
+            error!("Error fetching tags of listed links: {:?}", e);
+            return Problem::internal("Error listing links").into();
+        }
+    };
+    let base = state.public_url.base_for(&req);
+    HttpResponse::Ok().json(ListLinksResponse {
+        links: links
+            .into_iter()
+            .map(|link| {
+                let link_tags = tags.remove(&link.code).unwrap_or_default();
+                link_info(link, link_tags, &base)
+            })
+            .collect(),
+        next_cursor,
+    })
+}
+
+/// API Handler: Get click statistics of a link over a time range
+///
+/// Requires the bearer token.
+#[utoipa::path(
+    get,
+    path = "/api/v1/stats/{code}",
+    tag = "stats",
+    params(("code" = String, Path, description = "Short code of the link"), StatsQuery),
+    security(("bearer_token" = [])),
+    responses(
+        (status = 200, description = "Click statistics of the link", body = StatsResponse),
+        (status = 400, description = "Invalid range", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown code", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 429, description = "Rate limited", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn get_link_stats(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    query: web::Query<StatsQuery>,
+    req: HttpRequest,
+) -> impl Responder {
+    if let Err(e) = check_bearer_token(&state, &req) {
+        return e.into();
+    }
+
+    let code = path.into_inner();
+    let (from, to, bucket) = match stats_range(&query) {
+        Ok(range) => range,
+        Err(e) => return e.into(),
+    };
+
+    match state.store.get_link(&code).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Problem::link_not_found().into(),
+        Err(e) => {
+            error!("Error fetching link {}: {:?}", code, e);
+            return Problem::internal("Error fetching stats").into();
+        }
+    }
+
+    let filter = LinkFilter {
+        codes: Some(vec![code.clone()]),
+        ..LinkFilter::default()
+    };
+    match state
+        .store
+        .click_stats(&filter, from, to, stats::bucketing(bucket))
+        .await
+    {
+        Ok(click_stats) => {
+            HttpResponse::Ok().json(stats::build_response(code, from, to, bucket, click_stats))
+        }
+        Err(e) => {
+            error!("Error fetching stats for code {}: {:?}", code, e);
+            Problem::internal("Error fetching stats").into()
+        }
+    }
+}
+
+/// Resolve the range and bucket width of a stats query, defaulting to the last 30 days by day.
+fn stats_range(query: &StatsQuery) -> Result<(i64, i64, StatsBucket), Problem> {
+    let bucket = query.bucket.unwrap_or_default();
+    let to = query.to.unwrap_or_else(unix_now);
+    let from = match query.from {
+        Some(from) => from,
+        None => to
+            .checked_sub(stats::DEFAULT_RANGE)
+            .ok_or_else(|| Problem::invalid_request("to is out of range"))?,
+    };
+
+    stats::check_range(from, to, bucket).map_err(Problem::invalid_request)?;
+    Ok((from, to, bucket))
+}
+
+/// API Handler: List every tag in use with its number of links
+#[utoipa::path(
+    get,
+    path = "/api/v1/tags",
+    tag = "links",
+    security(("bearer_token" = [])),
+    responses(
+        (status = 200, description = "The tags in use", body = TagsResponse),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 429, description = "Rate limited", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn list_tags(state: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    if let Err(e) = check_bearer_token(&state, &req) {
+        return e.into();
+    }
+
+    match state.store.tag_counts().await {
+        Ok(counts) => HttpResponse::Ok().json(TagsResponse {
+            tags: counts
+                .into_iter()
+                .map(|(tag, links)| TagCount {
+                    tag,
+                    links: links as u64,
+                })
+                .collect(),
+        }),
+        Err(e) => {
+            error!("Error counting tags: {:?}", e);
+            Problem::internal("Error listing tags").into()
+        }
+    }
+}
+
+/// API Handler: Get the combined click statistics of every link with a tag over a time range
+///
+/// Requires the bearer token. A tag without links has no clicks.
+#[utoipa::path(
+    get,
+    path = "/api/v1/tags/{tag}/stats",
+    tag = "stats",
+    params(("tag" = String, Path, description = "The tag of the links"), StatsQuery),
+    security(("bearer_token" = [])),
+    responses(
+        (status = 200, description = "Click statistics of the links with the tag", body = StatsResponse),
+        (status = 400, description = "Invalid tag or range", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 429, description = "Rate limited", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn get_tag_stats(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    query: web::Query<StatsQuery>,
+    req: HttpRequest,
+) -> impl Responder {
+    if let Err(e) = check_bearer_token(&state, &req) {
+        return e.into();
+    }
+
+    let (tag, (from, to, bucket)) = match (normalize_tag(&path), stats_range(&query)) {
+        (Ok(tag), Ok(range)) => (tag, range),
+        (Err(e), _) | (_, Err(e)) => return e.into(),
+    };
+
+    let filter = LinkFilter {
+        tag: Some(tag.clone()),
+        ..LinkFilter::default()
+    };
+    match state
+        .store
+        .click_stats(&filter, from, to, stats::bucketing(bucket))
+        .await
+    {
+        Ok(click_stats) => {
+            let mut response = stats::build_response(String::new(), from, to, bucket, click_stats);
+            response.tag = Some(tag);
+            HttpResponse::Ok().json(response)
+        }
+        Err(e) => {
+            error!("Error fetching stats for tag {}: {:?}", tag, e);
+            Problem::internal("Error fetching stats").into()
+        }
+    }
+}
+
+/// API Handler: Stream link events as Server-Sent Events
+///
+/// Requires the bearer token. Every redirect and link lifecycle event published after the
+/// connection is sent as an event named after its type, with the JSON of the event as data.
+#[utoipa::path(
+    get,
+    path = "/api/v1/events",
+    tag = "links",
+    params(EventsQuery),
+    security(("bearer_token" = [])),
+    responses(
+        (status = 200, description = "Stream of link events", body = LinkEvent, content_type = "text/event-stream"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn stream_events(
+    state: web::Data<AppState>,
+    query: web::Query<EventsQuery>,
+    req: HttpRequest,
+) -> impl Responder {
+    if let Err(e) = check_bearer_token(&state, &req) {
+        return e.into();
+    }
+
+    let receiver = state.events.subscribe();
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(events::sse_stream(receiver, query.into_inner().code))
+}
+
+/// API Handler: Get the API versions and capabilities of the server
+///
+/// Served outside of the versioned scopes so that clients can find a version they support.
+#[utoipa::path(
+    get,
+    path = "/api/version",
+    tag = "meta",
+    responses((status = 200, description = "Supported API versions and capabilities", body = VersionResponse))
+)]
+pub async fn get_version() -> impl Responder {
+    HttpResponse::Ok().json(VersionResponse {
+        server_version: env!("CARGO_PKG_VERSION").to_string(),
+        api_versions: API_VERSIONS.iter().map(|v| v.to_string()).collect(),
+        capabilities: CAPABILITIES.iter().map(|c| c.to_string()).collect(),
+    })
+}
+
+/// API Handler: Render the short URL of a link as a QR code
+#[utoipa::path(
+    get,
+    path = "/api/v1/qr/{code}",
+    tag = "links",
+    params(("code" = String, Path, description = "Short code of the link"), QrQuery),
+    responses(
+        (status = 200, description = "The QR code of the short URL", content(
+            (Vec<u8> = "image/png"),
+            (String = "image/svg+xml"),
+            (String = "text/plain"),
+        )),
+        (status = 400, description = "Invalid rendering options", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown code", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 410, description = "Expired link", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 429, description = "Rate limited", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn get_qr(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    query: web::Query<QrQuery>,
+    req: HttpRequest,
+) -> impl Responder {
+    let code = path.into_inner();
+    let options = match QrOptions::from_query(&query) {
+        Ok(options) => options,
+        Err(e) => return e.into(),
+    };
+
+    match state.store.get_link(&code).await {
+        Ok(Some(record)) if record.is_expired(unix_now()) => {
+            return Problem::new(StatusCode::GONE, ErrorCode::Expired, "Link expired").into();
+        }
+        Ok(Some(_)) => {}
+        Ok(None) => return Problem::link_not_found().into(),
+        Err(e) => {
+            error!("Error fetching link {}: {:?}", code, e);
+            return Problem::internal("Error rendering QR code").into();
+        }
+    }
+
+    match qr::render(
+        &short_url(&state.public_url.base_for(&req), &code),
+        &options,
+    ) {
+        Ok((content_type, image)) => HttpResponse::Ok()
+            .content_type(content_type)
+            .insert_header((header::CACHE_CONTROL, "public, max-age=3600"))
+            .body(image),
+        Err(e) => {
+            error!("Error rendering QR code of {}: {}", code, e);
+            Problem::internal("Error rendering QR code").into()
+        }
+    }
+}
+
+/// Handler for redirection: given a code, look up the original URL and redirect.
+///
+/// Expired links that were not purged yet answer with 410 Gone. Successful redirects are
+/// queued for click analytics.
+#[utoipa::path(
+    get,
+    path = "/{code}",
+    tag = "redirect",
+    params(("code" = String, Path, description = "Short code of the link")),
+    responses(
+        (status = 302, description = "Redirect to the original URL", headers(("Location" = String))),
+        (status = 404, description = "Unknown code", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 410, description = "Expired link", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 429, description = "Rate limited", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn redirect(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> impl Responder {
+    let code = path.into_inner();
+    let now = unix_now();
+
+    match state.store.get_link(&code).await {
+        Ok(Some(record)) if record.is_expired(now) => {
+            state.metrics.redirect("expired");
+            Problem::new(StatusCode::GONE, ErrorCode::Expired, "Link expired").into()
+        }
+        Ok(Some(record)) => {
+            state.metrics.redirect("hit");
+            let referrer = header_string(&req, header::REFERER);
+            state
+                .events
+                .publish_click(&record.code, &record.url, referrer.clone());
+            state.clicks.record(ClickRecord {
+                code: record.code,
+                clicked_at: now,
+                referrer,
+                user_agent: header_string(&req, header::USER_AGENT),
+                client_ip: state
+                    .rate_limiter
+                    .client_ip(&req)
+                    .map(|ip| anonymize_ip(ip).to_string()),
+            });
+            HttpResponse::Found()
+                .append_header(("Location", record.url))
+                .finish()
+        }
+        Ok(None) => {
+            state.metrics.redirect("miss");
+            Problem::link_not_found().into()
+        }
+        Err(e) => {
+            error!("Error fetching link: {:?}", e);
+            Problem::internal("Error fetching link").into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,463 @@
+//! Handler tests against the in-memory store.
+
+use actix_web::{
+    App, Error,
+    body::MessageBody,
+    dev::{ServiceFactory, ServiceRequest, ServiceResponse},
+    http::{StatusCode, header},
+    test, web,
+};
+use std::sync::Arc;
+use swiftlink_api::{
+    BulkCreateResponse, BulkCreateResult, CreateLinkRequest, CreateLinkResponse, ErrorCode,
+    InfoResponse, ListLinksResponse, ProblemDetails,
+};
+
+use super::{handle_alias_conflict, redirect};
+use crate::{
+    AppState, analytics,
+    codes::ReservedCodes,
+    config::Config,
+    events::EventBus,
+    generator::{self, CollisionMetrics},
+    metrics::Metrics,
+    problem,
+    public_url::PublicUrl,
+    rate_limit::RateLimiter,
+    storage::{LinkRecord, LinkStore, MemoryStore},
+    unix_now,
+};
+
+const TOKEN: &str = "test-token";
+
+fn test_config() -> Config {
+    let mut config = Config::default();
+    config.base.bearer_token = Some(TOKEN.to_string());
+    config
+}
+
+/// App serving the v1 API, its legacy aliases and redirects from `store`
+fn test_app(
+    config: Config,
+    store: Arc<dyn LinkStore>,
+) -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse<impl MessageBody>,
+        Error = Error,
+        InitError = (),
+    >,
+> {
+    let config = Arc::new(config);
+    let code_metrics = Arc::new(CollisionMetrics::default());
+    let metrics = Arc::new(Metrics::new(&code_metrics).unwrap());
+    let reserved_codes = Arc::new(ReservedCodes::from_config(&config.base));
+    let generator =
+        generator::from_config(&config.base, store.clone(), reserved_codes.clone()).unwrap();
+    let events = EventBus::new(None);
+    let clicks = analytics::spawn(store.clone(), &config.analytics, events.clone());
+
+    let state = web::Data::new(AppState {
+        store,
+        generator,
+        reserved_codes,
+        code_metrics,
+        metrics,
+        rate_limiter: Arc::new(RateLimiter::from_config(&config.rate_limit)),
+        public_url: PublicUrl::from_config(&config.base).unwrap(),
+        events,
+        clicks,
+        config,
+    });
+    App::new()
+        .app_data(state)
+        .app_data(web::JsonConfig::default().error_handler(|e, _| problem::extractor_error(e)))
+        .service(web::scope("/api/v1").configure(crate::api_v1))
+        .service(web::scope("/api").configure(crate::api_legacy))
+        .route("/{code}", web::get().to(redirect))
+}
+
+fn create_request(body: &CreateLinkRequest) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/api/v1/create")
+        .set_json(body)
+}
+
+fn bulk_request(body: &[CreateLinkRequest], token: Option<&str>) -> test::TestRequest {
+    let mut request = test::TestRequest::post()
+        .uri("/api/v1/create/bulk")
+        .set_json(body);
+    if let Some(token) = token {
+        request = request.insert_header((header::AUTHORIZATION, format!("Bearer {token}")));
+    }
+    request
+}
+
+#[actix_web::test]
+async fn create_link_then_get_info() {
+    let app = test::init_service(test_app(test_config(), Arc::new(MemoryStore::default()))).await;
+
+    let created: CreateLinkResponse = test::call_and_read_body_json(
+        &app,
+        create_request(&CreateLinkRequest::new("https://example.com/page")).to_request(),
+    )
+    .await;
+    assert_eq!(created.url, "https://example.com/page");
+    assert_eq!(created.code.len(), 6);
+    assert_eq!(created.expires_at, None);
+
+    let request = test::TestRequest::get()
+        .uri(&format!("/api/v1/info/{}", created.code))
+        .to_request();
+    let info: InfoResponse = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(info.code, created.code);
+    assert_eq!(info.url, "https://example.com/page");
+}
+
+#[actix_web::test]
+async fn create_link_reuses_code_of_same_url() {
+    let app = test::init_service(test_app(test_config(), Arc::new(MemoryStore::default()))).await;
+    let request = CreateLinkRequest::new("https://example.com/");
+
+    let first: CreateLinkResponse =
+        test::call_and_read_body_json(&app, create_request(&request).to_request()).await;
+    let second: CreateLinkResponse =
+        test::call_and_read_body_json(&app, create_request(&request).to_request()).await;
+    assert_eq!(first.code, second.code);
+}
+
+#[actix_web::test]
+async fn create_link_rejects_invalid_requests() {
+    let app = test::init_service(test_app(test_config(), Arc::new(MemoryStore::default()))).await;
+
+    let response = test::call_service(
+        &app,
+        create_request(&CreateLinkRequest::new("nope")).to_request(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let problem: ProblemDetails = test::read_body_json(response).await;
+    assert!(matches!(problem.code, ErrorCode::InvalidUrl));
+
+    let aliased = CreateLinkRequest {
+        code: Some("promo".to_string()),
+        ..CreateLinkRequest::new("https://example.com/a")
+    };
+    let response = test::call_service(&app, create_request(&aliased).to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let taken = CreateLinkRequest {
+        code: Some("promo".to_string()),
+        ..CreateLinkRequest::new("https://example.com/b")
+    };
+    let response = test::call_service(&app, create_request(&taken).to_request()).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+}
+
+#[actix_web::test]
+async fn redirect_to_original_url() {
+    let app = test::init_service(test_app(test_config(), Arc::new(MemoryStore::default()))).await;
+    let created: CreateLinkResponse = test::call_and_read_body_json(
+        &app,
+        create_request(&CreateLinkRequest::new("https://example.com/target")).to_request(),
+    )
+    .await;
+
+    let request = test::TestRequest::get()
+        .uri(&format!("/{}", created.code))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::FOUND);
+    assert_eq!(
+        response.headers().get(header::LOCATION).unwrap(),
+        "https://example.com/target"
+    );
+
+    let request = test::TestRequest::get().uri("/unknown").to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn expired_link_is_gone() {
+    let store = Arc::new(MemoryStore::default());
+    let now = unix_now();
+    store
+        .insert_link(
+            &LinkRecord {
+                code: "old".to_string(),
+                url: "https://example.com/old".to_string(),
+                created_at: now - 100,
+                expires_at: Some(now - 10),
+            },
+            &[],
+        )
+        .await
+        .unwrap();
+    let app = test::init_service(test_app(test_config(), store)).await;
+
+    let request = test::TestRequest::get().uri("/old").to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::GONE);
+    let problem: ProblemDetails = test::read_body_json(response).await;
+    assert!(matches!(problem.code, ErrorCode::Expired));
+}
+
+#[actix_web::test]
+async fn create_link_with_ttl_expires() {
+    let app = test::init_service(test_app(test_config(), Arc::new(MemoryStore::default()))).await;
+    let request = CreateLinkRequest {
+        ttl: Some(3600),
+        ..CreateLinkRequest::new("https://example.com/soon")
+    };
+
+    let before = unix_now();
+    let created: CreateLinkResponse =
+        test::call_and_read_body_json(&app, create_request(&request).to_request()).await;
+    let expires_at = created.expires_at.expect("link with a ttl expires");
+    assert!((before + 3600..=unix_now() + 3600).contains(&expires_at));
+}
+
+#[actix_web::test]
+async fn list_links_by_tag() {
+    let app = test::init_service(test_app(test_config(), Arc::new(MemoryStore::default()))).await;
+    for (url, tags) in [
+        ("https://example.com/1", vec!["Spring"]),
+        ("https://example.com/2", vec!["spring", "mail"]),
+        ("https://example.com/3", vec![]),
+    ] {
+        let request = CreateLinkRequest {
+            tags: tags.into_iter().map(str::to_string).collect(),
+            ..CreateLinkRequest::new(url)
+        };
+        let response = test::call_service(&app, create_request(&request).to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    let request = test::TestRequest::get().uri("/api/v1/links").to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let request = test::TestRequest::get()
+        .uri("/api/v1/links?tag=spring")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {TOKEN}")))
+        .to_request();
+    let page: ListLinksResponse = test::call_and_read_body_json(&app, request).await;
+    let mut urls: Vec<&str> = page.links.iter().map(|link| link.url.as_str()).collect();
+    urls.sort();
+    assert_eq!(urls, ["https://example.com/1", "https://example.com/2"]);
+    assert!(page.next_cursor.is_none());
+}
+
+#[actix_web::test]
+async fn bulk_create_reports_every_link() {
+    let app = test::init_service(test_app(test_config(), Arc::new(MemoryStore::default()))).await;
+    let requests = vec![
+        CreateLinkRequest::new("https://example.com/x"),
+        CreateLinkRequest::new("not a url"),
+        CreateLinkRequest::new("https://example.com/x"),
+        CreateLinkRequest {
+            code: Some("bulk-alias".to_string()),
+            ..CreateLinkRequest::new("https://example.com/y")
+        },
+    ];
+
+    let response = test::call_service(&app, bulk_request(&requests, None).to_request()).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response: BulkCreateResponse =
+        test::call_and_read_body_json(&app, bulk_request(&requests, Some(TOKEN)).to_request())
+            .await;
+    let [first, invalid, duplicate, aliased] = &response.results[..] else {
+        panic!("expected 4 results, got {:?}", response.results);
+    };
+    let BulkCreateResult::Created(first) = first else {
+        panic!("expected a created link, got {first:?}");
+    };
+    assert!(matches!(
+        invalid,
+        BulkCreateResult::Invalid {
+            code: ErrorCode::InvalidUrl,
+            ..
+        }
+    ));
+    assert!(matches!(duplicate, BulkCreateResult::Existing(link) if link.code == first.code));
+    assert!(matches!(aliased, BulkCreateResult::Created(link) if link.code == "bulk-alias"));
+}
+
+#[actix_web::test]
+async fn bulk_create_rejects_too_many_links() {
+    let mut config = test_config();
+    config.base.max_bulk_links = Some(2);
+    let app = test::init_service(test_app(config, Arc::new(MemoryStore::default()))).await;
+    let requests: Vec<CreateLinkRequest> = (0..3)
+        .map(|i| CreateLinkRequest::new(format!("https://example.com/{i}")))
+        .collect();
+
+    let response =
+        test::call_service(&app, bulk_request(&requests, Some(TOKEN)).to_request()).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn taken_alias_conflicts_unless_same_url() {
+    let store = MemoryStore::default();
+    let promo = LinkRecord {
+        code: "promo".into(),
+        url: "https://example.com/a".into(),
+        created_at: 0,
+        expires_at: None,
+    };
+    store.insert_link(&promo, &[]).await.unwrap();
+
+    let response = handle_alias_conflict(&store, &promo, "https://sho.rt/").await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let other = LinkRecord {
+        url: "https://example.com/b".into(),
+        ..promo
+    };
+    let response = handle_alias_conflict(&store, &other, "https://sho.rt/").await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let problem: ProblemDetails =
+        serde_json::from_slice(&response.into_body().try_into_bytes().unwrap()).unwrap();
+    assert!(matches!(problem.code, ErrorCode::AliasTaken));
+}
+
+#[actix_web::test]
+async fn aliases_clash_with_codes_in_another_case() {
+    let store = Arc::new(MemoryStore::default());
+    let app = test::init_service(test_app(test_config(), store.clone())).await;
+    let promo = |code: &str| CreateLinkRequest {
+        code: Some(code.to_string()),
+        ..CreateLinkRequest::new("https://example.com/promo")
+    };
+
+    let response = test::call_service(&app, create_request(&promo("Promo")).to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    // Even for the same URL, since the code differs
+    let response = test::call_service(&app, create_request(&promo("promo")).to_request()).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let problem: ProblemDetails = test::read_body_json(response).await;
+    assert!(matches!(problem.code, ErrorCode::AliasTaken));
+
+    let requests = [promo("PROMO"), promo("Sale"), promo("sale"), promo("Promo")];
+    let response: BulkCreateResponse =
+        test::call_and_read_body_json(&app, bulk_request(&requests, Some(TOKEN)).to_request())
+            .await;
+    assert!(matches!(
+        response.results[0],
+        BulkCreateResult::Invalid {
+            code: ErrorCode::AliasTaken,
+            ..
+        }
+    ));
+    assert!(matches!(response.results[1], BulkCreateResult::Created(_)));
+    assert!(matches!(
+        response.results[2],
+        BulkCreateResult::Invalid {
+            code: ErrorCode::AliasTaken,
+            ..
+        }
+    ));
+    assert!(matches!(response.results[3], BulkCreateResult::Existing(_)));
+    assert!(store.get_link("sale").await.unwrap().is_none());
+}
+
+#[actix_web::test]
+async fn only_reserved_codes_exhaust_generation() {
+    let mut config = test_config();
+    config.base.code_alphabet = Some("abc".to_string());
+    config.base.code_size = Some(1);
+    config.base.reserved_codes = Some(vec!["a".into(), "b".into(), "c".into()]);
+    let app = test::init_service(test_app(config, Arc::new(MemoryStore::default()))).await;
+
+    let request = CreateLinkRequest::new("https://example.com/");
+    let response = test::call_service(&app, create_request(&request).to_request()).await;
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let problem: ProblemDetails = test::read_body_json(response).await;
+    assert!(matches!(problem.code, ErrorCode::CodesExhausted));
+
+    let response =
+        test::call_service(&app, bulk_request(&[request], Some(TOKEN)).to_request()).await;
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[actix_web::test]
+async fn legacy_paths_remain_aliases_of_v1() {
+    let app = test::init_service(test_app(test_config(), Arc::new(MemoryStore::default()))).await;
+    let created: CreateLinkResponse = test::call_and_read_body_json(
+        &app,
+        test::TestRequest::post()
+            .uri("/api/create")
+            .set_json(CreateLinkRequest::new("https://example.com/"))
+            .to_request(),
+    )
+    .await;
+
+    let code = &created.code;
+    let authorized = |request: test::TestRequest| {
+        request
+            .insert_header((header::AUTHORIZATION, format!("Bearer {TOKEN}")))
+            .to_request()
+    };
+    for request in [
+        test::TestRequest::get().uri(&format!("/api/info/{code}")),
+        test::TestRequest::get().uri(&format!("/api/stats/{code}")),
+        test::TestRequest::get().uri("/api/links"),
+        test::TestRequest::get().uri("/api/openapi.json"),
+        test::TestRequest::post()
+            .uri("/api/create/bulk")
+            .set_json([CreateLinkRequest::new("https://example.com/bulk")]),
+        test::TestRequest::patch()
+            .uri(&format!("/api/links/{code}"))
+            .set_json(serde_json::json!({ "url": "https://example.com/new" })),
+        test::TestRequest::post()
+            .uri("/api/delete/bulk")
+            .set_json(serde_json::json!({ "codes": ["missing"] })),
+        test::TestRequest::delete().uri(&format!("/api/links/{code}")),
+    ] {
+        let request = authorized(request);
+        let path = request.path().to_string();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK, "{path}");
+    }
+}
+
+#[actix_web::test]
+async fn metrics_require_the_bearer_token() {
+    // Below a path that `/{code}`, registered first by `test_app`, does not match
+    let metrics_app = |config| {
+        test_app(config, Arc::new(MemoryStore::default())).route(
+            "/internal/metrics",
+            web::get().to(crate::metrics::metrics_endpoint),
+        )
+    };
+    let scrape = |token: Option<&str>| {
+        let mut request = test::TestRequest::get().uri("/internal/metrics");
+        if let Some(token) = token {
+            request = request.insert_header((header::AUTHORIZATION, format!("Bearer {token}")));
+        }
+        request.to_request()
+    };
+
+    let app = test::init_service(metrics_app(test_config())).await;
+    let response = test::call_service(&app, scrape(None)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = test::call_service(&app, scrape(Some("wrong"))).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = test::call_service(&app, scrape(Some(TOKEN))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = test::read_body(response).await;
+    assert!(
+        std::str::from_utf8(&body)
+            .unwrap()
+            .contains("swiftlink_code_attempts_total")
+    );
+
+    let mut config = test_config();
+    config.metrics.require_token = false;
+    let app = test::init_service(metrics_app(config)).await;
+    let response = test::call_service(&app, scrape(None)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
@@ -0,0 +1,175 @@
+//! Liveness and readiness probes for orchestrators and load balancers.
+
+use actix_web::{HttpResponse, Responder, rt, web};
+use log::warn;
+use std::time::Duration;
+use swiftlink_api::{HealthCheck, HealthResponse, HealthStatus};
+
+use crate::{AppState, config::HealthConfig, storage::LinkStore};
+
+/// Share of the pool in use above which the server is not ready, when not configured
+const DEFAULT_MAX_POOL_SATURATION: f64 = 0.9;
+
+/// Milliseconds the database has to answer the readiness probe, when not configured
+const DEFAULT_TIMEOUT_MS: u64 = 2000;
+
+fn check(name: &str, ok: bool, detail: String) -> HealthCheck {
+    HealthCheck {
+        name: name.to_string(),
+        status: if ok {
+            HealthStatus::Ok
+        } else {
+            HealthStatus::Unavailable
+        },
+        detail: Some(detail),
+    }
+}
+
+/// Liveness probe: answers as long as the process serves requests
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "meta",
+    responses((status = 200, description = "The server is alive", body = HealthResponse))
+)]
+pub async fn healthz() -> impl Responder {
+    HttpResponse::Ok().json(HealthResponse {
+        status: HealthStatus::Ok,
+        checks: Vec::new(),
+    })
+}
+
+/// Readiness probe: checks that the database answers, that every migration is applied and
+/// that the connection pool is not saturated
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "meta",
+    responses(
+        (status = 200, description = "The server is ready", body = HealthResponse),
+        (status = 503, description = "A check failed", body = HealthResponse),
+    )
+)]
+pub async fn readyz(state: web::Data<AppState>) -> impl Responder {
+    let checks = readiness_checks(state.store.as_ref(), &state.config.health).await;
+    let ready = checks.iter().all(|c| c.status == HealthStatus::Ok);
+    let body = HealthResponse {
+        status: if ready {
+            HealthStatus::Ok
+        } else {
+            HealthStatus::Unavailable
+        },
+        checks,
+    };
+    if ready {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+}
+
+/// Check the database, its migrations and connection pool. Database errors are only logged,
+/// since they may reveal host names or credentials to unauthenticated clients.
+async fn readiness_checks(store: &dyn LinkStore, config: &HealthConfig) -> Vec<HealthCheck> {
+    let max_saturation = config
+        .max_pool_saturation
+        .unwrap_or(DEFAULT_MAX_POOL_SATURATION);
+    let timeout = Duration::from_millis(config.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS));
+
+    let mut checks = Vec::new();
+    match rt::time::timeout(timeout, store.health()).await {
+        Ok(Ok(health)) => {
+            checks.push(check("database", true, "Reachable".to_string()));
+            checks.push(if health.pending_migrations.is_empty() {
+                check("migrations", true, "Up to date".to_string())
+            } else {
+                check(
+                    "migrations",
+                    false,
+                    format!("Pending migrations: {:?}", health.pending_migrations),
+                )
+            });
+            if let Some(pool) = health.pool {
+                let saturation = pool.saturation();
+                checks.push(check(
+                    "pool",
+                    saturation < max_saturation,
+                    format!(
+                        "{} of {} connections open, {} idle ({:.0}% in use)",
+                        pool.size,
+                        pool.max_size,
+                        pool.idle,
+                        saturation * 100.0
+                    ),
+                ));
+            }
+        }
+        Ok(Err(e)) => {
+            warn!("Readiness check failed: {}", e);
+            checks.push(check("database", false, "Unreachable".to_string()));
+        }
+        Err(_) => {
+            warn!("Readiness check timed out after {:?}", timeout);
+            checks.push(check(
+                "database",
+                false,
+                format!("No answer within {} ms", timeout.as_millis()),
+            ));
+        }
+    }
+
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{MemoryStore, SqliteStore};
+
+    fn status(checks: &[HealthCheck], name: &str) -> HealthStatus {
+        checks
+            .iter()
+            .find(|check| check.name == name)
+            .unwrap()
+            .status
+    }
+
+    #[actix_web::test]
+    async fn ready_store_passes_every_check() {
+        let checks = readiness_checks(&MemoryStore::default(), &HealthConfig::default()).await;
+        assert!(checks.iter().all(|check| check.status == HealthStatus::Ok));
+
+        // The probe itself takes a connection, so the pool needs more than one
+        let store = SqliteStore::connect("sqlite:file:readyz?mode=memory&cache=shared", 4)
+            .await
+            .unwrap();
+        store.migrate().await.unwrap();
+        let checks = readiness_checks(&store, &HealthConfig::default()).await;
+        assert_eq!(status(&checks, "database"), HealthStatus::Ok);
+        assert_eq!(status(&checks, "migrations"), HealthStatus::Ok);
+        assert_eq!(status(&checks, "pool"), HealthStatus::Ok);
+    }
+
+    #[actix_web::test]
+    async fn saturated_pool_is_not_ready() {
+        let store = SqliteStore::connect("sqlite::memory:", 1).await.unwrap();
+        store.migrate().await.unwrap();
+        let config = HealthConfig {
+            max_pool_saturation: Some(0.0),
+            ..Default::default()
+        };
+        let checks = readiness_checks(&store, &config).await;
+        assert_eq!(status(&checks, "database"), HealthStatus::Ok);
+        assert_eq!(status(&checks, "pool"), HealthStatus::Unavailable);
+    }
+
+    #[actix_web::test]
+    async fn database_errors_are_not_disclosed() {
+        // Without migrations, the probe query fails on the missing `_sqlx_migrations` table
+        let store = SqliteStore::connect("sqlite::memory:", 1).await.unwrap();
+        let checks = readiness_checks(&store, &HealthConfig::default()).await;
+        assert_eq!(checks.len(), 1);
+        assert_eq!(status(&checks, "database"), HealthStatus::Unavailable);
+        assert_eq!(checks[0].detail.as_deref(), Some("Unreachable"));
+    }
+}
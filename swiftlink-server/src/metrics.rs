@@ -0,0 +1,242 @@
+//! Prometheus metrics, exported in the text format at `/metrics`.
+
+use actix_web::{
+    Error, HttpRequest, HttpResponse, Responder,
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web,
+};
+use log::error;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+use std::time::Instant;
+
+use crate::{AppState, generator::CollisionMetrics, handlers::check_bearer_token};
+
+/// Label of requests that matched no route
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Every metric of the server, registered in its own registry
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    redirects: IntCounterVec,
+    links_created: IntCounterVec,
+    rate_limited: IntCounterVec,
+    webhook_deliveries: IntCounterVec,
+    /// Link events dropped because the webhook queue was full
+    pub webhook_events_dropped: IntCounter,
+    /// Duration of the storage operations, by operation name
+    pub db_query_duration: HistogramVec,
+    /// Storage operations in progress
+    pub db_queries_in_flight: IntGauge,
+    db_pool_connections: IntGauge,
+    db_pool_idle: IntGauge,
+    db_pool_max: IntGauge,
+    db_pool_waiting: IntGauge,
+}
+
+impl Metrics {
+    pub fn new(code_metrics: &CollisionMetrics) -> prometheus::Result<Self> {
+        let registry = Registry::new();
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("swiftlink_http_requests_total", "HTTP requests handled"),
+            &["method", "route", "status"],
+        )?;
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "swiftlink_http_request_duration_seconds",
+                "Time taken to handle HTTP requests",
+            ),
+            &["method", "route", "status"],
+        )?;
+        let redirects = IntCounterVec::new(
+            Opts::new(
+                "swiftlink_redirects_total",
+                "Redirect requests, by result (hit, miss or expired)",
+            ),
+            &["result"],
+        )?;
+        let links_created = IntCounterVec::new(
+            Opts::new(
+                "swiftlink_links_created_total",
+                "Short links created, by endpoint (single or bulk)",
+            ),
+            &["endpoint"],
+        )?;
+        let rate_limited = IntCounterVec::new(
+            Opts::new(
+                "swiftlink_rate_limited_total",
+                "Requests rejected by the rate limiter, by limit (create, bulk_create, info or redirect)",
+            ),
+            &["limit"],
+        )?;
+        let webhook_deliveries = IntCounterVec::new(
+            Opts::new(
+                "swiftlink_webhook_deliveries_total",
+                "Attempts to deliver webhook events, by result (delivered, retry or failed)",
+            ),
+            &["result"],
+        )?;
+        let webhook_events_dropped = IntCounter::new(
+            "swiftlink_webhook_events_dropped_total",
+            "Link events not sent to webhooks because their queue was full",
+        )?;
+        let db_query_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "swiftlink_db_query_duration_seconds",
+                "Time taken by storage operations",
+            ),
+            &["operation"],
+        )?;
+        let db_queries_in_flight = IntGauge::new(
+            "swiftlink_db_queries_in_flight",
+            "Storage operations in progress",
+        )?;
+        let db_pool_connections = IntGauge::new(
+            "swiftlink_db_pool_connections",
+            "Open database connections, idle or in use",
+        )?;
+        let db_pool_idle = IntGauge::new(
+            "swiftlink_db_pool_idle_connections",
+            "Open database connections not in use",
+        )?;
+        let db_pool_max = IntGauge::new(
+            "swiftlink_db_pool_max_connections",
+            "Largest number of database connections the pool may open",
+        )?;
+        let db_pool_waiting = IntGauge::new(
+            "swiftlink_db_pool_waiting",
+            "Storage operations waiting for a database connection (estimated)",
+        )?;
+
+        registry.register(Box::new(http_requests.clone()))?;
+        registry.register(Box::new(http_request_duration.clone()))?;
+        registry.register(Box::new(redirects.clone()))?;
+        registry.register(Box::new(links_created.clone()))?;
+        registry.register(Box::new(rate_limited.clone()))?;
+        registry.register(Box::new(webhook_deliveries.clone()))?;
+        registry.register(Box::new(webhook_events_dropped.clone()))?;
+        registry.register(Box::new(db_query_duration.clone()))?;
+        registry.register(Box::new(db_queries_in_flight.clone()))?;
+        registry.register(Box::new(db_pool_connections.clone()))?;
+        registry.register(Box::new(db_pool_idle.clone()))?;
+        registry.register(Box::new(db_pool_max.clone()))?;
+        registry.register(Box::new(db_pool_waiting.clone()))?;
+        registry.register(Box::new(code_metrics.attempts.clone()))?;
+        registry.register(Box::new(code_metrics.collisions.clone()))?;
+
+        Ok(Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            redirects,
+            links_created,
+            rate_limited,
+            webhook_deliveries,
+            webhook_events_dropped,
+            db_query_duration,
+            db_queries_in_flight,
+            db_pool_connections,
+            db_pool_idle,
+            db_pool_max,
+            db_pool_waiting,
+        })
+    }
+
+    /// Count a redirect request by its result: `hit`, `miss` or `expired`
+    pub fn redirect(&self, result: &str) {
+        self.redirects.with_label_values(&[result]).inc();
+    }
+
+    /// Count links created by an endpoint: `single` or `bulk`
+    pub fn links_created(&self, endpoint: &str, count: usize) {
+        self.links_created
+            .with_label_values(&[endpoint])
+            .inc_by(count as u64);
+    }
+
+    /// Count a request rejected by the rate limiter
+    pub fn rate_limited(&self, limit: &str) {
+        self.rate_limited.with_label_values(&[limit]).inc();
+    }
+
+    /// Count an attempt to deliver a webhook event by its result: `delivered`, `retry` or
+    /// `failed`
+    pub fn webhook_delivery(&self, result: &str) {
+        self.webhook_deliveries.with_label_values(&[result]).inc();
+    }
+}
+
+/// Middleware recording the count and duration of every request, labelled with the route
+/// pattern rather than the path so that codes do not end up in labels.
+pub async fn track_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let Some(state) = req.app_data::<web::Data<AppState>>().cloned() else {
+        return next.call(req).await;
+    };
+    // Taken before routing, which needs the only reference to the request
+    let method = req.method().clone();
+    let route = req.match_pattern();
+    let start = Instant::now();
+
+    let result = next.call(req).await;
+
+    let status = match &result {
+        Ok(res) => res.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    let labels = [
+        method.as_str(),
+        route.as_deref().unwrap_or(UNMATCHED_ROUTE),
+        status.as_str(),
+    ];
+    let metrics = &state.metrics;
+    metrics.http_requests.with_label_values(&labels).inc();
+    metrics
+        .http_request_duration
+        .with_label_values(&labels)
+        .observe(start.elapsed().as_secs_f64());
+
+    result
+}
+
+/// Handler serving every metric in the Prometheus text format, to holders of the bearer token
+/// unless `require_token` is disabled
+pub async fn metrics_endpoint(state: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    if state.config.metrics.require_token
+        && let Err(e) = check_bearer_token(&state, &req)
+    {
+        return e.into();
+    }
+
+    let metrics = &state.metrics;
+    if let Some(pool) = state.store.pool_stats() {
+        let in_use = i64::from(pool.size.saturating_sub(pool.idle));
+        metrics.db_pool_connections.set(i64::from(pool.size));
+        metrics.db_pool_idle.set(i64::from(pool.idle));
+        metrics.db_pool_max.set(i64::from(pool.max_size));
+        // Every storage operation holds at most one connection, so the operations in
+        // progress beyond the connections in use are waiting for one
+        metrics
+            .db_pool_waiting
+            .set((metrics.db_queries_in_flight.get() - in_use).max(0));
+    }
+
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    if let Err(e) = encoder.encode(&metrics.registry.gather(), &mut buffer) {
+        error!("Error encoding metrics: {:?}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::Ok()
+        .content_type(encoder.format_type())
+        .body(buffer)
+}
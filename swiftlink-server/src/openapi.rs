@@ -0,0 +1,85 @@
+//! OpenAPI document of the server, generated from the handlers and the `swiftlink_api` types.
+
+use actix_web::{HttpResponse, Responder, web};
+use utoipa::{
+    Modify, OpenApi,
+    openapi::{
+        Server,
+        security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    },
+};
+
+use crate::{AppState, handlers, health};
+
+/// URL of the OpenAPI document relative to the docs page, which works under any path prefix
+#[cfg(feature = "swagger-ui")]
+const OPENAPI_URL: &str = "../openapi.json";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Swiftlink",
+        description = "URL shortening service. Errors are reported as RFC 7807 problem documents."
+    ),
+    paths(
+        handlers::create_link,
+        handlers::create_links_bulk,
+        handlers::get_link_info,
+        handlers::list_links,
+        handlers::update_link,
+        handlers::delete_links_bulk,
+        handlers::get_link_stats,
+        handlers::list_tags,
+        handlers::get_tag_stats,
+        handlers::get_qr,
+        handlers::stream_events,
+        handlers::delete_link,
+        handlers::redirect,
+        handlers::get_version,
+        health::healthz,
+        health::readyz,
+    ),
+    modifiers(&BearerToken),
+    tags(
+        (name = "links", description = "Creating and managing short links"),
+        (name = "stats", description = "Click statistics"),
+        (name = "redirect", description = "Following short links"),
+        (name = "meta", description = "Versions, capabilities and health of the server"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Declares the `bearer_token` security scheme required by the admin endpoints
+struct BearerToken;
+
+impl Modify for BearerToken {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer_token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+/// Handler serving the OpenAPI document as JSON, with the path prefix of the server if any
+pub async fn openapi_json(state: web::Data<AppState>) -> impl Responder {
+    let mut doc = ApiDoc::openapi();
+    let prefix = state.public_url.path_prefix();
+    if !prefix.is_empty() {
+        doc.servers = Some(vec![Server::new(prefix)]);
+    }
+    HttpResponse::Ok().json(doc)
+}
+
+/// Register the OpenAPI document at `openapi.json` and, with the `swagger-ui` feature, the docs
+/// page at `docs/`, relative to the API scope.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.route("/openapi.json", web::get().to(openapi_json));
+
+    #[cfg(feature = "swagger-ui")]
+    cfg.service(
+        utoipa_swagger_ui::SwaggerUi::new("/docs/{_:.*}")
+            .config(utoipa_swagger_ui::Config::from(OPENAPI_URL)),
+    );
+}
@@ -0,0 +1,102 @@
+//! RFC 7807 problem documents, the body of every error response of the API.
+
+use actix_web::{
+    HttpResponse, ResponseError,
+    http::{StatusCode, header::ContentType},
+};
+use std::fmt;
+use swiftlink_api::{ErrorCode, ProblemDetails};
+
+/// Media type of problem documents
+const PROBLEM_JSON: &str = "application/problem+json";
+
+/// An error reported to clients as an `application/problem+json` response
+#[derive(Debug)]
+pub struct Problem {
+    pub status: StatusCode,
+    pub code: ErrorCode,
+    pub detail: String,
+}
+
+impl Problem {
+    pub fn new(status: StatusCode, code: ErrorCode, detail: impl Into<String>) -> Self {
+        Problem {
+            status,
+            code,
+            detail: detail.into(),
+        }
+    }
+
+    /// 400 Bad Request with a specific error code
+    pub fn bad_request(code: ErrorCode, detail: impl Into<String>) -> Self {
+        Problem::new(StatusCode::BAD_REQUEST, code, detail)
+    }
+
+    /// 400 Bad Request for malformed requests and invalid parameters
+    pub fn invalid_request(detail: impl Into<String>) -> Self {
+        Problem::bad_request(ErrorCode::InvalidRequest, detail)
+    }
+
+    pub fn unauthorized(detail: impl Into<String>) -> Self {
+        Problem::new(StatusCode::UNAUTHORIZED, ErrorCode::Unauthorized, detail)
+    }
+
+    /// 404 Not Found for unknown link codes
+    pub fn link_not_found() -> Self {
+        Problem::new(StatusCode::NOT_FOUND, ErrorCode::NotFound, "Link not found")
+    }
+
+    /// 500 Internal Server Error, the details being logged rather than returned
+    pub fn internal(detail: impl Into<String>) -> Self {
+        Problem::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorCode::InternalError,
+            detail,
+        )
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.detail)
+    }
+}
+
+impl ResponseError for Problem {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status)
+            .insert_header(ContentType(PROBLEM_JSON.parse().unwrap()))
+            .json(ProblemDetails {
+                problem_type: "about:blank".to_string(),
+                title: self
+                    .status
+                    .canonical_reason()
+                    .unwrap_or_default()
+                    .to_string(),
+                status: self.status.as_u16(),
+                detail: Some(self.detail.clone()),
+                code: self.code,
+            })
+    }
+}
+
+impl From<Problem> for HttpResponse {
+    fn from(problem: Problem) -> Self {
+        problem.error_response()
+    }
+}
+
+/// Error handler of the JSON body and query string extractors, so that malformed requests
+/// are answered with problem documents as well.
+pub fn extractor_error<E: ResponseError>(err: E) -> actix_web::Error {
+    Problem::new(
+        err.status_code(),
+        ErrorCode::InvalidRequest,
+        err.to_string(),
+    )
+    .into()
+}
@@ -0,0 +1,157 @@
+//! Public URL of the short links, returned in responses and encoded in QR codes.
+
+use actix_web::HttpRequest;
+use url::Url;
+
+use crate::config::BaseOptions;
+
+/// Base URL under which short links are published
+#[derive(Debug, Clone)]
+pub struct PublicUrl {
+    /// Parsed `public_base_url`, its path always ending with a slash
+    base: Option<Url>,
+    /// Path under which every endpoint is served, without trailing slash
+    prefix: String,
+}
+
+impl PublicUrl {
+    /// Parse `public_base_url`, which must be an absolute http(s) URL without query nor fragment,
+    /// and `path_prefix`, which defaults to its path.
+    pub fn from_config(options: &BaseOptions) -> Result<Self, String> {
+        let base = match &options.public_base_url {
+            Some(input) => Some(parse_base(input)?),
+            None => None,
+        };
+        let prefix = match &options.path_prefix {
+            Some(prefix) => {
+                if !prefix.starts_with('/') || prefix.contains(['?', '#', '{', '}']) {
+                    return Err(format!(
+                        "path_prefix {prefix} must be a path starting with a slash"
+                    ));
+                }
+                prefix.trim_end_matches('/').to_string()
+            }
+            None => base.as_ref().map_or(String::new(), |url| {
+                url.path().trim_end_matches('/').to_string()
+            }),
+        };
+        Ok(Self { base, prefix })
+    }
+
+    /// Path under which every endpoint is served, without trailing slash: empty when they are
+    /// served at the root
+    pub fn path_prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Base of the short URLs returned to a request, ending with a slash. Without
+    /// `public_base_url`, the scheme and host the request was sent to are used.
+    pub fn base_for(&self, req: &HttpRequest) -> String {
+        match &self.base {
+            Some(url) => url.to_string(),
+            None => {
+                let info = req.connection_info();
+                format!("{}://{}/", info.scheme(), info.host())
+            }
+        }
+    }
+}
+
+fn parse_base(input: &str) -> Result<Url, String> {
+    let mut url = Url::parse(input).map_err(|e| format!("Invalid public_base_url {input}: {e}"))?;
+    if !matches!(url.scheme(), "http" | "https") || url.host().is_none() {
+        return Err(format!("public_base_url {input} is not an http(s) URL"));
+    }
+    if url.query().is_some() || url.fragment().is_some() {
+        return Err(format!(
+            "public_base_url {input} must not have a query or fragment"
+        ));
+    }
+    if !url.path().ends_with('/') {
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+    Ok(url)
+}
+
+/// Full short URL of a code, given the base returned by [`PublicUrl::base_for`]
+pub fn short_url(base: &str, code: &str) -> String {
+    format!("{base}{code}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn public_url(base: Option<&str>, prefix: Option<&str>) -> Result<PublicUrl, String> {
+        let mut options: BaseOptions = toml::from_str("").unwrap();
+        options.public_base_url = base.map(str::to_string);
+        options.path_prefix = prefix.map(str::to_string);
+        PublicUrl::from_config(&options)
+    }
+
+    #[test]
+    fn base_url_gets_a_trailing_slash() {
+        let req = TestRequest::default().to_http_request();
+        for (input, base, prefix) in [
+            ("https://example.com", "https://example.com/", ""),
+            ("https://example.com/", "https://example.com/", ""),
+            ("https://example.com/s", "https://example.com/s/", "/s"),
+            ("https://example.com/s/", "https://example.com/s/", "/s"),
+            (
+                "http://example.com:8080/a/b/",
+                "http://example.com:8080/a/b/",
+                "/a/b",
+            ),
+        ] {
+            let url = public_url(Some(input), None).unwrap();
+            assert_eq!(url.base_for(&req), base, "{input}");
+            assert_eq!(url.path_prefix(), prefix, "{input}");
+        }
+        assert_eq!(
+            short_url("https://example.com/s/", "abc"),
+            "https://example.com/s/abc"
+        );
+    }
+
+    #[test]
+    fn request_host_is_used_without_base_url() {
+        let url = public_url(None, None).unwrap();
+        let req = TestRequest::default()
+            .insert_header(("host", "sho.rt"))
+            .to_http_request();
+        assert_eq!(url.base_for(&req), "http://sho.rt/");
+        assert_eq!(url.path_prefix(), "");
+    }
+
+    #[test]
+    fn path_prefix_overrides_the_base_path() {
+        let req = TestRequest::default().to_http_request();
+        // A proxy stripping /s/ before forwarding
+        let url = public_url(Some("https://example.com/s/"), Some("/")).unwrap();
+        assert_eq!(url.base_for(&req), "https://example.com/s/");
+        assert_eq!(url.path_prefix(), "");
+
+        let url = public_url(Some("https://example.com/s/"), Some("/links/")).unwrap();
+        assert_eq!(url.path_prefix(), "/links");
+        let url = public_url(None, Some("/links")).unwrap();
+        assert_eq!(url.path_prefix(), "/links");
+
+        for invalid in ["links", "/links?a=b", "/{code}"] {
+            assert!(public_url(None, Some(invalid)).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn invalid_base_urls_are_rejected() {
+        for invalid in [
+            "example.com/s/",
+            "ftp://example.com/",
+            "https://example.com/?a=b",
+            "https://example.com/#top",
+        ] {
+            assert!(public_url(Some(invalid), None).is_err(), "{invalid}");
+        }
+    }
+}
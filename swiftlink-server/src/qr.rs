@@ -0,0 +1,294 @@
+//! Rendering of short links as QR codes, in PNG, SVG or terminal text.
+
+use qrcode::{Color, EcLevel, QrCode};
+use std::fmt::Write;
+use swiftlink_api::{QrErrorCorrection, QrFormat, QrQuery};
+
+use crate::problem::Problem;
+
+/// Image size in pixels when none is requested
+const DEFAULT_SIZE: u32 = 256;
+
+/// Largest image size in pixels that can be requested, keeping PNG rendering to a few MB. The
+/// image is rounded down to a whole number of pixels per module, and never below one.
+const MAX_SIZE: u32 = 1024;
+
+/// Border width in modules when none is requested, the quiet zone required by the standard
+const DEFAULT_MARGIN: u32 = 4;
+
+/// Largest border width in modules that can be requested
+const MAX_MARGIN: u32 = 64;
+
+type Rgb = [u8; 3];
+
+/// Validated rendering options of a QR code
+pub struct QrOptions {
+    format: QrFormat,
+    size: u32,
+    margin: u32,
+    ecc: QrErrorCorrection,
+    foreground: Rgb,
+    background: Rgb,
+}
+
+/// Parse a `RRGGBB` hex color, optionally prefixed by `#`.
+fn parse_color(input: &str) -> Option<Rgb> {
+    let hex = input.strip_prefix('#').unwrap_or(input);
+    // from_str_radix alone would also accept a sign, as in "+f8000"
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+fn hex(color: Rgb) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+impl QrOptions {
+    pub fn from_query(query: &QrQuery) -> Result<Self, Problem> {
+        let size = query.size.unwrap_or(DEFAULT_SIZE);
+        if size == 0 || size > MAX_SIZE {
+            return Err(Problem::invalid_request(format!(
+                "size must be between 1 and {MAX_SIZE} pixels"
+            )));
+        }
+        let margin = query.margin.unwrap_or(DEFAULT_MARGIN);
+        if margin > MAX_MARGIN {
+            return Err(Problem::invalid_request(format!(
+                "margin must be at most {MAX_MARGIN} modules"
+            )));
+        }
+        let color = |input: &Option<String>, default: Rgb, name: &str| match input {
+            Some(input) => parse_color(input).ok_or_else(|| {
+                Problem::invalid_request(format!("{name} must be an RRGGBB hex color"))
+            }),
+            None => Ok(default),
+        };
+
+        Ok(Self {
+            format: query.format.unwrap_or_default(),
+            size,
+            margin,
+            ecc: query.ecc.unwrap_or_default(),
+            foreground: color(&query.foreground, [0, 0, 0], "foreground")?,
+            background: color(&query.background, [255, 255, 255], "background")?,
+        })
+    }
+}
+
+/// The modules of a QR code surrounded by its margin
+struct Modules {
+    dark: Vec<bool>,
+    code_width: usize,
+    margin: usize,
+}
+
+impl Modules {
+    /// Number of modules on each side, margin included
+    fn width(&self) -> usize {
+        self.code_width + 2 * self.margin
+    }
+
+    fn is_dark(&self, x: usize, y: usize) -> bool {
+        let inside = |i: usize| i >= self.margin && i < self.margin + self.code_width;
+        inside(x) && inside(y) && self.dark[(y - self.margin) * self.code_width + (x - self.margin)]
+    }
+}
+
+/// Render `data` as a QR code, returning the media type and the content of the image.
+pub fn render(data: &str, options: &QrOptions) -> Result<(&'static str, Vec<u8>), String> {
+    let ec_level = match options.ecc {
+        QrErrorCorrection::Low => EcLevel::L,
+        QrErrorCorrection::Medium => EcLevel::M,
+        QrErrorCorrection::Quartile => EcLevel::Q,
+        QrErrorCorrection::High => EcLevel::H,
+    };
+    let code = QrCode::with_error_correction_level(data, ec_level).map_err(|e| e.to_string())?;
+    let modules = Modules {
+        code_width: code.width(),
+        dark: code
+            .to_colors()
+            .into_iter()
+            .map(|color| color == Color::Dark)
+            .collect(),
+        margin: options.margin as usize,
+    };
+    // Whole pixels per module, so that modules stay sharp
+    let scale = (options.size as usize / modules.width()).max(1);
+
+    match options.format {
+        QrFormat::Png => Ok(("image/png", render_png(&modules, scale, options)?)),
+        QrFormat::Svg => Ok((
+            "image/svg+xml",
+            render_svg(&modules, scale, options).into_bytes(),
+        )),
+        QrFormat::Text => Ok((
+            "text/plain; charset=utf-8",
+            render_text(&modules).into_bytes(),
+        )),
+    }
+}
+
+fn render_png(modules: &Modules, scale: usize, options: &QrOptions) -> Result<Vec<u8>, String> {
+    let side = modules.width() * scale;
+    let mut pixels = Vec::with_capacity(side * side * 3);
+    for y in 0..side {
+        for x in 0..side {
+            pixels.extend_from_slice(if modules.is_dark(x / scale, y / scale) {
+                &options.foreground
+            } else {
+                &options.background
+            });
+        }
+    }
+
+    let mut image = Vec::new();
+    let mut encoder = png::Encoder::new(&mut image, side as u32, side as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&pixels))
+        .map_err(|e| e.to_string())?;
+    Ok(image)
+}
+
+fn render_svg(modules: &Modules, scale: usize, options: &QrOptions) -> String {
+    let width = modules.width();
+    let mut path = String::new();
+    for y in 0..width {
+        for x in 0..width {
+            if modules.is_dark(x, y) {
+                let _ = write!(path, "M{x},{y}h1v1h-1z");
+            }
+        }
+    }
+    format!(
+        concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{size}" height="{size}" "#,
+            r#"viewBox="0 0 {width} {width}" shape-rendering="crispEdges">"#,
+            r#"<rect width="{width}" height="{width}" fill="{background}"/>"#,
+            r#"<path d="{path}" fill="{foreground}"/></svg>"#,
+        ),
+        size = width * scale,
+        width = width,
+        background = hex(options.background),
+        foreground = hex(options.foreground),
+        path = path,
+    )
+}
+
+/// Draw light modules as blocks, two rows per line, for terminals with a dark background
+fn render_text(modules: &Modules) -> String {
+    let width = modules.width();
+    let is_light = |x: usize, y: usize| y < width && !modules.is_dark(x, y);
+    let mut text = String::new();
+    for y in (0..width).step_by(2) {
+        for x in 0..width {
+            text.push(match (is_light(x, y), is_light(x, y + 1)) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            });
+        }
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://example.com/abc123";
+
+    fn options(size: u32, margin: u32, format: QrFormat) -> QrOptions {
+        QrOptions::from_query(&QrQuery {
+            format: Some(format),
+            size: Some(size),
+            margin: Some(margin),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    /// Width and height of a PNG image, read from its IHDR chunk
+    fn png_size(image: &[u8]) -> (u32, u32) {
+        let be = |i: usize| u32::from_be_bytes(image[i..i + 4].try_into().unwrap());
+        (be(16), be(20))
+    }
+
+    #[test]
+    fn size_and_margin_are_capped() {
+        let query = |size, margin| QrQuery {
+            size,
+            margin,
+            ..Default::default()
+        };
+        assert!(QrOptions::from_query(&query(None, None)).is_ok());
+        assert!(QrOptions::from_query(&query(Some(1), Some(0))).is_ok());
+        assert!(QrOptions::from_query(&query(Some(MAX_SIZE), Some(MAX_MARGIN))).is_ok());
+        assert!(QrOptions::from_query(&query(Some(0), None)).is_err());
+        assert!(QrOptions::from_query(&query(Some(MAX_SIZE + 1), None)).is_err());
+        assert!(QrOptions::from_query(&query(None, Some(MAX_MARGIN + 1))).is_err());
+    }
+
+    #[test]
+    fn colors_are_parsed() {
+        assert_eq!(parse_color("ff8000"), Some([255, 128, 0]));
+        assert_eq!(parse_color("#FF8000"), Some([255, 128, 0]));
+        assert_eq!(parse_color("#000000"), Some([0, 0, 0]));
+        for invalid in [
+            "", "#", "fff", "ff80000", "##ff8000", "gg8000", "+f8000", "ééé",
+        ] {
+            assert_eq!(parse_color(invalid), None, "{invalid:?}");
+        }
+
+        let query = |foreground: &str| QrQuery {
+            foreground: Some(foreground.to_string()),
+            ..Default::default()
+        };
+        let options = QrOptions::from_query(&query("#123abc")).unwrap();
+        assert_eq!(options.foreground, [0x12, 0x3a, 0xbc]);
+        assert_eq!(options.background, [255, 255, 255]);
+        assert!(QrOptions::from_query(&query("blue")).is_err());
+    }
+
+    #[test]
+    fn size_is_rounded_down_to_whole_modules() {
+        let modules = QrCode::new(URL).unwrap().width() as u32 + 2 * DEFAULT_MARGIN;
+        let size = 10 * modules - 1;
+
+        let (media_type, image) =
+            render(URL, &options(size, DEFAULT_MARGIN, QrFormat::Png)).unwrap();
+        assert_eq!(media_type, "image/png");
+        assert_eq!(png_size(&image), (9 * modules, 9 * modules));
+
+        let (_, image) = render(URL, &options(size, DEFAULT_MARGIN, QrFormat::Svg)).unwrap();
+        let svg = String::from_utf8(image).unwrap();
+        assert!(svg.contains(&format!(r#"width="{0}" height="{0}""#, 9 * modules)));
+        assert!(svg.contains(&format!(r#"viewBox="0 0 {modules} {modules}""#)));
+    }
+
+    #[test]
+    fn sizes_below_the_modules_use_one_pixel_per_module() {
+        let modules = QrCode::new(URL).unwrap().width() as u32;
+        let (_, image) = render(URL, &options(1, 0, QrFormat::Png)).unwrap();
+        assert_eq!(png_size(&image), (modules, modules));
+    }
+
+    #[test]
+    fn text_packs_two_rows_per_line() {
+        let modules = QrCode::new(URL).unwrap().width() + 2;
+        let (_, text) = render(URL, &options(DEFAULT_SIZE, 1, QrFormat::Text)).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), modules.div_ceil(2));
+        assert!(lines.iter().all(|line| line.chars().count() == modules));
+        // The margin is light
+        assert!(lines[0].chars().all(|c| c == '█' || c == '▀'));
+    }
+}
@@ -0,0 +1,565 @@
+//! Per-client token-bucket rate limiting of link creation, reads and redirects.
+//!
+//! Every client gets one bucket per kind of request, holding up to `burst` tokens and refilled
+//! continuously. Each request takes a token, or one per link for bulk creation, and is answered
+//! with 429 Too Many Requests when the bucket does not hold enough.
+
+use actix_web::{
+    Error, HttpRequest, HttpResponse,
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::{
+        StatusCode,
+        header::{self, HeaderMap, HeaderName, HeaderValue},
+    },
+    middleware::Next,
+    rt, web,
+};
+use log::debug;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use swiftlink_api::ErrorCode;
+
+use crate::{
+    AppState,
+    config::{RateLimit, RateLimitConfig},
+    problem::Problem,
+};
+
+/// Seconds between two removals of the buckets that refilled completely
+const PRUNE_INTERVAL: u64 = 60;
+
+const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+/// Parse an `X-Forwarded-For` entry: an address, possibly with a port
+fn parse_forwarded_addr(entry: &str) -> Option<IpAddr> {
+    entry
+        .parse::<IpAddr>()
+        .ok()
+        .or_else(|| entry.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+}
+
+/// Kinds of requests, each limited separately
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LimitClass {
+    Create,
+    BulkCreate,
+    Info,
+    Redirect,
+}
+
+impl LimitClass {
+    fn name(self) -> &'static str {
+        match self {
+            LimitClass::Create => "create",
+            LimitClass::BulkCreate => "bulk_create",
+            LimitClass::Info => "info",
+            LimitClass::Redirect => "redirect",
+        }
+    }
+}
+
+/// Who a bucket belongs to: clients sending the bearer token share one, others are told
+/// apart by address
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ClientKey {
+    Token,
+    Ip(IpAddr),
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// State of a bucket after a request took (or failed to take) its tokens from it
+pub struct Decision {
+    class: LimitClass,
+    allowed: bool,
+    limit: u32,
+    remaining: u32,
+    /// Seconds until the bucket is full again
+    reset: u64,
+    /// Seconds until the next request is allowed
+    retry_after: u64,
+}
+
+impl Decision {
+    pub fn add_headers(&self, headers: &mut HeaderMap) {
+        headers.insert(RATELIMIT_LIMIT, HeaderValue::from(self.limit));
+        headers.insert(RATELIMIT_REMAINING, HeaderValue::from(self.remaining));
+        headers.insert(RATELIMIT_RESET, HeaderValue::from(self.reset));
+        if !self.allowed {
+            headers.insert(header::RETRY_AFTER, HeaderValue::from(self.retry_after));
+        }
+    }
+
+    /// Response rejecting the request, unless it is allowed
+    pub fn rejection(&self) -> Option<HttpResponse> {
+        if self.allowed {
+            return None;
+        }
+        let mut response = HttpResponse::from(Problem::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::RateLimited,
+            format!(
+                "Too many {} requests, retry in {} seconds",
+                self.class.name(),
+                self.retry_after
+            ),
+        ));
+        self.add_headers(response.headers_mut());
+        Some(response)
+    }
+}
+
+/// The token buckets of every client
+pub struct RateLimiter {
+    enabled: bool,
+    trust_forwarded_for: bool,
+    trusted_proxies: usize,
+    create: RateLimit,
+    bulk_create: RateLimit,
+    info: RateLimit,
+    redirect: RateLimit,
+    buckets: Mutex<HashMap<(LimitClass, ClientKey), Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn from_config(config: &RateLimitConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            trust_forwarded_for: config.trust_forwarded_for,
+            trusted_proxies: config.trusted_proxies.unwrap_or(1).max(1),
+            create: config.create.unwrap_or(RateLimit {
+                burst: 20,
+                per_minute: 60,
+            }),
+            bulk_create: config.bulk_create.unwrap_or(RateLimit {
+                burst: 1000,
+                per_minute: 1000,
+            }),
+            info: config.info.unwrap_or(RateLimit {
+                burst: 60,
+                per_minute: 600,
+            }),
+            redirect: config.redirect.unwrap_or(RateLimit {
+                burst: 120,
+                per_minute: 3000,
+            }),
+            buckets: Mutex::default(),
+        }
+    }
+
+    /// Capacity and refill rate (tokens per second) of the buckets of a class
+    fn bucket_shape(&self, class: LimitClass) -> (f64, f64) {
+        let limit = match class {
+            LimitClass::Create => self.create,
+            LimitClass::BulkCreate => self.bulk_create,
+            LimitClass::Info => self.info,
+            LimitClass::Redirect => self.redirect,
+        };
+        (
+            f64::from(limit.burst.max(1)),
+            f64::from(limit.per_minute.max(1)) / 60.0,
+        )
+    }
+
+    /// Address of the client of a request: when forwarded addresses are trusted, the entry of
+    /// `X-Forwarded-For` appended by the outermost of the `trusted_proxies`, the peer address
+    /// otherwise or when there is no such entry. `None` when the request has no (valid)
+    /// address, e.g. in tests.
+    pub fn client_ip(&self, req: &HttpRequest) -> Option<IpAddr> {
+        let peer = req.peer_addr().map(|addr| addr.ip());
+        if !self.trust_forwarded_for {
+            return peer;
+        }
+        // Entries written by the client come first, each proxy appending one after them
+        let forwarded: Vec<&str> = req
+            .headers()
+            .get_all(X_FORWARDED_FOR)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect();
+        let Some(index) = forwarded.len().checked_sub(self.trusted_proxies) else {
+            debug!("Fewer X-Forwarded-For entries than trusted proxies, using the peer address");
+            return peer;
+        };
+        parse_forwarded_addr(forwarded[index]).or(peer)
+    }
+
+    /// Identify the client of a request, unless it has no address (e.g. in tests)
+    fn client_key(&self, req: &HttpRequest, bearer_token: Option<&str>) -> Option<ClientKey> {
+        let provided_token = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if provided_token.is_some() && provided_token == bearer_token {
+            return Some(ClientKey::Token);
+        }
+
+        Some(ClientKey::Ip(match self.client_ip(req)? {
+            // Hosts usually get a whole /64, so its addresses count as one client
+            IpAddr::V6(ip) => IpAddr::V6(Ipv6Addr::from(u128::from(ip) & !(u64::MAX as u128))),
+            ip => ip,
+        }))
+    }
+
+    /// Take `cost` tokens from the bucket of the client for this class of requests. Costs
+    /// over the capacity of the bucket are lowered to it, such requests needing a full bucket.
+    fn take(&self, class: LimitClass, key: ClientKey, cost: u32, now: Instant) -> Decision {
+        let (capacity, rate) = self.bucket_shape(class);
+        let cost = f64::from(cost.max(1)).min(capacity);
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry((class, key)).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.updated = now;
+        let allowed = bucket.tokens >= cost;
+        if allowed {
+            bucket.tokens -= cost;
+        }
+
+        Decision {
+            class,
+            allowed,
+            limit: capacity as u32,
+            remaining: bucket.tokens.floor() as u32,
+            reset: ((capacity - bucket.tokens) / rate).ceil() as u64,
+            retry_after: ((cost - bucket.tokens) / rate).ceil().max(1.0) as u64,
+        }
+    }
+
+    /// Forget the buckets that refilled completely, which are no different from new ones
+    fn prune(&self, now: Instant) {
+        let mut buckets = self.buckets.lock().unwrap();
+        let before = buckets.len();
+        buckets.retain(|(class, _), bucket| {
+            let (capacity, rate) = self.bucket_shape(*class);
+            let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens + elapsed * rate < capacity
+        });
+        debug!("Pruned {} rate limit bucket(s)", before - buckets.len());
+    }
+}
+
+/// Spawn the background task forgetting the buckets of clients that stopped sending requests.
+pub fn spawn_pruner(limiter: Arc<RateLimiter>) {
+    if !limiter.enabled {
+        return;
+    }
+
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(Duration::from_secs(PRUNE_INTERVAL));
+        loop {
+            interval.tick().await;
+            limiter.prune(Instant::now());
+        }
+    });
+}
+
+/// Charge a request `cost` tokens of its class, unless rate limiting is disabled or the client
+/// cannot be identified.
+fn charge(state: &AppState, req: &HttpRequest, class: LimitClass, cost: u32) -> Option<Decision> {
+    let limiter = &state.rate_limiter;
+    if !limiter.enabled {
+        return None;
+    }
+    let key = limiter.client_key(req, state.config.base.bearer_token.as_deref())?;
+    let decision = limiter.take(class, key, cost, Instant::now());
+    if !decision.allowed {
+        state.metrics.rate_limited(class.name());
+    }
+    Some(decision)
+}
+
+/// Apply the limit of a class of requests, adding `RateLimit-*` headers to the response.
+async fn limit<B: MessageBody>(
+    class: LimitClass,
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    let decision = req
+        .app_data::<web::Data<AppState>>()
+        .and_then(|state| charge(state, req.request(), class, 1));
+
+    if let Some(response) = decision.as_ref().and_then(Decision::rejection) {
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+    match decision {
+        Some(decision) => {
+            let mut response = next.call(req).await?;
+            decision.add_headers(response.headers_mut());
+            Ok(response.map_into_left_body())
+        }
+        None => Ok(next.call(req).await?.map_into_left_body()),
+    }
+}
+
+/// Middleware applying the limit of link creation
+pub async fn limit_create<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    limit(LimitClass::Create, req, next).await
+}
+
+/// Apply the limit of bulk creation to a request creating `links` links, one token each.
+/// The request is to be rejected with [`Decision::rejection`] when it is not allowed, and
+/// the headers of the decision added to its response otherwise.
+pub fn limit_bulk_create(state: &AppState, req: &HttpRequest, links: usize) -> Option<Decision> {
+    let cost = u32::try_from(links).unwrap_or(u32::MAX);
+    charge(state, req, LimitClass::BulkCreate, cost)
+}
+
+/// Middleware applying the limit of the read endpoints
+pub async fn limit_info<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    limit(LimitClass::Info, req, next).await
+}
+
+/// Middleware applying the limit of redirects
+pub async fn limit_redirect<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    limit(LimitClass::Redirect, req, next).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use std::collections::HashSet;
+
+    fn limiter(burst: u32, per_minute: u32) -> RateLimiter {
+        RateLimiter::from_config(&RateLimitConfig {
+            enabled: true,
+            create: Some(RateLimit { burst, per_minute }),
+            ..Default::default()
+        })
+    }
+
+    fn client() -> ClientKey {
+        ClientKey::Ip("192.0.2.1".parse().unwrap())
+    }
+
+    #[test]
+    fn burst_then_refill() {
+        // One token every 2 seconds
+        let limiter = limiter(3, 30);
+        let start = Instant::now();
+
+        for remaining in [2, 1, 0] {
+            let decision = limiter.take(LimitClass::Create, client(), 1, start);
+            assert!(decision.allowed);
+            assert_eq!(decision.limit, 3);
+            assert_eq!(decision.remaining, remaining);
+        }
+        let decision = limiter.take(LimitClass::Create, client(), 1, start);
+        assert!(!decision.allowed);
+        assert_eq!(decision.retry_after, 2);
+        assert_eq!(decision.reset, 6);
+
+        let later = start + Duration::from_millis(1_500);
+        let decision = limiter.take(LimitClass::Create, client(), 1, later);
+        assert!(!decision.allowed);
+        assert_eq!(decision.retry_after, 1);
+
+        let later = start + Duration::from_secs(2);
+        assert!(limiter.take(LimitClass::Create, client(), 1, later).allowed);
+
+        // Refilling stops at the burst
+        let much_later = start + Duration::from_secs(3_600);
+        let decision = limiter.take(LimitClass::Create, client(), 1, much_later);
+        assert_eq!(decision.remaining, 2);
+        assert_eq!(decision.reset, 2);
+    }
+
+    #[test]
+    fn retry_after_is_at_least_one_second() {
+        let limiter = limiter(1, 6_000);
+        let start = Instant::now();
+        assert!(limiter.take(LimitClass::Create, client(), 1, start).allowed);
+        let decision = limiter.take(LimitClass::Create, client(), 1, start);
+        assert!(!decision.allowed);
+        assert_eq!(decision.retry_after, 1);
+
+        let mut headers = HeaderMap::new();
+        decision.add_headers(&mut headers);
+        assert_eq!(headers.get(header::RETRY_AFTER).unwrap(), "1");
+        assert_eq!(headers.get(RATELIMIT_LIMIT).unwrap(), "1");
+        assert_eq!(headers.get(RATELIMIT_REMAINING).unwrap(), "0");
+        assert_eq!(
+            decision.rejection().unwrap().status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+    }
+
+    #[test]
+    fn costs_above_burst_need_a_full_bucket() {
+        let limiter = limiter(10, 60);
+        let start = Instant::now();
+        assert!(
+            limiter
+                .take(LimitClass::Create, client(), 50, start)
+                .allowed
+        );
+        let decision = limiter.take(LimitClass::Create, client(), 50, start);
+        assert!(!decision.allowed);
+        assert_eq!(decision.retry_after, 10);
+    }
+
+    #[test]
+    fn classes_and_clients_have_separate_buckets() {
+        let limiter = limiter(1, 1);
+        let start = Instant::now();
+        assert!(limiter.take(LimitClass::Create, client(), 1, start).allowed);
+        assert!(!limiter.take(LimitClass::Create, client(), 1, start).allowed);
+        assert!(limiter.take(LimitClass::Info, client(), 1, start).allowed);
+        let other = ClientKey::Ip("192.0.2.2".parse().unwrap());
+        assert!(limiter.take(LimitClass::Create, other, 1, start).allowed);
+    }
+
+    #[test]
+    fn ipv6_clients_are_grouped_by_64() {
+        let limiter = limiter(1, 1);
+        let key = |peer: &str| {
+            let req = TestRequest::default()
+                .peer_addr(peer.parse().unwrap())
+                .to_http_request();
+            limiter.client_key(&req, None).unwrap()
+        };
+
+        let first = key("[2001:db8:1:2:aaaa::1]:1000");
+        assert_eq!(first, ClientKey::Ip("2001:db8:1:2::".parse().unwrap()));
+        assert_eq!(first, key("[2001:db8:1:2:ffff:ffff:ffff:ffff]:2000"));
+        assert_ne!(first, key("[2001:db8:1:3::1]:1000"));
+        assert_eq!(
+            key("198.51.100.7:1000"),
+            ClientKey::Ip("198.51.100.7".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn bearer_token_holders_share_a_bucket() {
+        let limiter = limiter(1, 1);
+        let req = TestRequest::default()
+            .peer_addr("198.51.100.7:1000".parse().unwrap())
+            .insert_header((header::AUTHORIZATION, "Bearer secret"))
+            .to_http_request();
+        assert_eq!(
+            limiter.client_key(&req, Some("secret")),
+            Some(ClientKey::Token)
+        );
+        assert_eq!(
+            limiter.client_key(&req, Some("other")),
+            Some(ClientKey::Ip("198.51.100.7".parse().unwrap()))
+        );
+    }
+
+    fn forwarded(peer: &str, headers: &[&str]) -> HttpRequest {
+        let mut req = TestRequest::default().peer_addr(peer.parse().unwrap());
+        for value in headers {
+            req = req.append_header(("X-Forwarded-For", *value));
+        }
+        req.to_http_request()
+    }
+
+    fn trusting(trusted_proxies: Option<usize>) -> RateLimiter {
+        RateLimiter::from_config(&RateLimitConfig {
+            enabled: true,
+            trust_forwarded_for: true,
+            trusted_proxies,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn forwarded_addresses_are_only_used_when_trusted() {
+        let req = forwarded("10.0.0.1:1000", &["203.0.113.9"]);
+        let ip = |addr: &str| Some(addr.parse::<IpAddr>().unwrap());
+
+        assert_eq!(limiter(1, 1).client_ip(&req), ip("10.0.0.1"));
+        assert_eq!(trusting(None).client_ip(&req), ip("203.0.113.9"));
+
+        // Ports and every header line are accepted
+        let req = forwarded(
+            "10.0.0.1:1000",
+            &["192.0.2.1", "198.51.100.7:443, [2001:db8::1]:80"],
+        );
+        assert_eq!(trusting(None).client_ip(&req), ip("2001:db8::1"));
+        assert_eq!(trusting(Some(2)).client_ip(&req), ip("198.51.100.7"));
+        assert_eq!(trusting(Some(3)).client_ip(&req), ip("192.0.2.1"));
+
+        // Without enough entries or a valid one, the request did not come through the proxies
+        assert_eq!(trusting(Some(4)).client_ip(&req), ip("10.0.0.1"));
+        assert_eq!(
+            trusting(None).client_ip(&forwarded("10.0.0.1:1000", &[])),
+            ip("10.0.0.1")
+        );
+        let req = forwarded("10.0.0.1:1000", &["unknown"]);
+        assert_eq!(trusting(None).client_ip(&req), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn spoofed_forwarded_entries_share_the_bucket() {
+        let limiter = trusting(None);
+        let mut keys = HashSet::new();
+        for spoofed in ["1.1.1.1", "2.2.2.2, 3.3.3.3", "2001:db8::1", "garbage"] {
+            // The proxy appends the address the request came from
+            let req = forwarded("10.0.0.1:1000", &[spoofed, "203.0.113.9"]);
+            keys.insert(limiter.client_key(&req, None).unwrap());
+            let req = forwarded("10.0.0.1:1000", &[&format!("{spoofed}, 203.0.113.9")]);
+            keys.insert(limiter.client_key(&req, None).unwrap());
+        }
+        assert_eq!(
+            keys,
+            HashSet::from([ClientKey::Ip("203.0.113.9".parse().unwrap())])
+        );
+
+        let start = Instant::now();
+        let key = limiter
+            .client_key(&forwarded("10.0.0.1:1000", &["1.1.1.1, 203.0.113.9"]), None)
+            .unwrap();
+        for _ in 0..20 {
+            limiter.take(LimitClass::Create, key.clone(), 1, start);
+        }
+        let key = limiter
+            .client_key(&forwarded("10.0.0.1:1000", &["4.4.4.4, 203.0.113.9"]), None)
+            .unwrap();
+        assert!(!limiter.take(LimitClass::Create, key, 1, start).allowed);
+    }
+
+    #[test]
+    fn full_buckets_are_pruned() {
+        let limiter = limiter(2, 60);
+        let start = Instant::now();
+        limiter.take(LimitClass::Create, client(), 1, start);
+        limiter.prune(start + Duration::from_millis(500));
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 1);
+        limiter.prune(start + Duration::from_secs(1));
+        assert!(limiter.buckets.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        assert!(!RateLimiter::from_config(&RateLimitConfig::default()).enabled);
+    }
+}
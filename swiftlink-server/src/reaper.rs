@@ -0,0 +1,99 @@
+use actix_web::rt;
+use log::{info, warn};
+use std::{sync::Arc, time::Duration};
+use swiftlink_api::LinkEventType;
+
+use crate::{config::ExpirationConfig, events::EventBus, storage::LinkStore, unix_now};
+
+/// Spawn the background task periodically removing expired links, publishing an event for
+/// each of them.
+pub fn spawn(store: Arc<dyn LinkStore>, config: &ExpirationConfig, events: EventBus) {
+    let period = Duration::from_secs(config.reaper_interval.unwrap_or(300).max(1));
+    let archive = config.archive;
+
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(period);
+        loop {
+            interval.tick().await;
+            reap(store.as_ref(), unix_now(), archive, &events).await;
+        }
+    });
+}
+
+/// Remove the links expired at `now`, publishing an event for each of them.
+async fn reap(store: &dyn LinkStore, now: i64, archive: bool, events: &EventBus) {
+    let purged = match store.purge_expired(now, archive).await {
+        Ok(purged) => purged,
+        Err(e) => {
+            warn!("Error purging expired links: {:?}", e);
+            return;
+        }
+    };
+    if purged.is_empty() {
+        return;
+    }
+
+    if archive {
+        info!("Archived {} expired link(s)", purged.len());
+    } else {
+        info!("Purged {} expired link(s)", purged.len());
+    }
+    for link in &purged {
+        events.publish(LinkEventType::Expired, &link.code, Some(&link.url));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{LinkRecord, SqliteStore};
+
+    fn link(code: &str, expires_at: Option<i64>) -> LinkRecord {
+        LinkRecord {
+            code: code.to_string(),
+            url: format!("https://example.com/{code}"),
+            created_at: 0,
+            expires_at,
+        }
+    }
+
+    #[actix_web::test]
+    async fn reap_removes_expired_links_and_publishes_events() {
+        for archive in [false, true] {
+            let store = SqliteStore::connect("sqlite::memory:", 1).await.unwrap();
+            store.migrate().await.unwrap();
+            for link in [
+                link("old", Some(100)),
+                link("due", Some(200)),
+                link("new", Some(300)),
+            ] {
+                store.insert_link(&link, &[]).await.unwrap();
+            }
+            store.insert_link(&link("kept", None), &[]).await.unwrap();
+
+            let events = EventBus::new(None);
+            let mut receiver = events.subscribe();
+            reap(&store, 200, archive, &events).await;
+
+            assert!(store.get_link("old").await.unwrap().is_none());
+            assert!(store.get_link("due").await.unwrap().is_none());
+            assert!(store.get_link("new").await.unwrap().is_some());
+            assert!(store.get_link("kept").await.unwrap().is_some());
+
+            let mut expired = [
+                receiver.recv().await.unwrap(),
+                receiver.recv().await.unwrap(),
+            ];
+            expired.sort_by(|a, b| a.code.cmp(&b.code));
+            assert!(
+                expired
+                    .iter()
+                    .all(|event| event.event == LinkEventType::Expired)
+            );
+            assert_eq!(expired[0].code, "due");
+            assert_eq!(expired[1].code, "old");
+            assert_eq!(expired[1].url.as_deref(), Some("https://example.com/old"));
+            assert!(receiver.try_recv().is_err());
+        }
+    }
+}
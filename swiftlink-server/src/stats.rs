@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+use swiftlink_api::request_types::{NamedCount, StatsBucket, StatsResponse, TimeBucketCount};
+use url::Url;
+
+use crate::storage::{Bucketing, ClickStats};
+
+/// Range used when a stats query does not specify one: the last 30 days
+pub const DEFAULT_RANGE: i64 = 30 * 86_400;
+
+/// Upper bound on the number of timeline buckets a single query may produce
+pub const MAX_BUCKETS: i64 = 1_000;
+
+/// Number of referrers listed in [`StatsResponse::top_referrers`]
+const TOP_REFERRERS: usize = 10;
+
+/// Bucket boundaries for a [`StatsBucket`]. Weeks start on Monday, and the Unix epoch
+/// was a Thursday, hence the 4 day offset.
+pub fn bucketing(bucket: StatsBucket) -> Bucketing {
+    match bucket {
+        StatsBucket::Hour => Bucketing {
+            width: 3_600,
+            offset: 0,
+        },
+        StatsBucket::Day => Bucketing {
+            width: 86_400,
+            offset: 0,
+        },
+        StatsBucket::Week => Bucketing {
+            width: 7 * 86_400,
+            offset: 4 * 86_400,
+        },
+    }
+}
+
+/// Check that `[from, to)` is a non-empty range whose timeline has at most [`MAX_BUCKETS`]
+/// buckets.
+pub fn check_range(from: i64, to: i64, bucket: StatsBucket) -> Result<(), &'static str> {
+    if from >= to {
+        return Err("from must be before to");
+    }
+    let bucketing = bucketing(bucket);
+    let start = bucketing.start_of(from).ok_or("from is out of range")?;
+    // Spans between arbitrary timestamps do not always fit in an i64
+    let buckets = (i128::from(to) - i128::from(start)) / i128::from(bucketing.width);
+    if buckets > i128::from(MAX_BUCKETS) {
+        return Err("Range spans too many buckets");
+    }
+    Ok(())
+}
+
+/// Classify a user agent string into a coarse browser family.
+pub fn user_agent_family(user_agent: Option<&str>) -> &'static str {
+    let Some(ua) = user_agent else {
+        return "Unknown";
+    };
+    let lower = ua.to_ascii_lowercase();
+
+    if ["bot", "spider", "crawl", "preview"]
+        .iter()
+        .any(|marker| lower.contains(marker))
+    {
+        "Bot"
+    } else if ua.contains("Edg/") {
+        "Edge"
+    } else if ua.contains("OPR/") || ua.contains("Opera") {
+        "Opera"
+    } else if ua.contains("Firefox/") || ua.contains("FxiOS") {
+        "Firefox"
+    } else if ua.contains("Chrome/") || ua.contains("CriOS") {
+        "Chrome"
+    } else if ua.contains("Safari/") {
+        "Safari"
+    } else if lower.starts_with("curl/") {
+        "curl"
+    } else if lower.starts_with("wget/") {
+        "Wget"
+    } else {
+        "Other"
+    }
+}
+
+/// Host of a referrer URL, `direct` if there is none.
+fn referrer_host(referrer: Option<&str>) -> String {
+    match referrer {
+        None | Some("") => "direct".to_string(),
+        Some(referrer) => Url::parse(referrer)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_else(|| referrer.to_string()),
+    }
+}
+
+/// Sum counts by name, most clicked first.
+fn ranked(counts: impl Iterator<Item = (String, i64)>) -> Vec<NamedCount> {
+    let mut merged: HashMap<String, u64> = HashMap::new();
+    for (name, clicks) in counts {
+        *merged.entry(name).or_default() += clicks as u64;
+    }
+
+    let mut ranked: Vec<NamedCount> = merged
+        .into_iter()
+        .map(|(name, clicks)| NamedCount { name, clicks })
+        .collect();
+    ranked.sort_by(|a, b| b.clicks.cmp(&a.clicks).then_with(|| a.name.cmp(&b.name)));
+    ranked
+}
+
+/// Turn raw aggregates into the API response, filling empty timeline buckets. The range has
+/// to be checked with [`check_range`] first.
+pub fn build_response(
+    code: String,
+    from: i64,
+    to: i64,
+    bucket: StatsBucket,
+    stats: ClickStats,
+) -> StatsResponse {
+    let bucketing = bucketing(bucket);
+    let counts: HashMap<i64, i64> = stats.timeline.into_iter().collect();
+    let timeline = (bucketing.start_of(from).unwrap_or(from)..to)
+        .step_by(bucketing.width as usize)
+        .map(|start| TimeBucketCount {
+            start,
+            clicks: counts.get(&start).copied().unwrap_or(0) as u64,
+        })
+        .collect();
+
+    let mut top_referrers = ranked(
+        stats
+            .referrers
+            .into_iter()
+            .map(|(referrer, clicks)| (referrer_host(referrer.as_deref()), clicks)),
+    );
+    top_referrers.truncate(TOP_REFERRERS);
+
+    let user_agents = ranked(
+        stats
+            .user_agents
+            .into_iter()
+            .map(|(ua, clicks)| (user_agent_family(ua.as_deref()).to_string(), clicks)),
+    );
+
+    StatsResponse {
+        code,
+        tag: None,
+        from,
+        to,
+        bucket,
+        total_clicks: stats.total as u64,
+        unique_visitors: stats.unique_visitors as u64,
+        timeline,
+        top_referrers,
+        user_agents,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::storage::{
+        ClickRecord, LinkFilter, LinkRecord, LinkStore, MemoryStore, SqliteStore,
+    };
+
+    const DAY: i64 = 86_400;
+
+    #[test]
+    fn weeks_start_on_monday() {
+        let week = bucketing(StatsBucket::Week);
+        // Monday 1970-01-05 and the Thursday before it
+        assert_eq!(week.start_of(4 * DAY), Some(4 * DAY));
+        assert_eq!(week.start_of(11 * DAY - 1), Some(4 * DAY));
+        assert_eq!(week.start_of(0), Some(-3 * DAY));
+        assert_eq!(week.start_of(-1), Some(-3 * DAY));
+        assert_eq!(bucketing(StatsBucket::Hour).start_of(-1), Some(-3_600));
+    }
+
+    #[test]
+    fn ranges_must_be_ordered() {
+        assert!(check_range(10, 10, StatsBucket::Hour).is_err());
+        assert!(check_range(10, 5, StatsBucket::Hour).is_err());
+        assert!(check_range(0, 3_600, StatsBucket::Hour).is_ok());
+    }
+
+    #[test]
+    fn ranges_are_limited_in_buckets() {
+        assert!(check_range(0, MAX_BUCKETS * 3_600, StatsBucket::Hour).is_ok());
+        assert!(check_range(0, (MAX_BUCKETS + 1) * 3_600, StatsBucket::Hour).is_err());
+        assert!(check_range(0, MAX_BUCKETS * DAY, StatsBucket::Day).is_ok());
+    }
+
+    #[test]
+    fn overflowing_ranges_are_rejected() {
+        assert_eq!(
+            check_range(i64::MIN, 0, StatsBucket::Hour),
+            Err("from is out of range")
+        );
+        assert_eq!(
+            check_range(i64::MIN, 0, StatsBucket::Week),
+            Err("from is out of range")
+        );
+        // The span itself does not fit in an i64
+        assert_eq!(
+            check_range(i64::MIN / 2, i64::MAX, StatsBucket::Hour),
+            Err("Range spans too many buckets")
+        );
+        assert_eq!(
+            check_range(0, i64::MAX, StatsBucket::Week),
+            Err("Range spans too many buckets")
+        );
+    }
+
+    #[test]
+    fn timeline_has_every_bucket() {
+        let stats = ClickStats {
+            total: 3,
+            timeline: vec![(DAY, 3)],
+            ..Default::default()
+        };
+        let response = build_response("abc".into(), 10, 3 * DAY, StatsBucket::Day, stats);
+        let timeline: Vec<(i64, u64)> = response
+            .timeline
+            .iter()
+            .map(|bucket| (bucket.start, bucket.clicks))
+            .collect();
+        assert_eq!(timeline, vec![(0, 0), (DAY, 3), (2 * DAY, 0)]);
+    }
+
+    #[test]
+    fn referrers_and_user_agents_are_grouped() {
+        let stats = ClickStats {
+            total: 4,
+            referrers: vec![
+                (Some("https://news.example/a".into()), 1),
+                (Some("https://news.example/b".into()), 2),
+                (None, 1),
+            ],
+            user_agents: vec![
+                (Some("curl/8.0".into()), 3),
+                (Some("Googlebot/2.1".into()), 1),
+            ],
+            ..Default::default()
+        };
+        let response = build_response("abc".into(), 0, DAY, StatsBucket::Day, stats);
+        let referrers: Vec<(&str, u64)> = response
+            .top_referrers
+            .iter()
+            .map(|count| (&*count.name, count.clicks))
+            .collect();
+        assert_eq!(referrers, vec![("news.example", 3), ("direct", 1)]);
+        let user_agents: Vec<(&str, u64)> = response
+            .user_agents
+            .iter()
+            .map(|count| (&*count.name, count.clicks))
+            .collect();
+        assert_eq!(user_agents, vec![("curl", 3), ("Bot", 1)]);
+    }
+
+    /// Timeline of the clicks at the given timestamps, as computed by a store
+    async fn timeline(
+        store: Arc<dyn LinkStore>,
+        clicked_at: &[i64],
+        bucket: StatsBucket,
+    ) -> Vec<(i64, i64)> {
+        let link = LinkRecord {
+            code: "abc".into(),
+            url: "https://example.com/".into(),
+            created_at: 0,
+            expires_at: None,
+        };
+        store.insert_link(&link, &[]).await.unwrap();
+        let clicks: Vec<ClickRecord> = clicked_at
+            .iter()
+            .map(|&clicked_at| ClickRecord {
+                code: "abc".into(),
+                clicked_at,
+                referrer: None,
+                user_agent: None,
+                client_ip: None,
+            })
+            .collect();
+        store.record_clicks(&clicks).await.unwrap();
+
+        let filter = LinkFilter {
+            codes: Some(vec!["abc".into()]),
+            ..Default::default()
+        };
+        let mut timeline = store
+            .click_stats(&filter, -30 * DAY, 30 * DAY, bucketing(bucket))
+            .await
+            .unwrap()
+            .timeline;
+        timeline.sort();
+        timeline
+    }
+
+    #[actix_web::test]
+    async fn sql_and_memory_buckets_agree() {
+        // Around the epoch and the first Monday, before and after the bucket offsets
+        let clicked_at = [-8 * DAY, -DAY - 1, -1, 0, 1, 4 * DAY - 1, 4 * DAY, 12 * DAY];
+        for bucket in [StatsBucket::Hour, StatsBucket::Day, StatsBucket::Week] {
+            let sqlite = SqliteStore::connect("sqlite::memory:", 1).await.unwrap();
+            sqlite.migrate().await.unwrap();
+
+            let memory = timeline(Arc::new(MemoryStore::default()), &clicked_at, bucket).await;
+            let sql = timeline(Arc::new(sqlite), &clicked_at, bucket).await;
+            assert_eq!(memory, sql);
+
+            let bucketing = bucketing(bucket);
+            for &clicked_at in &clicked_at {
+                let start = bucketing.start_of(clicked_at).unwrap();
+                assert!(sql.iter().any(|(bucket, _)| *bucket == start));
+            }
+        }
+    }
+}
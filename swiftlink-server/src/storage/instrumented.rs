@@ -0,0 +1,211 @@
+use async_trait::async_trait;
+use prometheus::{HistogramVec, IntGauge};
+use std::{future::Future, sync::Arc};
+
+use super::{
+    Bucketing, ClickRecord, ClickStats, DeliveryAttempt, DeliveryRecord, LinkChanges, LinkFilter,
+    LinkRecord, LinkStore, NewDelivery, PageRequest, PoolStats, StoreHealth, StoreResult,
+};
+
+/// Wraps another backend to record the duration of every operation, labelled with the name
+/// of the operation, and the number of operations in progress.
+pub struct InstrumentedStore {
+    inner: Arc<dyn LinkStore>,
+    durations: HistogramVec,
+    in_flight: IntGauge,
+}
+
+impl InstrumentedStore {
+    pub fn new(inner: Arc<dyn LinkStore>, durations: HistogramVec, in_flight: IntGauge) -> Self {
+        Self {
+            inner,
+            durations,
+            in_flight,
+        }
+    }
+
+    async fn timed<T>(&self, operation: &str, query: impl Future<Output = T>) -> T {
+        let _in_flight = InFlight::start(&self.in_flight);
+        let timer = self.durations.with_label_values(&[operation]).start_timer();
+        let result = query.await;
+        timer.observe_duration();
+        result
+    }
+}
+
+/// Operation counted in the in-flight gauge until dropped, even when its future is cancelled
+/// (e.g. on client disconnect).
+struct InFlight<'a>(&'a IntGauge);
+
+impl<'a> InFlight<'a> {
+    fn start(gauge: &'a IntGauge) -> Self {
+        gauge.inc();
+        Self(gauge)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+#[async_trait]
+impl LinkStore for InstrumentedStore {
+    async fn migrate(&self) -> StoreResult<()> {
+        self.timed("migrate", self.inner.migrate()).await
+    }
+
+    async fn health(&self) -> StoreResult<StoreHealth> {
+        self.timed("health", self.inner.health()).await
+    }
+
+    fn pool_stats(&self) -> Option<PoolStats> {
+        self.inner.pool_stats()
+    }
+
+    async fn find_code_by_url(&self, url: &str) -> StoreResult<Option<String>> {
+        self.timed("find_code_by_url", self.inner.find_code_by_url(url))
+            .await
+    }
+
+    async fn find_codes_by_urls(&self, urls: &[String]) -> StoreResult<Vec<(String, String)>> {
+        self.timed("find_codes_by_urls", self.inner.find_codes_by_urls(urls))
+            .await
+    }
+
+    async fn get_link(&self, code: &str) -> StoreResult<Option<LinkRecord>> {
+        self.timed("get_link", self.inner.get_link(code)).await
+    }
+
+    async fn find_codes_ignore_case(&self, codes: &[String]) -> StoreResult<Vec<String>> {
+        self.timed(
+            "find_codes_ignore_case",
+            self.inner.find_codes_ignore_case(codes),
+        )
+        .await
+    }
+
+    async fn insert_link(&self, link: &LinkRecord, tags: &[String]) -> StoreResult<()> {
+        self.timed("insert_link", self.inner.insert_link(link, tags))
+            .await
+    }
+
+    async fn insert_links(&self, links: &[(LinkRecord, Vec<String>)]) -> StoreResult<Vec<bool>> {
+        self.timed("insert_links", self.inner.insert_links(links))
+            .await
+    }
+
+    async fn update_link(
+        &self,
+        code: &str,
+        changes: &LinkChanges,
+        actor: &str,
+        edited_at: i64,
+    ) -> StoreResult<Option<LinkRecord>> {
+        self.timed(
+            "update_link",
+            self.inner.update_link(code, changes, actor, edited_at),
+        )
+        .await
+    }
+
+    async fn list_links(
+        &self,
+        filter: &LinkFilter,
+        page: &PageRequest,
+    ) -> StoreResult<Vec<LinkRecord>> {
+        self.timed("list_links", self.inner.list_links(filter, page))
+            .await
+    }
+
+    async fn link_tags(&self, codes: &[String]) -> StoreResult<Vec<(String, String)>> {
+        self.timed("link_tags", self.inner.link_tags(codes)).await
+    }
+
+    async fn tag_counts(&self) -> StoreResult<Vec<(String, i64)>> {
+        self.timed("tag_counts", self.inner.tag_counts()).await
+    }
+
+    async fn delete_link(&self, code: &str) -> StoreResult<bool> {
+        self.timed("delete_link", self.inner.delete_link(code))
+            .await
+    }
+
+    async fn find_codes(&self, filter: &LinkFilter) -> StoreResult<Vec<String>> {
+        self.timed("find_codes", self.inner.find_codes(filter))
+            .await
+    }
+
+    async fn delete_links(&self, filter: &LinkFilter) -> StoreResult<Vec<String>> {
+        self.timed("delete_links", self.inner.delete_links(filter))
+            .await
+    }
+
+    async fn purge_expired(&self, now: i64, archive: bool) -> StoreResult<Vec<LinkRecord>> {
+        self.timed("purge_expired", self.inner.purge_expired(now, archive))
+            .await
+    }
+
+    async fn next_counter(&self, name: &str) -> StoreResult<i64> {
+        self.timed("next_counter", self.inner.next_counter(name))
+            .await
+    }
+
+    async fn counter(&self, name: &str) -> StoreResult<i64> {
+        self.timed("counter", self.inner.counter(name)).await
+    }
+
+    async fn record_clicks(&self, clicks: &[ClickRecord]) -> StoreResult<()> {
+        self.timed("record_clicks", self.inner.record_clicks(clicks))
+            .await
+    }
+
+    async fn count_clicks(&self, codes: &[String]) -> StoreResult<Vec<(String, i64)>> {
+        self.timed("count_clicks", self.inner.count_clicks(codes))
+            .await
+    }
+
+    async fn click_stats(
+        &self,
+        filter: &LinkFilter,
+        from: i64,
+        to: i64,
+        bucketing: Bucketing,
+    ) -> StoreResult<ClickStats> {
+        self.timed(
+            "click_stats",
+            self.inner.click_stats(filter, from, to, bucketing),
+        )
+        .await
+    }
+
+    async fn insert_deliveries(&self, deliveries: &[NewDelivery]) -> StoreResult<()> {
+        self.timed(
+            "insert_deliveries",
+            self.inner.insert_deliveries(deliveries),
+        )
+        .await
+    }
+
+    async fn claim_due_deliveries(
+        &self,
+        now: i64,
+        lease_until: i64,
+        limit: u32,
+    ) -> StoreResult<Vec<DeliveryRecord>> {
+        self.timed(
+            "claim_due_deliveries",
+            self.inner.claim_due_deliveries(now, lease_until, limit),
+        )
+        .await
+    }
+
+    async fn record_delivery_attempt(&self, id: i64, attempt: &DeliveryAttempt) -> StoreResult<()> {
+        self.timed(
+            "record_delivery_attempt",
+            self.inner.record_delivery_attempt(id, attempt),
+        )
+        .await
+    }
+}
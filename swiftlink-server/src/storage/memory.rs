@@ -0,0 +1,425 @@
+use async_trait::async_trait;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    sync::RwLock,
+};
+
+use super::{
+    Bucketing, ClickRecord, ClickStats, DeliveryAttempt, DeliveryRecord, DeliveryStatus,
+    LinkChanges, LinkFilter, LinkRecord, LinkStore, NewDelivery, PageRequest, StoreError,
+    StoreHealth, StoreResult,
+};
+
+/// Tags of the links without any
+static NO_TAGS: BTreeSet<String> = BTreeSet::new();
+
+/// In-memory link storage. Nothing is persisted across restarts.
+#[derive(Default)]
+pub struct MemoryStore {
+    links: RwLock<HashMap<String, LinkRecord>>,
+    /// Tags of every link having some
+    tags: RwLock<HashMap<String, BTreeSet<String>>>,
+    expired_links: RwLock<Vec<LinkRecord>>,
+    clicks: RwLock<Vec<ClickRecord>>,
+    counters: RwLock<HashMap<String, i64>>,
+    /// (actor, edited_at, old link) for every edit
+    edits: RwLock<Vec<(String, i64, LinkRecord)>>,
+    /// Webhook deliveries, the id of each one being its index plus one
+    deliveries: RwLock<Vec<StoredDelivery>>,
+}
+
+struct StoredDelivery {
+    record: DeliveryRecord,
+    status: DeliveryStatus,
+    next_attempt_at: i64,
+}
+
+impl MemoryStore {
+    /// Codes of the links matching a filter
+    fn matching_codes(&self, filter: &LinkFilter) -> HashSet<String> {
+        let links = self.links.read().unwrap();
+        let tags = self.tags.read().unwrap();
+        links
+            .values()
+            .filter(|link| filter.matches(link, tags.get(&link.code).unwrap_or(&NO_TAGS)))
+            .map(|link| link.code.clone())
+            .collect()
+    }
+
+    /// Forget the tags of removed links
+    fn remove_tags<'a>(&self, codes: impl IntoIterator<Item = &'a String>) {
+        let mut tags = self.tags.write().unwrap();
+        for code in codes {
+            tags.remove(code);
+        }
+    }
+}
+
+#[async_trait]
+impl LinkStore for MemoryStore {
+    async fn migrate(&self) -> StoreResult<()> {
+        Ok(())
+    }
+
+    async fn health(&self) -> StoreResult<StoreHealth> {
+        Ok(StoreHealth {
+            pending_migrations: Vec::new(),
+            pool: None,
+        })
+    }
+
+    async fn find_code_by_url(&self, url: &str) -> StoreResult<Option<String>> {
+        let links = self.links.read().unwrap();
+        Ok(links
+            .values()
+            .find(|link| link.url == url && link.expires_at.is_none())
+            .map(|link| link.code.clone()))
+    }
+
+    async fn find_codes_by_urls(&self, urls: &[String]) -> StoreResult<Vec<(String, String)>> {
+        let urls: HashSet<&String> = urls.iter().collect();
+        let links = self.links.read().unwrap();
+        Ok(links
+            .values()
+            .filter(|link| urls.contains(&link.url) && link.expires_at.is_none())
+            .map(|link| (link.url.clone(), link.code.clone()))
+            .collect())
+    }
+
+    async fn get_link(&self, code: &str) -> StoreResult<Option<LinkRecord>> {
+        Ok(self.links.read().unwrap().get(code).cloned())
+    }
+
+    async fn find_codes_ignore_case(&self, codes: &[String]) -> StoreResult<Vec<String>> {
+        let codes: HashSet<String> = codes.iter().map(|code| code.to_lowercase()).collect();
+        let links = self.links.read().unwrap();
+        Ok(links
+            .keys()
+            .filter(|code| codes.contains(&code.to_lowercase()))
+            .cloned()
+            .collect())
+    }
+
+    async fn insert_link(&self, link: &LinkRecord, tags: &[String]) -> StoreResult<()> {
+        let mut links = self.links.write().unwrap();
+        if links.contains_key(&link.code) {
+            return Err(StoreError::CodeTaken);
+        }
+        links.insert(link.code.clone(), link.clone());
+        if !tags.is_empty() {
+            self.tags
+                .write()
+                .unwrap()
+                .insert(link.code.clone(), tags.iter().cloned().collect());
+        }
+        Ok(())
+    }
+
+    async fn insert_links(&self, links: &[(LinkRecord, Vec<String>)]) -> StoreResult<Vec<bool>> {
+        let mut stored = self.links.write().unwrap();
+        let mut stored_tags = self.tags.write().unwrap();
+        Ok(links
+            .iter()
+            .map(|(link, tags)| {
+                if stored.contains_key(&link.code) {
+                    return false;
+                }
+                stored.insert(link.code.clone(), link.clone());
+                if !tags.is_empty() {
+                    stored_tags.insert(link.code.clone(), tags.iter().cloned().collect());
+                }
+                true
+            })
+            .collect())
+    }
+
+    async fn update_link(
+        &self,
+        code: &str,
+        changes: &LinkChanges,
+        actor: &str,
+        edited_at: i64,
+    ) -> StoreResult<Option<LinkRecord>> {
+        let mut links = self.links.write().unwrap();
+        let Some(link) = links.get_mut(code) else {
+            return Ok(None);
+        };
+
+        let old = link.clone();
+        if let Some(url) = &changes.url {
+            link.url = url.clone();
+        }
+        if let Some(expires_at) = changes.expires_at {
+            link.expires_at = expires_at;
+        }
+
+        if changes.edits_link() {
+            self.edits
+                .write()
+                .unwrap()
+                .push((actor.to_string(), edited_at, old));
+        }
+        if changes.edits_tags() {
+            let mut tags = self.tags.write().unwrap();
+            let link_tags = tags.entry(code.to_string()).or_default();
+            if let Some(replaced) = &changes.tags {
+                *link_tags = replaced.iter().cloned().collect();
+            }
+            link_tags.extend(changes.add_tags.iter().cloned());
+            for tag in &changes.remove_tags {
+                link_tags.remove(tag);
+            }
+            if link_tags.is_empty() {
+                tags.remove(code);
+            }
+        }
+        Ok(Some(link.clone()))
+    }
+
+    async fn list_links(
+        &self,
+        filter: &LinkFilter,
+        page: &PageRequest,
+    ) -> StoreResult<Vec<LinkRecord>> {
+        let links = self.links.read().unwrap();
+        let tags = self.tags.read().unwrap();
+        let mut matching: Vec<&LinkRecord> = links
+            .values()
+            .filter(|link| filter.matches(link, tags.get(&link.code).unwrap_or(&NO_TAGS)))
+            .filter(|link| {
+                page.after.as_ref().is_none_or(|(created_at, code)| {
+                    let key = (link.created_at, &link.code);
+                    if page.descending {
+                        key < (*created_at, code)
+                    } else {
+                        key > (*created_at, code)
+                    }
+                })
+            })
+            .collect();
+
+        matching.sort_by(|a, b| (a.created_at, &a.code).cmp(&(b.created_at, &b.code)));
+        if page.descending {
+            matching.reverse();
+        }
+        Ok(matching
+            .into_iter()
+            .take(page.limit as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn link_tags(&self, codes: &[String]) -> StoreResult<Vec<(String, String)>> {
+        let tags = self.tags.read().unwrap();
+        Ok(codes
+            .iter()
+            .filter_map(|code| Some((code, tags.get(code)?)))
+            .flat_map(|(code, tags)| tags.iter().map(|tag| (code.clone(), tag.clone())))
+            .collect())
+    }
+
+    async fn tag_counts(&self) -> StoreResult<Vec<(String, i64)>> {
+        let mut counts: BTreeMap<String, i64> = BTreeMap::new();
+        for tags in self.tags.read().unwrap().values() {
+            for tag in tags {
+                *counts.entry(tag.clone()).or_insert(0) += 1;
+            }
+        }
+        Ok(counts.into_iter().collect())
+    }
+
+    async fn delete_link(&self, code: &str) -> StoreResult<bool> {
+        self.clicks
+            .write()
+            .unwrap()
+            .retain(|click| click.code != code);
+        self.tags.write().unwrap().remove(code);
+        self.edits
+            .write()
+            .unwrap()
+            .retain(|(_, _, link)| link.code != code);
+        Ok(self.links.write().unwrap().remove(code).is_some())
+    }
+
+    async fn find_codes(&self, filter: &LinkFilter) -> StoreResult<Vec<String>> {
+        Ok(self.matching_codes(filter).into_iter().collect())
+    }
+
+    async fn delete_links(&self, filter: &LinkFilter) -> StoreResult<Vec<String>> {
+        let removed = self.matching_codes(filter);
+        self.links
+            .write()
+            .unwrap()
+            .retain(|code, _| !removed.contains(code));
+        self.clicks
+            .write()
+            .unwrap()
+            .retain(|click| !removed.contains(&click.code));
+        self.edits
+            .write()
+            .unwrap()
+            .retain(|(_, _, link)| !removed.contains(&link.code));
+        self.remove_tags(&removed);
+        Ok(removed.into_iter().collect())
+    }
+
+    async fn purge_expired(&self, now: i64, archive: bool) -> StoreResult<Vec<LinkRecord>> {
+        let mut links = self.links.write().unwrap();
+        let expired: Vec<String> = links
+            .values()
+            .filter(|link| link.is_expired(now))
+            .map(|link| link.code.clone())
+            .collect();
+
+        self.clicks
+            .write()
+            .unwrap()
+            .retain(|click| !expired.contains(&click.code));
+        self.edits
+            .write()
+            .unwrap()
+            .retain(|(_, _, link)| !expired.contains(&link.code));
+        self.remove_tags(&expired);
+
+        let purged: Vec<LinkRecord> = expired
+            .iter()
+            .filter_map(|code| links.remove(code))
+            .collect();
+        if archive {
+            self.expired_links
+                .write()
+                .unwrap()
+                .extend(purged.iter().cloned());
+        }
+
+        Ok(purged)
+    }
+
+    async fn next_counter(&self, name: &str) -> StoreResult<i64> {
+        let mut counters = self.counters.write().unwrap();
+        let value = counters.entry(name.to_string()).or_insert(0);
+        *value += 1;
+        Ok(*value)
+    }
+
+    async fn counter(&self, name: &str) -> StoreResult<i64> {
+        Ok(self
+            .counters
+            .read()
+            .unwrap()
+            .get(name)
+            .copied()
+            .unwrap_or(0))
+    }
+
+    async fn record_clicks(&self, clicks: &[ClickRecord]) -> StoreResult<()> {
+        self.clicks.write().unwrap().extend_from_slice(clicks);
+        Ok(())
+    }
+
+    async fn count_clicks(&self, codes: &[String]) -> StoreResult<Vec<(String, i64)>> {
+        let codes: HashSet<&String> = codes.iter().collect();
+        let mut counts: HashMap<String, i64> = HashMap::new();
+        for click in self.clicks.read().unwrap().iter() {
+            if codes.contains(&click.code) {
+                *counts.entry(click.code.clone()).or_insert(0) += 1;
+            }
+        }
+        Ok(counts.into_iter().collect())
+    }
+
+    async fn click_stats(
+        &self,
+        filter: &LinkFilter,
+        from: i64,
+        to: i64,
+        bucketing: Bucketing,
+    ) -> StoreResult<ClickStats> {
+        let codes = self.matching_codes(filter);
+        let clicks = self.clicks.read().unwrap();
+        let in_range = clicks
+            .iter()
+            .filter(|click| codes.contains(&click.code) && (from..to).contains(&click.clicked_at));
+
+        let mut visitors = HashSet::new();
+        let mut timeline = BTreeMap::new();
+        let mut referrers = HashMap::new();
+        let mut user_agents = HashMap::new();
+        let mut total = 0;
+        for click in in_range {
+            total += 1;
+            visitors.insert((&click.client_ip, &click.user_agent));
+            *timeline
+                .entry(
+                    bucketing
+                        .start_of(click.clicked_at)
+                        .unwrap_or(click.clicked_at),
+                )
+                .or_insert(0) += 1;
+            *referrers.entry(click.referrer.clone()).or_insert(0) += 1;
+            *user_agents.entry(click.user_agent.clone()).or_insert(0) += 1;
+        }
+
+        Ok(ClickStats {
+            total,
+            unique_visitors: visitors.len() as i64,
+            timeline: timeline.into_iter().collect(),
+            referrers: referrers.into_iter().collect(),
+            user_agents: user_agents.into_iter().collect(),
+        })
+    }
+
+    async fn insert_deliveries(&self, deliveries: &[NewDelivery]) -> StoreResult<()> {
+        let mut stored = self.deliveries.write().unwrap();
+        for delivery in deliveries {
+            let id = stored.len() as i64 + 1;
+            stored.push(StoredDelivery {
+                record: DeliveryRecord {
+                    id,
+                    webhook_url: delivery.webhook_url.clone(),
+                    event: delivery.event.clone(),
+                    code: delivery.code.clone(),
+                    payload: delivery.payload.clone(),
+                    attempts: 0,
+                },
+                status: DeliveryStatus::Pending,
+                next_attempt_at: delivery.created_at,
+            });
+        }
+        Ok(())
+    }
+
+    async fn claim_due_deliveries(
+        &self,
+        now: i64,
+        lease_until: i64,
+        limit: u32,
+    ) -> StoreResult<Vec<DeliveryRecord>> {
+        let mut stored = self.deliveries.write().unwrap();
+        let mut due: Vec<&mut StoredDelivery> = stored
+            .iter_mut()
+            .filter(|delivery| {
+                delivery.status == DeliveryStatus::Pending && delivery.next_attempt_at <= now
+            })
+            .collect();
+        due.sort_by_key(|delivery| (delivery.next_attempt_at, delivery.record.id));
+        Ok(due
+            .into_iter()
+            .take(limit as usize)
+            .map(|delivery| {
+                delivery.next_attempt_at = lease_until;
+                delivery.record.clone()
+            })
+            .collect())
+    }
+
+    async fn record_delivery_attempt(&self, id: i64, attempt: &DeliveryAttempt) -> StoreResult<()> {
+        let mut stored = self.deliveries.write().unwrap();
+        if let Some(delivery) = stored.get_mut(id as usize - 1) {
+            delivery.record.attempts += 1;
+            delivery.status = attempt.status;
+            delivery.next_attempt_at = attempt.next_attempt_at;
+        }
+        Ok(())
+    }
+}
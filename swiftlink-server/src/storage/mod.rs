@@ -0,0 +1,824 @@
+//! Storage backends for short links.
+//!
+//! Handlers only talk to the [`LinkStore`] trait, so adding a backend (or a mock for tests)
+//! does not require touching every handler.
+
+use async_trait::async_trait;
+use std::{collections::BTreeSet, sync::Arc};
+use thiserror::Error;
+use url::Url;
+
+use crate::config::{DatabaseConfig, DatabaseType};
+
+mod instrumented;
+mod memory;
+mod postgres;
+mod sql;
+mod sqlite;
+
+pub use instrumented::InstrumentedStore;
+pub use memory::MemoryStore;
+pub use postgres::PostgresStore;
+pub use sqlite::SqliteStore;
+
+pub type StoreResult<T> = Result<T, StoreError>;
+
+/// Number of rows bound in a single statement by batch operations, staying well below the
+/// bind parameter limits of every backend
+const BATCH_SIZE: usize = 1000;
+
+#[derive(Debug, Error)]
+pub enum StoreError {
+    #[error("Database error: {0}")]
+    Database(sqlx::Error),
+
+    /// A unique constraint was violated
+    #[error("Unique constraint violation")]
+    UniqueViolation,
+
+    /// A link with the same code already exists
+    #[error("Code is already taken")]
+    CodeTaken,
+
+    #[error("Migration error: {0}")]
+    Migration(sqlx::migrate::MigrateError),
+
+    /// The database has migrations applied that this binary does not know about
+    #[error("Database schema version {0} is newer than this binary supports")]
+    SchemaTooNew(i64),
+
+    /// The database configuration lacks a required setting
+    #[error("Invalid database configuration: {0}")]
+    InvalidConfig(&'static str),
+
+    /// The code generator only produced reserved codes
+    #[error("No code that is not reserved was generated after {0} attempts")]
+    CodesExhausted(u32),
+}
+
+impl From<sqlx::Error> for StoreError {
+    fn from(e: sqlx::Error) -> Self {
+        match &e {
+            sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+                StoreError::UniqueViolation
+            }
+            _ => StoreError::Database(e),
+        }
+    }
+}
+
+impl StoreError {
+    /// Report unique violations on `links` as taken codes, the primary key being
+    /// the only unique constraint of the table.
+    fn on_code(self) -> Self {
+        match self {
+            StoreError::UniqueViolation => StoreError::CodeTaken,
+            e => e,
+        }
+    }
+}
+
+impl From<sqlx::migrate::MigrateError> for StoreError {
+    fn from(e: sqlx::migrate::MigrateError) -> Self {
+        match e {
+            sqlx::migrate::MigrateError::VersionMissing(version) => {
+                StoreError::SchemaTooNew(version)
+            }
+            _ => StoreError::Migration(e),
+        }
+    }
+}
+
+/// A row of the `links` table
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct LinkRecord {
+    pub code: String,
+    pub url: String,
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+}
+
+impl LinkRecord {
+    /// Whether the link has expired at the given Unix timestamp
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// Lowercased destination host of a URL, stored alongside links for filtering
+pub fn url_host(url: &str) -> Option<String> {
+    Url::parse(url).ok()?.host_str().map(str::to_lowercase)
+}
+
+/// `LIKE` pattern matching any lowercased string containing `needle`, with `\` as the
+/// escape character
+fn contains_pattern(needle: &str) -> String {
+    let mut pattern = String::from("%");
+    for c in needle.to_lowercase().chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+/// Criteria selecting links. Only links matching every set field are selected.
+#[derive(Debug, Default, Clone)]
+pub struct LinkFilter {
+    /// Codes of the links, compared exactly
+    pub codes: Option<Vec<String>>,
+    /// Destination host, compared case-insensitively
+    pub host: Option<String>,
+    /// Substring of the destination URL, compared case-insensitively
+    pub url_contains: Option<String>,
+    /// Links created at or after this Unix timestamp
+    pub created_after: Option<i64>,
+    /// Links created before this Unix timestamp
+    pub created_before: Option<i64>,
+    /// Tag of the links, compared exactly since tags are stored lowercased
+    pub tag: Option<String>,
+}
+
+impl LinkFilter {
+    /// Whether a link with the given tags matches the filter, for backends that cannot filter
+    /// in queries
+    pub fn matches(&self, link: &LinkRecord, tags: &BTreeSet<String>) -> bool {
+        self.codes
+            .as_ref()
+            .is_none_or(|codes| codes.contains(&link.code))
+            && self
+                .host
+                .as_ref()
+                .is_none_or(|host| url_host(&link.url).as_ref() == Some(&host.to_lowercase()))
+            && self
+                .url_contains
+                .as_ref()
+                .is_none_or(|needle| link.url.to_lowercase().contains(&needle.to_lowercase()))
+            && self
+                .created_after
+                .is_none_or(|after| link.created_at >= after)
+            && self
+                .created_before
+                .is_none_or(|before| link.created_at < before)
+            && self.tag.as_ref().is_none_or(|tag| tags.contains(tag))
+    }
+}
+
+/// A page of links in creation order, ties being broken by code
+#[derive(Debug, Clone)]
+pub struct PageRequest {
+    /// `(created_at, code)` of the last link of the previous page
+    pub after: Option<(i64, String)>,
+    pub limit: u32,
+    /// Newest links first
+    pub descending: bool,
+}
+
+/// Changes to apply to a link. `None` fields are left untouched.
+#[derive(Debug, Default)]
+pub struct LinkChanges {
+    pub url: Option<String>,
+    /// `Some(None)` removes the expiration
+    pub expires_at: Option<Option<i64>>,
+    /// Replaces every tag of the link, before `add_tags` and `remove_tags` are applied
+    pub tags: Option<Vec<String>>,
+    pub add_tags: Vec<String>,
+    pub remove_tags: Vec<String>,
+}
+
+impl LinkChanges {
+    /// Whether the URL or expiration changes, which is recorded in the edit history
+    pub fn edits_link(&self) -> bool {
+        self.url.is_some() || self.expires_at.is_some()
+    }
+
+    /// Whether the tags of the link change
+    pub fn edits_tags(&self) -> bool {
+        self.tags.is_some() || !self.add_tags.is_empty() || !self.remove_tags.is_empty()
+    }
+}
+
+/// A row of the `clicks` table, recorded on every redirect
+#[derive(Debug, Clone)]
+pub struct ClickRecord {
+    pub code: String,
+    pub clicked_at: i64,
+    pub referrer: Option<String>,
+    pub user_agent: Option<String>,
+    /// Anonymized client IP address
+    pub client_ip: Option<String>,
+}
+
+/// Time bucketing of click timelines: buckets start at `offset + k * width` seconds
+#[derive(Debug, Clone, Copy)]
+pub struct Bucketing {
+    pub width: i64,
+    pub offset: i64,
+}
+
+impl Bucketing {
+    /// Start of the bucket containing the given Unix timestamp, `None` if it does not fit in
+    /// an `i64`
+    pub fn start_of(&self, timestamp: i64) -> Option<i64> {
+        timestamp
+            .checked_sub(self.offset)?
+            .div_euclid(self.width)
+            .checked_mul(self.width)?
+            .checked_add(self.offset)
+    }
+}
+
+/// Raw click aggregates of a link over a time range
+#[derive(Debug, Default)]
+pub struct ClickStats {
+    pub total: i64,
+    /// Distinct (anonymized IP, user agent) pairs
+    pub unique_visitors: i64,
+    /// (bucket start, clicks) for every non-empty bucket
+    pub timeline: Vec<(i64, i64)>,
+    /// (referrer, clicks) for every distinct referrer
+    pub referrers: Vec<(Option<String>, i64)>,
+    /// (user agent, clicks) for every distinct user agent
+    pub user_agents: Vec<(Option<String>, i64)>,
+}
+
+/// State of a webhook delivery
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    /// Waiting for its next attempt
+    Pending,
+    Delivered,
+    /// Given up after too many attempts
+    Failed,
+}
+
+impl DeliveryStatus {
+    /// Value of the `status` column
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+}
+
+/// A notification queued for a webhook
+#[derive(Debug, Clone)]
+pub struct NewDelivery {
+    pub webhook_url: String,
+    pub event: String,
+    pub code: String,
+    /// JSON body of the request
+    pub payload: String,
+    pub created_at: i64,
+}
+
+/// A pending row of the `webhook_deliveries` table
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct DeliveryRecord {
+    pub id: i64,
+    pub webhook_url: String,
+    pub event: String,
+    pub code: String,
+    pub payload: String,
+    /// Attempts made so far
+    pub attempts: i32,
+}
+
+/// Outcome of one attempt to deliver a notification
+#[derive(Debug, Clone)]
+pub struct DeliveryAttempt {
+    pub status: DeliveryStatus,
+    pub attempted_at: i64,
+    /// When to try again, for deliveries still pending
+    pub next_attempt_at: i64,
+    /// HTTP status of the response, if one was received
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+}
+
+/// Usage of a connection pool at one point in time
+#[derive(Debug, Clone, Copy)]
+pub struct PoolStats {
+    /// Open connections, idle or in use
+    pub size: u32,
+    /// Open connections not in use
+    pub idle: u32,
+    /// Largest number of connections the pool may open
+    pub max_size: u32,
+}
+
+impl PoolStats {
+    fn of<DB: sqlx::Database>(pool: &sqlx::Pool<DB>) -> Self {
+        Self {
+            size: pool.size(),
+            idle: pool.num_idle() as u32,
+            max_size: pool.options().get_max_connections(),
+        }
+    }
+
+    /// Share of the pool in use, between 0 and 1
+    pub fn saturation(&self) -> f64 {
+        if self.max_size == 0 {
+            return 0.0;
+        }
+        f64::from(self.size.saturating_sub(self.idle)) / f64::from(self.max_size)
+    }
+}
+
+/// State of a backend as seen by the readiness probe
+#[derive(Debug, Clone)]
+pub struct StoreHealth {
+    /// Versions of the migrations known to this binary but not applied to the database
+    pub pending_migrations: Vec<i64>,
+    /// Usage of the connection pool, `None` for backends without one
+    pub pool: Option<PoolStats>,
+}
+
+/// Operations every storage backend has to provide.
+#[async_trait]
+pub trait LinkStore: Send + Sync {
+    /// Apply all pending schema migrations, recording the applied versions.
+    ///
+    /// Fails with [`StoreError::SchemaTooNew`] if the database was migrated by a newer binary.
+    async fn migrate(&self) -> StoreResult<()>;
+
+    /// Check that the database answers queries, and report pending migrations and pool usage.
+    async fn health(&self) -> StoreResult<StoreHealth>;
+
+    /// Current usage of the connection pool, `None` for backends without one.
+    fn pool_stats(&self) -> Option<PoolStats> {
+        None
+    }
+
+    /// Look up the code of an already shortened URL that never expires.
+    async fn find_code_by_url(&self, url: &str) -> StoreResult<Option<String>>;
+
+    /// Look up the codes of already shortened URLs that never expire, as `(url, code)` pairs.
+    async fn find_codes_by_urls(&self, urls: &[String]) -> StoreResult<Vec<(String, String)>>;
+
+    /// Fetch a link by its code.
+    async fn get_link(&self, code: &str) -> StoreResult<Option<LinkRecord>>;
+
+    /// Return the existing codes among `codes`, compared case-insensitively.
+    async fn find_codes_ignore_case(&self, codes: &[String]) -> StoreResult<Vec<String>>;
+
+    /// Insert a new link with its tags. Returns [`StoreError::CodeTaken`] if the code is taken.
+    async fn insert_link(&self, link: &LinkRecord, tags: &[String]) -> StoreResult<()>;
+
+    /// Insert new links with their tags in a single transaction, skipping those whose code is
+    /// taken. Returns whether each link was inserted, in order.
+    async fn insert_links(&self, links: &[(LinkRecord, Vec<String>)]) -> StoreResult<Vec<bool>>;
+
+    /// Apply changes to a link, recording the previous URL and expiration in the `link_edits`
+    /// table when they change. Returns the updated link, or `None` if there is no link with
+    /// this code.
+    async fn update_link(
+        &self,
+        code: &str,
+        changes: &LinkChanges,
+        actor: &str,
+        edited_at: i64,
+    ) -> StoreResult<Option<LinkRecord>>;
+
+    /// List the links matching a filter, one page at a time.
+    async fn list_links(
+        &self,
+        filter: &LinkFilter,
+        page: &PageRequest,
+    ) -> StoreResult<Vec<LinkRecord>>;
+
+    /// Return the tags of the given links as `(code, tag)` pairs, the tags of each link in
+    /// alphabetical order.
+    async fn link_tags(&self, codes: &[String]) -> StoreResult<Vec<(String, String)>>;
+
+    /// Count the links of every tag, as `(tag, links)` pairs sorted by tag.
+    async fn tag_counts(&self) -> StoreResult<Vec<(String, i64)>>;
+
+    /// Delete a link (and its recorded clicks, tags and edit history) by its code. Returns whether
+    /// a link was removed.
+    async fn delete_link(&self, code: &str) -> StoreResult<bool>;
+
+    /// Return the codes of every link matching a filter.
+    async fn find_codes(&self, filter: &LinkFilter) -> StoreResult<Vec<String>>;
+
+    /// Delete every link matching a filter, and their recorded clicks, tags and edit history, in
+    /// a single transaction. Returns the codes of the deleted links.
+    async fn delete_links(&self, filter: &LinkFilter) -> StoreResult<Vec<String>>;
+
+    /// Remove every link that expired at or before `now`, with its clicks, tags and edit history,
+    /// moving them to the `expired_links` table first if `archive` is set. Returns the removed
+    /// links.
+    async fn purge_expired(&self, now: i64, archive: bool) -> StoreResult<Vec<LinkRecord>>;
+
+    /// Increment the named counter and return its new value, starting at 1.
+    async fn next_counter(&self, name: &str) -> StoreResult<i64>;
+
+    /// Return the value of the named counter, 0 if it was never incremented.
+    async fn counter(&self, name: &str) -> StoreResult<i64>;
+
+    /// Insert a batch of click events.
+    async fn record_clicks(&self, clicks: &[ClickRecord]) -> StoreResult<()>;
+
+    /// Count all recorded clicks of the given links, as `(code, clicks)` pairs. Links without
+    /// clicks may be omitted.
+    async fn count_clicks(&self, codes: &[String]) -> StoreResult<Vec<(String, i64)>>;
+
+    /// Aggregate the clicks of the links matching a filter in the `[from, to)` range.
+    async fn click_stats(
+        &self,
+        filter: &LinkFilter,
+        from: i64,
+        to: i64,
+        bucketing: Bucketing,
+    ) -> StoreResult<ClickStats>;
+
+    /// Queue webhook notifications in the `webhook_deliveries` table.
+    async fn insert_deliveries(&self, deliveries: &[NewDelivery]) -> StoreResult<()>;
+
+    /// Claim up to `limit` pending deliveries due at `now`, oldest first, by postponing them to
+    /// `lease_until`, so that other servers sharing the database do not send them too. They
+    /// are due again then, unless an attempt is recorded meanwhile.
+    async fn claim_due_deliveries(
+        &self,
+        now: i64,
+        lease_until: i64,
+        limit: u32,
+    ) -> StoreResult<Vec<DeliveryRecord>>;
+
+    /// Record an attempt to deliver a notification, counting it in `attempts`.
+    async fn record_delivery_attempt(&self, id: i64, attempt: &DeliveryAttempt) -> StoreResult<()>;
+}
+
+/// Connect to the backend described by the database configuration.
+pub async fn connect(db_config: &DatabaseConfig) -> StoreResult<Arc<dyn LinkStore>> {
+    let max_connections = db_config.max_connections.unwrap_or(5);
+
+    let store: Arc<dyn LinkStore> = match db_config.database_type {
+        DatabaseType::Postgres => {
+            let database_url = format!(
+                "postgres://{}:{}@{}:{}/{}",
+                db_config.username.as_deref().unwrap_or("postgres"),
+                db_config.password.as_deref().unwrap_or_default(),
+                db_config.host.as_deref().unwrap_or("localhost"),
+                db_config.port.unwrap_or(5432),
+                db_config.database.as_deref().unwrap_or("swiftlink_db"),
+            );
+            Arc::new(PostgresStore::connect(&database_url, max_connections).await?)
+        }
+        DatabaseType::Sqlite => {
+            let database_url = db_config
+                .database
+                .as_deref()
+                .ok_or(StoreError::InvalidConfig(
+                    "database must be set to the path of the SQLite database",
+                ))?;
+            Arc::new(SqliteStore::connect(database_url, max_connections).await?)
+        }
+        DatabaseType::Memory => Arc::new(MemoryStore::default()),
+    };
+
+    Ok(store)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stores that have to behave alike, SQLite running in memory
+    async fn stores() -> Vec<Arc<dyn LinkStore>> {
+        let sqlite = SqliteStore::connect("sqlite::memory:", 1).await.unwrap();
+        sqlite.migrate().await.unwrap();
+        vec![Arc::new(MemoryStore::default()), Arc::new(sqlite)]
+    }
+
+    fn link(code: &str, url: &str, created_at: i64) -> LinkRecord {
+        LinkRecord {
+            code: code.to_string(),
+            url: url.to_string(),
+            created_at,
+            expires_at: None,
+        }
+    }
+
+    fn tags(names: &[&str]) -> Vec<String> {
+        names.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    #[actix_web::test]
+    async fn insert_then_look_up() {
+        for store in stores().await {
+            store
+                .insert_link(&link("abc", "https://example.com/", 10), &[])
+                .await
+                .unwrap();
+
+            let found = store.get_link("abc").await.unwrap().unwrap();
+            assert_eq!(found.url, "https://example.com/");
+            assert_eq!(found.created_at, 10);
+            assert!(store.get_link("ABC").await.unwrap().is_none());
+            assert_eq!(
+                store
+                    .find_code_by_url("https://example.com/")
+                    .await
+                    .unwrap(),
+                Some("abc".to_string())
+            );
+            assert_eq!(
+                store.find_codes_ignore_case(&["ABC".into()]).await.unwrap(),
+                vec!["abc".to_string()]
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn taken_codes_are_reported() {
+        for store in stores().await {
+            store
+                .insert_link(&link("abc", "https://example.com/a", 10), &[])
+                .await
+                .unwrap();
+            let result = store
+                .insert_link(&link("abc", "https://example.com/b", 11), &[])
+                .await;
+            assert!(matches!(result, Err(StoreError::CodeTaken)));
+
+            let inserted = store
+                .insert_links(&[
+                    (link("abc", "https://example.com/c", 12), Vec::new()),
+                    (link("def", "https://example.com/d", 12), Vec::new()),
+                ])
+                .await
+                .unwrap();
+            assert_eq!(inserted, vec![false, true]);
+        }
+    }
+
+    #[actix_web::test]
+    async fn delete_removes_link_and_clicks() {
+        for store in stores().await {
+            store
+                .insert_link(&link("abc", "https://example.com/", 10), &[])
+                .await
+                .unwrap();
+            store
+                .record_clicks(&[ClickRecord {
+                    code: "abc".into(),
+                    clicked_at: 20,
+                    referrer: None,
+                    user_agent: None,
+                    client_ip: None,
+                }])
+                .await
+                .unwrap();
+
+            assert!(store.delete_link("abc").await.unwrap());
+            assert!(!store.delete_link("abc").await.unwrap());
+            assert!(store.get_link("abc").await.unwrap().is_none());
+            assert!(
+                store
+                    .count_clicks(&["abc".into()])
+                    .await
+                    .unwrap()
+                    .is_empty()
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn counters_start_at_one() {
+        for store in stores().await {
+            assert_eq!(store.counter("a").await.unwrap(), 0);
+            assert_eq!(store.next_counter("a").await.unwrap(), 1);
+            assert_eq!(store.next_counter("a").await.unwrap(), 2);
+            assert_eq!(store.next_counter("b").await.unwrap(), 1);
+            assert_eq!(store.counter("a").await.unwrap(), 2);
+        }
+    }
+
+    #[actix_web::test]
+    async fn update_link_sets_adds_and_removes_tags() {
+        for store in stores().await {
+            store
+                .insert_link(&link("abc", "https://example.com/", 10), &tags(&["a", "b"]))
+                .await
+                .unwrap();
+            let update = |changes: LinkChanges| {
+                let store = store.clone();
+                async move {
+                    store
+                        .update_link("abc", &changes, "test", 20)
+                        .await
+                        .unwrap()
+                        .unwrap();
+                    store
+                        .link_tags(&["abc".into()])
+                        .await
+                        .unwrap()
+                        .into_iter()
+                        .map(|(_, tag)| tag)
+                        .collect::<Vec<_>>()
+                }
+            };
+
+            // Adding a tag the link has, or removing one it has not, changes nothing else
+            let changes = LinkChanges {
+                add_tags: tags(&["c", "a"]),
+                remove_tags: tags(&["b", "z"]),
+                ..Default::default()
+            };
+            assert_eq!(update(changes).await, tags(&["a", "c"]));
+
+            // Tags are replaced before the others are added and removed
+            let changes = LinkChanges {
+                tags: Some(tags(&["x", "y"])),
+                add_tags: tags(&["a"]),
+                remove_tags: tags(&["y"]),
+                ..Default::default()
+            };
+            assert_eq!(update(changes).await, tags(&["a", "x"]));
+
+            // Changing only the URL keeps the tags
+            let changes = LinkChanges {
+                url: Some("https://example.com/new".into()),
+                ..Default::default()
+            };
+            assert_eq!(update(changes).await, tags(&["a", "x"]));
+
+            let changes = LinkChanges {
+                tags: Some(Vec::new()),
+                ..Default::default()
+            };
+            assert!(update(changes).await.is_empty());
+            assert!(store.tag_counts().await.unwrap().is_empty());
+
+            let changes = LinkChanges {
+                add_tags: tags(&["a"]),
+                ..Default::default()
+            };
+            assert!(
+                store
+                    .update_link("missing", &changes, "test", 20)
+                    .await
+                    .unwrap()
+                    .is_none()
+            );
+            assert!(
+                store
+                    .link_tags(&["missing".into()])
+                    .await
+                    .unwrap()
+                    .is_empty()
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn tag_counts_count_links() {
+        for store in stores().await {
+            store
+                .insert_link(
+                    &link("abc", "https://example.com/a", 10),
+                    &tags(&["a", "b"]),
+                )
+                .await
+                .unwrap();
+            store
+                .insert_link(&link("def", "https://example.com/d", 10), &tags(&["b"]))
+                .await
+                .unwrap();
+            assert_eq!(
+                store.tag_counts().await.unwrap(),
+                vec![("a".to_string(), 1), ("b".to_string(), 2)]
+            );
+            store.delete_link("abc").await.unwrap();
+            assert_eq!(
+                store.tag_counts().await.unwrap(),
+                vec![("b".to_string(), 1)]
+            );
+        }
+    }
+
+    fn deliveries(count: usize) -> Vec<NewDelivery> {
+        (0..count)
+            .map(|i| NewDelivery {
+                webhook_url: "https://example.com/hook".into(),
+                event: "created".into(),
+                code: format!("c{i}"),
+                payload: "{}".into(),
+                created_at: 10 + i as i64,
+            })
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn claimed_deliveries_are_due_again_after_the_lease() {
+        for store in stores().await {
+            store.insert_deliveries(&deliveries(3)).await.unwrap();
+            assert!(
+                store
+                    .claim_due_deliveries(9, 100, 10)
+                    .await
+                    .unwrap()
+                    .is_empty()
+            );
+
+            let claimed = store.claim_due_deliveries(20, 100, 2).await.unwrap();
+            let codes: Vec<&str> = claimed.iter().map(|d| d.code.as_str()).collect();
+            assert_eq!(codes, ["c0", "c1"]);
+            let claimed = store.claim_due_deliveries(20, 100, 10).await.unwrap();
+            assert_eq!(claimed.len(), 1);
+            assert_eq!(claimed[0].code, "c2");
+            assert!(
+                store
+                    .claim_due_deliveries(99, 200, 10)
+                    .await
+                    .unwrap()
+                    .is_empty()
+            );
+
+            // Recorded attempts replace the lease
+            let delivered = DeliveryAttempt {
+                status: DeliveryStatus::Delivered,
+                attempted_at: 30,
+                next_attempt_at: 30,
+                response_status: Some(200),
+                error: None,
+            };
+            store
+                .record_delivery_attempt(claimed[0].id, &delivered)
+                .await
+                .unwrap();
+            let retry = DeliveryAttempt {
+                status: DeliveryStatus::Pending,
+                next_attempt_at: 40,
+                response_status: Some(500),
+                ..delivered
+            };
+            let first = store.claim_due_deliveries(100, 200, 10).await.unwrap();
+            assert_eq!(first.len(), 2, "leases of unrecorded attempts expire");
+            store
+                .record_delivery_attempt(first[0].id, &retry)
+                .await
+                .unwrap();
+            let again = store.claim_due_deliveries(40, 300, 10).await.unwrap();
+            assert_eq!(again.len(), 1);
+            assert_eq!(again[0].id, first[0].id);
+            assert_eq!(again[0].attempts, 1);
+        }
+    }
+
+    #[actix_web::test]
+    async fn servers_never_claim_the_same_delivery() {
+        // Two stores on one database, as two servers would be
+        let url = "sqlite:file:claims?mode=memory&cache=shared";
+        let first = SqliteStore::connect(url, 2).await.unwrap();
+        first.migrate().await.unwrap();
+        let second = SqliteStore::connect(url, 2).await.unwrap();
+        first.insert_deliveries(&deliveries(200)).await.unwrap();
+
+        let mut ids = Vec::new();
+        loop {
+            let (a, b) = tokio::join!(
+                first.claim_due_deliveries(1000, 2000, 30),
+                second.claim_due_deliveries(1000, 2000, 30)
+            );
+            let (a, b) = (a.unwrap(), b.unwrap());
+            if a.is_empty() && b.is_empty() {
+                break;
+            }
+            ids.extend(a.into_iter().chain(b).map(|delivery| delivery.id));
+        }
+        let count = ids.len();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), count, "a delivery was claimed twice");
+        assert_eq!(count, 200);
+    }
+
+    #[actix_web::test]
+    async fn sqlite_requires_a_path() {
+        let config = DatabaseConfig {
+            database_type: DatabaseType::Sqlite,
+            username: None,
+            password: None,
+            host: None,
+            port: None,
+            database: None,
+            max_connections: None,
+        };
+        assert!(matches!(
+            connect(&config).await,
+            Err(StoreError::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn contains_pattern_escapes_wildcards() {
+        assert_eq!(contains_pattern("Ab"), "%ab%");
+        assert_eq!(contains_pattern(r"50%_\"), r"%50\%\_\\%");
+    }
+}
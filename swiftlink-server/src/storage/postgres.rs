@@ -0,0 +1,7 @@
+super::sql::sql_store!(
+    /// PostgreSQL-backed link storage
+    PostgresStore,
+    sqlx::Postgres,
+    "migrations/postgres",
+    lock_for_update = " FOR UPDATE"
+);
@@ -0,0 +1,721 @@
+//! Queries shared by the SQL backends, which only differ by their migrations and locking.
+
+use sqlx::{Database, Encode, QueryBuilder, Type};
+
+use super::{LinkFilter, contains_pattern};
+
+/// Append the conditions of a filter to a query ending in an open `WHERE` clause.
+pub(super) fn push_filter<'a, DB: Database>(query: &mut QueryBuilder<'a, DB>, filter: &LinkFilter)
+where
+    i64: Encode<'a, DB> + Type<DB>,
+    String: Encode<'a, DB> + Type<DB>,
+{
+    if let Some(codes) = &filter.codes {
+        if codes.is_empty() {
+            query.push(" AND 1 = 0");
+        } else {
+            query.push(" AND code IN (");
+            let mut separated = query.separated(", ");
+            for code in codes {
+                separated.push_bind(code.clone());
+            }
+            separated.push_unseparated(")");
+        }
+    }
+    if let Some(host) = &filter.host {
+        query.push(" AND host = ").push_bind(host.to_lowercase());
+    }
+    if let Some(needle) = &filter.url_contains {
+        query
+            .push(" AND LOWER(url) LIKE ")
+            .push_bind(contains_pattern(needle))
+            .push(r" ESCAPE '\'");
+    }
+    if let Some(created_after) = filter.created_after {
+        query.push(" AND created_at >= ").push_bind(created_after);
+    }
+    if let Some(created_before) = filter.created_before {
+        query.push(" AND created_at < ").push_bind(created_before);
+    }
+    if let Some(tag) = &filter.tag {
+        query
+            .push(" AND code IN (SELECT code FROM link_tags WHERE tag = ")
+            .push_bind(tag.clone())
+            .push(")");
+    }
+}
+
+/// Append the `FROM` and `WHERE` clauses selecting the clicks of the links matching a filter
+/// in the `[from, to)` range.
+pub(super) fn push_click_range<'a, DB: Database>(
+    query: &mut QueryBuilder<'a, DB>,
+    filter: &LinkFilter,
+    from: i64,
+    to: i64,
+) where
+    i64: Encode<'a, DB> + Type<DB>,
+    String: Encode<'a, DB> + Type<DB>,
+{
+    query
+        .push(" FROM clicks WHERE clicked_at >= ")
+        .push_bind(from)
+        .push(" AND clicked_at < ")
+        .push_bind(to)
+        .push(" AND code IN (SELECT code FROM links WHERE 1 = 1");
+    push_filter(query, filter);
+    query.push(")");
+}
+
+/// Define the link storage `$store` of the SQL database `$db`, with its migrations in the
+/// `$migrations` directory and `$lock_for_update` appended to the selection of a link about to
+/// be updated to lock its row.
+macro_rules! sql_store {
+    (
+        $(#[$attr:meta])*
+        $store:ident,
+        $db:ty,
+        $migrations:literal,
+        lock_for_update = $lock_for_update:literal
+    ) => {
+        use sqlx::{QueryBuilder, migrate::Migrator, pool::PoolOptions};
+        use std::collections::HashSet;
+
+        use super::{
+            BATCH_SIZE, Bucketing, ClickRecord, ClickStats, DeliveryAttempt, DeliveryRecord,
+            DeliveryStatus, LinkChanges, LinkFilter, LinkRecord, LinkStore, NewDelivery,
+            PageRequest, PoolStats, StoreError, StoreHealth, StoreResult,
+            sql::{push_click_range, push_filter},
+            url_host,
+        };
+
+        static MIGRATOR: Migrator = sqlx::migrate!($migrations);
+
+        $(#[$attr])*
+        pub struct $store {
+            pool: sqlx::Pool<$db>,
+        }
+
+        impl $store {
+            pub async fn connect(database_url: &str, max_connections: u32) -> StoreResult<Self> {
+                let pool = PoolOptions::<$db>::new()
+                    .max_connections(max_connections)
+                    .connect(database_url)
+                    .await?;
+                Ok(Self { pool })
+            }
+        }
+
+        #[async_trait::async_trait]
+        impl LinkStore for $store {
+            async fn migrate(&self) -> StoreResult<()> {
+                MIGRATOR.run(&self.pool).await?;
+                Ok(())
+            }
+
+            async fn health(&self) -> StoreResult<StoreHealth> {
+                let applied: HashSet<i64> =
+                    sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+                        .fetch_all(&self.pool)
+                        .await?
+                        .into_iter()
+                        .collect();
+                let pending_migrations = MIGRATOR
+                    .iter()
+                    .map(|migration| migration.version)
+                    .filter(|version| !applied.contains(version))
+                    .collect();
+                Ok(StoreHealth {
+                    pending_migrations,
+                    pool: self.pool_stats(),
+                })
+            }
+
+            fn pool_stats(&self) -> Option<PoolStats> {
+                Some(PoolStats::of(&self.pool))
+            }
+
+            async fn find_code_by_url(&self, url: &str) -> StoreResult<Option<String>> {
+                let code = sqlx::query_scalar(
+                    "SELECT code FROM links WHERE url = $1 AND expires_at IS NULL",
+                )
+                .bind(url)
+                .fetch_optional(&self.pool)
+                .await?;
+                Ok(code)
+            }
+
+            async fn find_codes_by_urls(
+                &self,
+                urls: &[String],
+            ) -> StoreResult<Vec<(String, String)>> {
+                let mut found = Vec::new();
+                for chunk in urls.chunks(BATCH_SIZE) {
+                    let mut query = QueryBuilder::<$db>::new(
+                        "SELECT url, code FROM links WHERE expires_at IS NULL AND url IN (",
+                    );
+                    let mut separated = query.separated(", ");
+                    for url in chunk {
+                        separated.push_bind(url);
+                    }
+                    separated.push_unseparated(")");
+                    found.extend(query.build_query_as().fetch_all(&self.pool).await?);
+                }
+                Ok(found)
+            }
+
+            async fn get_link(&self, code: &str) -> StoreResult<Option<LinkRecord>> {
+                let link = sqlx::query_as::<_, LinkRecord>(
+                    "SELECT code, url, created_at, expires_at FROM links WHERE code = $1",
+                )
+                .bind(code)
+                .fetch_optional(&self.pool)
+                .await?;
+                Ok(link)
+            }
+
+            async fn find_codes_ignore_case(&self, codes: &[String]) -> StoreResult<Vec<String>> {
+                if codes.is_empty() {
+                    return Ok(Vec::new());
+                }
+
+                let mut query = QueryBuilder::<$db>::new(
+                    "SELECT code FROM links WHERE LOWER(code) IN (",
+                );
+                let mut separated = query.separated(", ");
+                for code in codes {
+                    separated.push_bind(code.to_lowercase());
+                }
+                separated.push_unseparated(")");
+
+                let found = query.build_query_scalar().fetch_all(&self.pool).await?;
+                Ok(found)
+            }
+
+            async fn insert_link(&self, link: &LinkRecord, tags: &[String]) -> StoreResult<()> {
+                let mut tx = self.pool.begin().await?;
+
+                sqlx::query(
+                    "INSERT INTO links (code, url, host, created_at, expires_at) VALUES ($1, $2, $3, $4, $5)",
+                )
+                .bind(&link.code)
+                .bind(&link.url)
+                .bind(url_host(&link.url))
+                .bind(link.created_at)
+                .bind(link.expires_at)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| StoreError::from(e).on_code())?;
+
+                let tags: Vec<(&str, &str)> =
+                    tags.iter().map(|tag| (&*link.code, &**tag)).collect();
+                add_tags(&mut tx, &tags).await?;
+
+                tx.commit().await?;
+                Ok(())
+            }
+
+            async fn insert_links(
+                &self,
+                links: &[(LinkRecord, Vec<String>)],
+            ) -> StoreResult<Vec<bool>> {
+                let mut tx = self.pool.begin().await?;
+
+                let mut inserted = HashSet::new();
+                for chunk in links.chunks(BATCH_SIZE) {
+                    let mut query = QueryBuilder::<$db>::new(
+                        "INSERT INTO links (code, url, host, created_at, expires_at) ",
+                    );
+                    query.push_values(chunk, |mut row, (link, _)| {
+                        row.push_bind(&link.code)
+                            .push_bind(&link.url)
+                            .push_bind(url_host(&link.url))
+                            .push_bind(link.created_at)
+                            .push_bind(link.expires_at);
+                    });
+                    query.push(" ON CONFLICT (code) DO NOTHING RETURNING code");
+                    let codes: Vec<String> = query.build_query_scalar().fetch_all(&mut *tx).await?;
+                    inserted.extend(codes);
+                }
+
+                // A code given twice is only inserted once, for its first occurrence
+                let inserted: Vec<bool> = links
+                    .iter()
+                    .map(|(link, _)| inserted.remove(&link.code))
+                    .collect();
+                let tags: Vec<(&str, &str)> = links
+                    .iter()
+                    .zip(&inserted)
+                    .filter(|(_, inserted)| **inserted)
+                    .flat_map(|((link, tags), _)| tags.iter().map(|tag| (&*link.code, &**tag)))
+                    .collect();
+                add_tags(&mut tx, &tags).await?;
+
+                tx.commit().await?;
+                Ok(inserted)
+            }
+
+            async fn update_link(
+                &self,
+                code: &str,
+                changes: &LinkChanges,
+                actor: &str,
+                edited_at: i64,
+            ) -> StoreResult<Option<LinkRecord>> {
+                let mut tx = self.pool.begin().await?;
+
+                let Some(old) = sqlx::query_as::<_, LinkRecord>(concat!(
+                    "SELECT code, url, created_at, expires_at FROM links WHERE code = $1",
+                    $lock_for_update
+                ))
+                .bind(code)
+                .fetch_optional(&mut *tx)
+                .await?
+                else {
+                    return Ok(None);
+                };
+
+                let new = LinkRecord {
+                    url: changes.url.clone().unwrap_or_else(|| old.url.clone()),
+                    expires_at: changes.expires_at.unwrap_or(old.expires_at),
+                    ..old.clone()
+                };
+
+                if changes.edits_link() {
+                    sqlx::query(
+                        "UPDATE links SET url = $1, host = $2, expires_at = $3 WHERE code = $4",
+                    )
+                    .bind(&new.url)
+                    .bind(url_host(&new.url))
+                    .bind(new.expires_at)
+                    .bind(code)
+                    .execute(&mut *tx)
+                    .await?;
+
+                    sqlx::query(
+                        r#"
+                        INSERT INTO link_edits
+                            (code, edited_at, actor, old_url, new_url, old_expires_at, new_expires_at)
+                        VALUES ($1, $2, $3, $4, $5, $6, $7)
+                        "#,
+                    )
+                    .bind(code)
+                    .bind(edited_at)
+                    .bind(actor)
+                    .bind(&old.url)
+                    .bind(&new.url)
+                    .bind(old.expires_at)
+                    .bind(new.expires_at)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+
+                if let Some(tags) = &changes.tags {
+                    sqlx::query("DELETE FROM link_tags WHERE code = $1")
+                        .bind(code)
+                        .execute(&mut *tx)
+                        .await?;
+                    let tags: Vec<(&str, &str)> = tags.iter().map(|tag| (code, &**tag)).collect();
+                    add_tags(&mut tx, &tags).await?;
+                }
+                let added: Vec<(&str, &str)> =
+                    changes.add_tags.iter().map(|tag| (code, &**tag)).collect();
+                add_tags(&mut tx, &added).await?;
+                if !changes.remove_tags.is_empty() {
+                    let mut query =
+                        QueryBuilder::<$db>::new("DELETE FROM link_tags WHERE code = ");
+                    query.push_bind(code).push(" AND tag IN (");
+                    let mut separated = query.separated(", ");
+                    for tag in &changes.remove_tags {
+                        separated.push_bind(tag);
+                    }
+                    separated.push_unseparated(")");
+                    query.build().execute(&mut *tx).await?;
+                }
+
+                tx.commit().await?;
+                Ok(Some(new))
+            }
+
+            async fn list_links(
+                &self,
+                filter: &LinkFilter,
+                page: &PageRequest,
+            ) -> StoreResult<Vec<LinkRecord>> {
+                let mut query = QueryBuilder::<$db>::new(
+                    "SELECT code, url, created_at, expires_at FROM links WHERE 1 = 1",
+                );
+                push_filter(&mut query, filter);
+
+                let (cmp, order) = if page.descending {
+                    ("<", "DESC")
+                } else {
+                    (">", "ASC")
+                };
+                if let Some((created_at, code)) = &page.after {
+                    query
+                        .push(format!(" AND (created_at, code) {cmp} ("))
+                        .push_bind(*created_at)
+                        .push(", ")
+                        .push_bind(code.clone())
+                        .push(")");
+                }
+                query
+                    .push(format!(" ORDER BY created_at {order}, code {order} LIMIT "))
+                    .push_bind(i64::from(page.limit));
+
+                let links = query
+                    .build_query_as::<LinkRecord>()
+                    .fetch_all(&self.pool)
+                    .await?;
+                Ok(links)
+            }
+
+            async fn link_tags(&self, codes: &[String]) -> StoreResult<Vec<(String, String)>> {
+                let mut tags = Vec::new();
+                for chunk in codes.chunks(BATCH_SIZE) {
+                    let mut query = QueryBuilder::<$db>::new(
+                        "SELECT code, tag FROM link_tags WHERE code IN (",
+                    );
+                    let mut separated = query.separated(", ");
+                    for code in chunk {
+                        separated.push_bind(code);
+                    }
+                    separated.push_unseparated(") ORDER BY tag");
+                    tags.extend(query.build_query_as().fetch_all(&self.pool).await?);
+                }
+                Ok(tags)
+            }
+
+            async fn tag_counts(&self) -> StoreResult<Vec<(String, i64)>> {
+                let counts =
+                    sqlx::query_as("SELECT tag, COUNT(*) FROM link_tags GROUP BY tag ORDER BY tag")
+                        .fetch_all(&self.pool)
+                        .await?;
+                Ok(counts)
+            }
+
+            async fn delete_link(&self, code: &str) -> StoreResult<bool> {
+                let mut tx = self.pool.begin().await?;
+
+                sqlx::query("DELETE FROM clicks WHERE code = $1")
+                    .bind(code)
+                    .execute(&mut *tx)
+                    .await?;
+                for table in ["link_tags", "link_edits"] {
+                    sqlx::query(&format!("DELETE FROM {table} WHERE code = $1"))
+                        .bind(code)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+                let result = sqlx::query("DELETE FROM links WHERE code = $1")
+                    .bind(code)
+                    .execute(&mut *tx)
+                    .await?;
+
+                tx.commit().await?;
+                Ok(result.rows_affected() > 0)
+            }
+
+            async fn find_codes(&self, filter: &LinkFilter) -> StoreResult<Vec<String>> {
+                let mut query =
+                    QueryBuilder::<$db>::new("SELECT code FROM links WHERE 1 = 1");
+                push_filter(&mut query, filter);
+                let codes = query.build_query_scalar().fetch_all(&self.pool).await?;
+                Ok(codes)
+            }
+
+            async fn delete_links(&self, filter: &LinkFilter) -> StoreResult<Vec<String>> {
+                let mut tx = self.pool.begin().await?;
+
+                let mut query = QueryBuilder::<$db>::new(
+                    "DELETE FROM clicks WHERE code IN (SELECT code FROM links WHERE 1 = 1",
+                );
+                push_filter(&mut query, filter);
+                query.push(")");
+                query.build().execute(&mut *tx).await?;
+
+                let mut query = QueryBuilder::<$db>::new("DELETE FROM links WHERE 1 = 1");
+                push_filter(&mut query, filter);
+                query.push(" RETURNING code");
+                let codes: Vec<String> = query.build_query_scalar().fetch_all(&mut *tx).await?;
+
+                // Only now, since the filter may select links by tag
+                for table in ["link_tags", "link_edits"] {
+                    for chunk in codes.chunks(BATCH_SIZE) {
+                        let mut query = QueryBuilder::<$db>::new(format!(
+                            "DELETE FROM {table} WHERE code IN ("
+                        ));
+                        let mut separated = query.separated(", ");
+                        for code in chunk {
+                            separated.push_bind(code);
+                        }
+                        separated.push_unseparated(")");
+                        query.build().execute(&mut *tx).await?;
+                    }
+                }
+
+                tx.commit().await?;
+                Ok(codes)
+            }
+
+            async fn purge_expired(&self, now: i64, archive: bool) -> StoreResult<Vec<LinkRecord>> {
+                let mut tx = self.pool.begin().await?;
+
+                if archive {
+                    sqlx::query(
+                        r#"
+                        INSERT INTO expired_links (code, url, created_at, expires_at, archived_at)
+                        SELECT code, url, created_at, expires_at, $1 FROM links
+                        WHERE expires_at IS NOT NULL AND expires_at <= $1
+                        "#,
+                    )
+                    .bind(now)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+
+                for table in ["clicks", "link_tags", "link_edits"] {
+                    sqlx::query(&format!(
+                        r#"
+                        DELETE FROM {table} WHERE code IN
+                            (SELECT code FROM links WHERE expires_at IS NOT NULL AND expires_at <= $1)
+                        "#
+                    ))
+                    .bind(now)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+
+                let purged = sqlx::query_as::<_, LinkRecord>(
+                    r#"
+                    DELETE FROM links WHERE expires_at IS NOT NULL AND expires_at <= $1
+                    RETURNING code, url, created_at, expires_at
+                    "#,
+                )
+                .bind(now)
+                .fetch_all(&mut *tx)
+                .await?;
+
+                tx.commit().await?;
+                Ok(purged)
+            }
+
+            async fn next_counter(&self, name: &str) -> StoreResult<i64> {
+                let value = sqlx::query_scalar(
+                    r#"
+                    INSERT INTO counters (name, value) VALUES ($1, 1)
+                    ON CONFLICT (name) DO UPDATE SET value = counters.value + 1
+                    RETURNING value
+                    "#,
+                )
+                .bind(name)
+                .fetch_one(&self.pool)
+                .await?;
+                Ok(value)
+            }
+
+            async fn counter(&self, name: &str) -> StoreResult<i64> {
+                let value: Option<i64> =
+                    sqlx::query_scalar("SELECT value FROM counters WHERE name = $1")
+                        .bind(name)
+                        .fetch_optional(&self.pool)
+                        .await?;
+                Ok(value.unwrap_or(0))
+            }
+
+            async fn record_clicks(&self, clicks: &[ClickRecord]) -> StoreResult<()> {
+                let mut tx = self.pool.begin().await?;
+                for chunk in clicks.chunks(BATCH_SIZE) {
+                    let mut query = QueryBuilder::<$db>::new(
+                        "INSERT INTO clicks (code, clicked_at, referrer, user_agent, client_ip) ",
+                    );
+                    query.push_values(chunk, |mut row, click| {
+                        row.push_bind(&click.code)
+                            .push_bind(click.clicked_at)
+                            .push_bind(&click.referrer)
+                            .push_bind(&click.user_agent)
+                            .push_bind(&click.client_ip);
+                    });
+                    query.build().execute(&mut *tx).await?;
+                }
+                tx.commit().await?;
+                Ok(())
+            }
+
+            async fn count_clicks(&self, codes: &[String]) -> StoreResult<Vec<(String, i64)>> {
+                let mut counts = Vec::new();
+                for chunk in codes.chunks(BATCH_SIZE) {
+                    let mut query = QueryBuilder::<$db>::new(
+                        "SELECT code, COUNT(*) FROM clicks WHERE code IN (",
+                    );
+                    let mut separated = query.separated(", ");
+                    for code in chunk {
+                        separated.push_bind(code);
+                    }
+                    separated.push_unseparated(") GROUP BY code");
+                    counts.extend(query.build_query_as().fetch_all(&self.pool).await?);
+                }
+                Ok(counts)
+            }
+
+            async fn click_stats(
+                &self,
+                filter: &LinkFilter,
+                from: i64,
+                to: i64,
+                bucketing: Bucketing,
+            ) -> StoreResult<ClickStats> {
+                let mut query = QueryBuilder::<$db>::new(
+                    r#"
+                    SELECT COUNT(*),
+                           COUNT(DISTINCT COALESCE(client_ip, '') || '|' || COALESCE(user_agent, ''))
+                    "#,
+                );
+                push_click_range(&mut query, filter, from, to);
+                let (total, unique_visitors): (i64, i64) =
+                    query.build_query_as().fetch_one(&self.pool).await?;
+
+                // Floored like `Bucketing::start_of`, `%` truncating towards zero in SQL
+                let mut query =
+                    QueryBuilder::<$db>::new("SELECT clicked_at - (((clicked_at - ");
+                query
+                    .push_bind(bucketing.offset)
+                    .push(") % ")
+                    .push_bind(bucketing.width)
+                    .push(" + ")
+                    .push_bind(bucketing.width)
+                    .push(") % ")
+                    .push_bind(bucketing.width)
+                    .push(") AS bucket, COUNT(*)");
+                push_click_range(&mut query, filter, from, to);
+                query.push(" GROUP BY bucket ORDER BY bucket");
+                let timeline = query.build_query_as().fetch_all(&self.pool).await?;
+
+                let mut query = QueryBuilder::<$db>::new("SELECT referrer, COUNT(*)");
+                push_click_range(&mut query, filter, from, to);
+                query.push(" GROUP BY referrer");
+                let referrers = query.build_query_as().fetch_all(&self.pool).await?;
+
+                let mut query = QueryBuilder::<$db>::new("SELECT user_agent, COUNT(*)");
+                push_click_range(&mut query, filter, from, to);
+                query.push(" GROUP BY user_agent");
+                let user_agents = query.build_query_as().fetch_all(&self.pool).await?;
+
+                Ok(ClickStats {
+                    total,
+                    unique_visitors,
+                    timeline,
+                    referrers,
+                    user_agents,
+                })
+            }
+
+            async fn insert_deliveries(&self, deliveries: &[NewDelivery]) -> StoreResult<()> {
+                for chunk in deliveries.chunks(BATCH_SIZE) {
+                    let mut query = QueryBuilder::<$db>::new(
+                        r#"
+                        INSERT INTO webhook_deliveries
+                            (webhook_url, event, code, payload, status, created_at, next_attempt_at)
+                        "#,
+                    );
+                    query.push_values(chunk, |mut row, delivery| {
+                        row.push_bind(&delivery.webhook_url)
+                            .push_bind(&delivery.event)
+                            .push_bind(&delivery.code)
+                            .push_bind(&delivery.payload)
+                            .push_bind(DeliveryStatus::Pending.as_str())
+                            .push_bind(delivery.created_at)
+                            .push_bind(delivery.created_at);
+                    });
+                    query.build().execute(&self.pool).await?;
+                }
+                Ok(())
+            }
+
+            async fn claim_due_deliveries(
+                &self,
+                now: i64,
+                lease_until: i64,
+                limit: u32,
+            ) -> StoreResult<Vec<DeliveryRecord>> {
+                let due = sqlx::query_as::<_, DeliveryRecord>(
+                    r#"
+                    SELECT id, webhook_url, event, code, payload, attempts FROM webhook_deliveries
+                    WHERE status = $1 AND next_attempt_at <= $2
+                    ORDER BY next_attempt_at, id LIMIT $3
+                    "#,
+                )
+                .bind(DeliveryStatus::Pending.as_str())
+                .bind(now)
+                .bind(i64::from(limit))
+                .fetch_all(&self.pool)
+                .await?;
+
+                // Another server may have claimed a row since it was read, in which case it
+                // is no longer due and the update leaves it alone
+                let mut claimed = Vec::with_capacity(due.len());
+                for delivery in due {
+                    let updated = sqlx::query(
+                        r#"
+                        UPDATE webhook_deliveries SET next_attempt_at = $1
+                        WHERE id = $2 AND status = $3 AND next_attempt_at <= $4
+                        "#,
+                    )
+                    .bind(lease_until)
+                    .bind(delivery.id)
+                    .bind(DeliveryStatus::Pending.as_str())
+                    .bind(now)
+                    .execute(&self.pool)
+                    .await?;
+                    if updated.rows_affected() == 1 {
+                        claimed.push(delivery);
+                    }
+                }
+                Ok(claimed)
+            }
+
+            async fn record_delivery_attempt(
+                &self,
+                id: i64,
+                attempt: &DeliveryAttempt,
+            ) -> StoreResult<()> {
+                sqlx::query(
+                    r#"
+                    UPDATE webhook_deliveries
+                    SET status = $1, attempts = attempts + 1, last_attempt_at = $2, next_attempt_at = $3,
+                        response_status = $4, last_error = $5
+                    WHERE id = $6
+                    "#,
+                )
+                .bind(attempt.status.as_str())
+                .bind(attempt.attempted_at)
+                .bind(attempt.next_attempt_at)
+                .bind(attempt.response_status)
+                .bind(&attempt.error)
+                .bind(id)
+                .execute(&self.pool)
+                .await?;
+                Ok(())
+            }
+        }
+
+        /// Give tags to links, as `(code, tag)` pairs, skipping those they already have.
+        async fn add_tags(
+            tx: &mut sqlx::Transaction<'_, $db>,
+            tags: &[(&str, &str)],
+        ) -> StoreResult<()> {
+            for chunk in tags.chunks(BATCH_SIZE) {
+                let mut query =
+                    QueryBuilder::<$db>::new("INSERT INTO link_tags (code, tag) ");
+                query.push_values(chunk, |mut row, (code, tag)| {
+                    row.push_bind(*code).push_bind(*tag);
+                });
+                query.push(" ON CONFLICT (code, tag) DO NOTHING");
+                query.build().execute(&mut **tx).await?;
+            }
+            Ok(())
+        }
+    };
+}
+
+pub(super) use sql_store;
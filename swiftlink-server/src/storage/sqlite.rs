@@ -0,0 +1,164 @@
+super::sql::sql_store!(
+    /// SQLite-backed link storage
+    SqliteStore,
+    sqlx::Sqlite,
+    "migrations/sqlite",
+    // Writing transactions lock the whole database
+    lock_for_update = ""
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Schema created by `init_db` before migrations existed
+    const BASELINE_SCHEMA: &str = r#"
+        CREATE TABLE IF NOT EXISTS links (
+            code TEXT PRIMARY KEY,
+            url TEXT NOT NULL,
+            created_at BIGINT NOT NULL DEFAULT (strftime('%s', 'now'))
+        )
+    "#;
+
+    async fn store() -> SqliteStore {
+        SqliteStore::connect("sqlite::memory:", 1).await.unwrap()
+    }
+
+    #[actix_web::test]
+    async fn migrations_apply_to_fresh_database() {
+        let store = store().await;
+        store.migrate().await.unwrap();
+        assert!(store.health().await.unwrap().pending_migrations.is_empty());
+
+        // Running them again is a no-op
+        store.migrate().await.unwrap();
+    }
+
+    #[actix_web::test]
+    async fn migrations_take_over_baseline_database() {
+        let store = store().await;
+        sqlx::query(BASELINE_SCHEMA)
+            .execute(&store.pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO links (code, url) VALUES ($1, $2)")
+            .bind("old")
+            .bind("https://User@Example.com:8080/page?q=1")
+            .execute(&store.pool)
+            .await
+            .unwrap();
+
+        store.migrate().await.unwrap();
+        assert!(store.health().await.unwrap().pending_migrations.is_empty());
+
+        let link = store.get_link("old").await.unwrap().unwrap();
+        assert_eq!(link.url, "https://User@Example.com:8080/page?q=1");
+        assert_eq!(link.expires_at, None);
+        let host: Option<String> = sqlx::query_scalar("SELECT host FROM links WHERE code = $1")
+            .bind("old")
+            .fetch_one(&store.pool)
+            .await
+            .unwrap();
+        assert_eq!(host.as_deref(), Some("example.com"));
+    }
+
+    #[actix_web::test]
+    async fn purge_archives_expired_links() {
+        let store = store().await;
+        store.migrate().await.unwrap();
+        for (code, expires_at) in [("gone", Some(100)), ("kept", None)] {
+            let link = LinkRecord {
+                code: code.to_string(),
+                url: format!("https://example.com/{code}"),
+                created_at: 10,
+                expires_at,
+            };
+            store
+                .insert_link(&link, &["tag".to_string()])
+                .await
+                .unwrap();
+        }
+        let click = ClickRecord {
+            code: "gone".into(),
+            clicked_at: 50,
+            referrer: None,
+            user_agent: None,
+            client_ip: None,
+        };
+        store.record_clicks(&[click]).await.unwrap();
+
+        let purged = store.purge_expired(100, true).await.unwrap();
+        assert_eq!(purged.len(), 1);
+        assert_eq!(purged[0].code, "gone");
+
+        let archived: Vec<(String, String, i64, i64, i64)> = sqlx::query_as(
+            "SELECT code, url, created_at, expires_at, archived_at FROM expired_links",
+        )
+        .fetch_all(&store.pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            archived,
+            vec![(
+                "gone".into(),
+                "https://example.com/gone".into(),
+                10,
+                100,
+                100
+            )]
+        );
+        assert!(
+            store
+                .count_clicks(&["gone".into()])
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert!(store.link_tags(&["gone".into()]).await.unwrap().is_empty());
+        assert!(store.get_link("kept").await.unwrap().is_some());
+
+        // Without archiving, expired links are only deleted
+        store
+            .update_link(
+                "kept",
+                &LinkChanges {
+                    expires_at: Some(Some(150)),
+                    ..Default::default()
+                },
+                "test",
+                120,
+            )
+            .await
+            .unwrap();
+        store.purge_expired(200, false).await.unwrap();
+        let archived: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM expired_links")
+            .fetch_one(&store.pool)
+            .await
+            .unwrap();
+        assert_eq!(archived, 1);
+        assert!(store.get_link("kept").await.unwrap().is_none());
+    }
+
+    #[actix_web::test]
+    async fn record_clicks_above_bind_limit() {
+        let store = store().await;
+        store.migrate().await.unwrap();
+        // 5 values per click, more than the 32766 bind parameters of a SQLite statement
+        let clicks: Vec<ClickRecord> = (0..7000)
+            .map(|i| ClickRecord {
+                code: "abc".into(),
+                clicked_at: i,
+                referrer: Some("https://referrer.example/".into()),
+                user_agent: Some("test".into()),
+                client_ip: Some("192.0.2.0".into()),
+            })
+            .collect();
+
+        store.record_clicks(&clicks).await.unwrap();
+        store.record_clicks(&[]).await.unwrap();
+        assert_eq!(
+            store.count_clicks(&["abc".into()]).await.unwrap(),
+            vec![("abc".to_string(), 7000)]
+        );
+    }
+}
@@ -0,0 +1,96 @@
+//! Tags grouping links, e.g. by campaign.
+
+use std::collections::BTreeSet;
+
+use crate::problem::Problem;
+
+/// Longest tag, in characters
+pub const MAX_TAG_LENGTH: usize = 64;
+
+/// Largest number of tags in one field of a request
+pub const MAX_TAGS: usize = 20;
+
+/// Normalize a tag given in a request: trimmed and lowercased.
+/// Tags may only contain ASCII letters, digits, `-`, `_`, `.` and `:`.
+pub fn normalize_tag(tag: &str) -> Result<String, Problem> {
+    let tag = tag.trim().to_ascii_lowercase();
+    if tag.is_empty() {
+        return Err(Problem::invalid_request("Tags must not be empty"));
+    }
+    if tag.len() > MAX_TAG_LENGTH {
+        return Err(Problem::invalid_request(format!(
+            "Tags may be at most {MAX_TAG_LENGTH} characters long"
+        )));
+    }
+    if !tag
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+    {
+        return Err(Problem::invalid_request(format!(
+            "Tag {tag} may only contain letters, digits, '-', '_', '.' and ':'"
+        )));
+    }
+    Ok(tag)
+}
+
+/// Normalize the tags of a request field, dropping duplicates and sorting them.
+pub fn normalize_tags(tags: &[String]) -> Result<Vec<String>, Problem> {
+    if tags.len() > MAX_TAGS {
+        return Err(Problem::invalid_request(format!(
+            "At most {MAX_TAGS} tags can be given at once"
+        )));
+    }
+    let tags: BTreeSet<String> = tags
+        .iter()
+        .map(|tag| normalize_tag(tag))
+        .collect::<Result<_, _>>()?;
+    Ok(tags.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_are_trimmed_and_lowercased() {
+        assert_eq!(normalize_tag(" Spring-2025 ").unwrap(), "spring-2025");
+        assert_eq!(normalize_tag("utm:Source_a.b").unwrap(), "utm:source_a.b");
+        let longest = "a".repeat(MAX_TAG_LENGTH);
+        assert_eq!(normalize_tag(&longest).unwrap(), longest);
+    }
+
+    #[test]
+    fn invalid_tags_are_rejected() {
+        let too_long = "a".repeat(MAX_TAG_LENGTH + 1);
+        for invalid in [
+            "",
+            "  ",
+            "two words",
+            "a/b",
+            "été",
+            "a,b",
+            too_long.as_str(),
+        ] {
+            assert!(normalize_tag(invalid).is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn duplicates_are_dropped_and_sorted() {
+        let tags = ["b", "A", " a", "B", "c"].map(String::from);
+        assert_eq!(normalize_tags(&tags).unwrap(), ["a", "b", "c"]);
+        assert!(normalize_tags(&[]).unwrap().is_empty());
+        assert!(normalize_tags(&["ok".into(), "not ok".into()]).is_err());
+    }
+
+    #[test]
+    fn at_most_max_tags_are_accepted() {
+        let tags: Vec<String> = (0..MAX_TAGS).map(|i| format!("tag{i}")).collect();
+        assert_eq!(normalize_tags(&tags).unwrap().len(), MAX_TAGS);
+        let mut tags = tags;
+        tags.push("one-more".into());
+        assert!(normalize_tags(&tags).is_err());
+        // The limit applies to the request, before duplicates are dropped
+        assert!(normalize_tags(&vec!["a".to_string(); MAX_TAGS + 1]).is_err());
+    }
+}
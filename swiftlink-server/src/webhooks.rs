@@ -0,0 +1,412 @@
+//! Delivery of link events to the configured webhooks.
+//!
+//! Lifecycle events reach the worker on a bounded channel, dropped (and counted) while it is
+//! full so that publishers never wait on the database. Due deliveries are claimed before being
+//! sent, so that servers sharing the database do not send them twice.
+//! Events matching a webhook are then queued in the `webhook_deliveries` table and sent by a
+//! background task which retries failed deliveries with an exponential backoff. Deliveries
+//! still pending when the server stops are sent after it restarts. Clicks are not delivered to
+//! webhooks, the `click_threshold` event reporting the popular links instead.
+
+use actix_web::rt;
+use hmac::{Hmac, Mac};
+use log::{debug, info, warn};
+use sha2::Sha256;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use swiftlink_api::{LinkEvent, LinkEventType};
+use tokio::sync::{Notify, mpsc};
+use url::Url;
+
+use crate::{
+    config::WebhookConfig,
+    metrics::Metrics,
+    storage::{DeliveryAttempt, DeliveryRecord, DeliveryStatus, LinkStore, NewDelivery},
+    unix_now,
+};
+
+/// Seconds between two checks for deliveries due to be retried
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Number of events waiting to be queued before new ones are dropped
+const QUEUE_CAPACITY: usize = 1024;
+
+/// Seconds added to the longest webhook timeout for the attempts on claimed deliveries to be
+/// recorded, after which deliveries are due again in case their server stopped
+const CLAIM_MARGIN: i64 = 60;
+
+/// Number of deliveries sent concurrently
+const BATCH_SIZE: u32 = 50;
+
+/// Seconds before the first retry, doubled after every failed attempt
+const BASE_RETRY_DELAY: i64 = 10;
+
+/// Longest delay between two attempts, in seconds
+const MAX_RETRY_DELAY: i64 = 3600;
+
+/// Attempts to deliver an event when `max_attempts` is not set
+const DEFAULT_MAX_ATTEMPTS: u32 = 8;
+
+/// Seconds to wait for a response when `timeout` is not set
+const DEFAULT_TIMEOUT: u64 = 10;
+
+/// Headers of the requests sent to webhooks
+const EVENT_HEADER: &str = "X-Swiftlink-Event";
+const DELIVERY_HEADER: &str = "X-Swiftlink-Delivery";
+const TIMESTAMP_HEADER: &str = "X-Swiftlink-Timestamp";
+const SIGNATURE_HEADER: &str = "X-Swiftlink-Signature";
+
+/// Check that every webhook has an http(s) URL, given at most once, and does not subscribe
+/// to clicks.
+pub fn validate(webhooks: &[WebhookConfig]) -> Result<(), String> {
+    for (i, webhook) in webhooks.iter().enumerate() {
+        let url = Url::parse(&webhook.url)
+            .map_err(|e| format!("Invalid webhook URL {}: {}", webhook.url, e))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(format!("Webhook URL {} is not an http(s) URL", webhook.url));
+        }
+        if webhooks[..i].iter().any(|other| other.url == webhook.url) {
+            return Err(format!("Webhook URL {} is configured twice", webhook.url));
+        }
+        if webhook.accepts(LinkEventType::Clicked) {
+            return Err(format!(
+                "Webhook {} cannot receive clicked events, use click_threshold instead",
+                webhook.url
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Signature of a request body, `sha256=` followed by the hex-encoded HMAC-SHA256 of
+/// `{timestamp}.{body}` keyed with the secret of the webhook
+pub fn signature(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(format!("{timestamp}.{body}").as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Delay before the next attempt after `attempts` failed ones
+fn retry_delay(attempts: i32) -> i64 {
+    let exponent = attempts.saturating_sub(1).clamp(0, 16) as u32;
+    (BASE_RETRY_DELAY << exponent).min(MAX_RETRY_DELAY)
+}
+
+/// Sending side of the queue of events to deliver to the webhooks
+#[derive(Clone)]
+pub struct WebhookQueue {
+    sender: mpsc::Sender<LinkEvent>,
+    metrics: Arc<Metrics>,
+}
+
+impl WebhookQueue {
+    /// Queue an event without waiting, dropping it when the queue is full, e.g. while the
+    /// database is unreachable.
+    pub fn push(&self, event: LinkEvent) {
+        match self.sender.try_send(event) {
+            Ok(()) => {}
+            Err(mpsc::error::TrySendError::Full(event)) => {
+                warn!("Webhook queue full, dropping event on {}", event.code);
+                self.metrics.webhook_events_dropped.inc();
+            }
+            Err(mpsc::error::TrySendError::Closed(event)) => {
+                warn!(
+                    "Webhook worker stopped, event on {} not delivered",
+                    event.code
+                );
+            }
+        }
+    }
+}
+
+/// Spawn the tasks queueing the events pushed to the returned queue for every matching webhook
+/// and delivering them. Nothing is spawned when no webhook is configured.
+pub fn spawn(
+    store: Arc<dyn LinkStore>,
+    webhooks: &[WebhookConfig],
+    metrics: Arc<Metrics>,
+) -> Option<WebhookQueue> {
+    if webhooks.is_empty() {
+        return None;
+    }
+    info!("Delivering link events to {} webhook(s)", webhooks.len());
+    let lease = webhooks
+        .iter()
+        .map(|webhook| webhook.timeout.unwrap_or(DEFAULT_TIMEOUT).max(1))
+        .max()
+        .unwrap_or(DEFAULT_TIMEOUT) as i64
+        + CLAIM_MARGIN;
+
+    let webhooks: Arc<HashMap<String, WebhookConfig>> = Arc::new(
+        webhooks
+            .iter()
+            .map(|webhook| (webhook.url.clone(), webhook.clone()))
+            .collect(),
+    );
+    let queued = Arc::new(Notify::new());
+
+    let (sender, mut receiver) = mpsc::channel::<LinkEvent>(QUEUE_CAPACITY);
+    rt::spawn({
+        let store = store.clone();
+        let webhooks = webhooks.clone();
+        let queued = queued.clone();
+        async move {
+            while let Some(event) = receiver.recv().await {
+                let payload = match serde_json::to_string(&event) {
+                    Ok(payload) => payload,
+                    Err(e) => {
+                        warn!("Error serializing event on {}: {:?}", event.code, e);
+                        continue;
+                    }
+                };
+                let deliveries: Vec<NewDelivery> = webhooks
+                    .values()
+                    .filter(|webhook| webhook.accepts(event.event))
+                    .map(|webhook| NewDelivery {
+                        webhook_url: webhook.url.clone(),
+                        event: event.event.name().to_string(),
+                        code: event.code.clone(),
+                        payload: payload.clone(),
+                        created_at: event.timestamp,
+                    })
+                    .collect();
+                if deliveries.is_empty() {
+                    continue;
+                }
+                // Retry until the database is back, new events being dropped once the queue fills
+                while let Err(e) = store.insert_deliveries(&deliveries).await {
+                    warn!("Error queueing webhook deliveries: {:?}", e);
+                    rt::time::sleep(POLL_INTERVAL).await;
+                }
+                queued.notify_one();
+            }
+        }
+    });
+
+    let queue = WebhookQueue {
+        sender,
+        metrics: metrics.clone(),
+    };
+    rt::spawn(async move {
+        let client = reqwest::Client::new();
+        loop {
+            let now = unix_now();
+            let due = match store
+                .claim_due_deliveries(now, now + lease, BATCH_SIZE)
+                .await
+            {
+                Ok(due) => due,
+                Err(e) => {
+                    warn!("Error fetching webhook deliveries: {:?}", e);
+                    Vec::new()
+                }
+            };
+            let full_batch = due.len() == BATCH_SIZE as usize;
+
+            let attempts: Vec<_> = due
+                .into_iter()
+                .map(|delivery| {
+                    let store = store.clone();
+                    let client = client.clone();
+                    let metrics = metrics.clone();
+                    let webhook = webhooks.get(&delivery.webhook_url).cloned();
+                    rt::spawn(async move {
+                        let attempt = deliver(&client, webhook.as_ref(), &delivery).await;
+                        metrics.webhook_delivery(match attempt.status {
+                            DeliveryStatus::Delivered => "delivered",
+                            DeliveryStatus::Pending => "retry",
+                            DeliveryStatus::Failed => "failed",
+                        });
+                        if let Err(e) = store.record_delivery_attempt(delivery.id, &attempt).await {
+                            warn!("Error recording webhook delivery {}: {:?}", delivery.id, e);
+                        }
+                    })
+                })
+                .collect();
+            for attempt in attempts {
+                let _ = attempt.await;
+            }
+
+            // More deliveries may already be due when the batch was full
+            if !full_batch {
+                let _ = rt::time::timeout(POLL_INTERVAL, queued.notified()).await;
+            }
+        }
+    });
+
+    Some(queue)
+}
+
+/// Send one event to its webhook, returning the outcome to record.
+async fn deliver(
+    client: &reqwest::Client,
+    webhook: Option<&WebhookConfig>,
+    delivery: &DeliveryRecord,
+) -> DeliveryAttempt {
+    let attempted_at = unix_now();
+    let Some(webhook) = webhook else {
+        warn!(
+            "Dropping delivery {} to {}, which is no longer configured",
+            delivery.id, delivery.webhook_url
+        );
+        return DeliveryAttempt {
+            status: DeliveryStatus::Failed,
+            attempted_at,
+            next_attempt_at: attempted_at,
+            response_status: None,
+            error: Some("Webhook no longer configured".to_string()),
+        };
+    };
+
+    let mut request = client
+        .post(&webhook.url)
+        .timeout(Duration::from_secs(
+            webhook.timeout.unwrap_or(DEFAULT_TIMEOUT).max(1),
+        ))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, &delivery.event)
+        .header(DELIVERY_HEADER, delivery.id)
+        .header(TIMESTAMP_HEADER, attempted_at);
+    if let Some(secret) = &webhook.secret {
+        request = request.header(
+            SIGNATURE_HEADER,
+            signature(secret, attempted_at, &delivery.payload),
+        );
+    }
+
+    let (response_status, error) = match request.body(delivery.payload.clone()).send().await {
+        Ok(response) if response.status().is_success() => {
+            debug!(
+                "Delivered {} event on {} to {}",
+                delivery.event, delivery.code, webhook.url
+            );
+            return DeliveryAttempt {
+                status: DeliveryStatus::Delivered,
+                attempted_at,
+                next_attempt_at: attempted_at,
+                response_status: Some(i32::from(response.status().as_u16())),
+                error: None,
+            };
+        }
+        Ok(response) => (
+            Some(i32::from(response.status().as_u16())),
+            format!("Unexpected status {}", response.status()),
+        ),
+        Err(e) => (None, e.to_string()),
+    };
+
+    let attempts = delivery.attempts + 1;
+    let max_attempts = webhook.max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS).max(1);
+    let status = if attempts as u32 >= max_attempts {
+        warn!(
+            "Giving up on delivery {} to {} after {} attempt(s): {}",
+            delivery.id, webhook.url, attempts, error
+        );
+        DeliveryStatus::Failed
+    } else {
+        warn!(
+            "Delivery {} to {} failed, retrying: {}",
+            delivery.id, webhook.url, error
+        );
+        DeliveryStatus::Pending
+    };
+    DeliveryAttempt {
+        status,
+        attempted_at,
+        next_attempt_at: attempted_at + retry_delay(attempts),
+        response_status,
+        error: Some(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn webhook(url: &str, events: Option<Vec<LinkEventType>>) -> WebhookConfig {
+        WebhookConfig {
+            url: url.to_string(),
+            events,
+            secret: None,
+            max_attempts: None,
+            timeout: None,
+        }
+    }
+
+    fn event(code: &str) -> LinkEvent {
+        LinkEvent {
+            event: LinkEventType::Created,
+            code: code.to_string(),
+            url: None,
+            timestamp: 0,
+            clicks: None,
+            referrer: None,
+        }
+    }
+
+    #[test]
+    fn full_queue_drops_events_without_waiting() {
+        let metrics = Arc::new(Metrics::new(&Default::default()).unwrap());
+        let (sender, mut receiver) = mpsc::channel(2);
+        let queue = WebhookQueue {
+            sender,
+            metrics: metrics.clone(),
+        };
+        for code in ["a", "b", "c", "d"] {
+            queue.push(event(code));
+        }
+        assert_eq!(metrics.webhook_events_dropped.get(), 2);
+        assert_eq!(receiver.try_recv().unwrap().code, "a");
+        assert_eq!(receiver.try_recv().unwrap().code, "b");
+        assert!(receiver.try_recv().is_err());
+
+        // Room was made for another one
+        queue.push(event("e"));
+        assert_eq!(receiver.try_recv().unwrap().code, "e");
+        assert_eq!(metrics.webhook_events_dropped.get(), 2);
+    }
+
+    #[test]
+    fn signature_matches_reference_hmac() {
+        // Computed with Python: hmac.new(key, f"{timestamp}.{body}".encode(), sha256).hexdigest()
+        assert_eq!(
+            signature("secret", 1700000000, r#"{"event":"created","code":"abc"}"#),
+            "sha256=a5bcd6e9f1b12bf21d54bbcb65f137ffbb034d618b6642b85881e556bf7c6f18"
+        );
+        assert_eq!(
+            signature("", 0, ""),
+            "sha256=b849d5a581847b281957065739df36df2463d1977ea8d6e1e4e6cf33fadc68c3"
+        );
+        // The timestamp is signed, so a replayed body with a new timestamp does not verify
+        assert_ne!(signature("secret", 1, "{}"), signature("secret", 2, "{}"));
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_an_hour() {
+        let delays: Vec<i64> = (1..=11).map(retry_delay).collect();
+        assert_eq!(
+            delays,
+            [10, 20, 40, 80, 160, 320, 640, 1280, 2560, 3600, 3600]
+        );
+        assert_eq!(retry_delay(0), BASE_RETRY_DELAY);
+        assert_eq!(retry_delay(-1), BASE_RETRY_DELAY);
+        assert_eq!(retry_delay(i32::MAX), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn invalid_webhooks_are_rejected() {
+        assert!(validate(&[webhook("https://example.com/hook", None)]).is_ok());
+        assert!(validate(&[webhook("ftp://example.com/hook", None)]).is_err());
+        assert!(validate(&[webhook("not a url", None)]).is_err());
+        let twice = webhook(
+            "https://example.com/hook",
+            Some(vec![LinkEventType::Created]),
+        );
+        assert!(validate(&[twice.clone(), twice]).is_err());
+        let clicks = webhook(
+            "https://example.com/hook",
+            Some(vec![LinkEventType::Clicked]),
+        );
+        assert!(validate(&[clicks]).is_err());
+    }
+}